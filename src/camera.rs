@@ -1,17 +1,30 @@
+use std::f64::consts::PI;
+
 use crate::ray::Ray;
+use crate::sampler::Sampler;
 use crate::vec::Vec3;
 
 pub struct Camera {
     pub origin: Vec3,
-    pub vfov: f64,
-    pub focal_length: f64,
     pub aperture: f64,
-    pub aspect_ratio: f64,
     horizontal: Vec3,
     vertical: Vec3,
     lower_left_corner: Vec3,
     u: Vec3,
     v: Vec3,
+    w: Vec3,
+    focal_length: f64,
+}
+
+// A point on the lens from which the camera sees a given point, see Camera::sample_lens
+pub struct LensSample {
+    pub point: Vec3,
+    // position on the film, in the same coordinates as generate_ray takes
+    pub s: f64,
+    pub t: f64,
+    pub importance: f64,
+    // solid angle density of the direction towards the lens, as seen from the given point
+    pub pdf: f64,
 }
 
 impl Camera {
@@ -41,24 +54,96 @@ impl Camera {
 
         Camera {
             origin,
-            vfov,
-            focal_length,
-            aspect_ratio,
             horizontal,
             vertical,
             lower_left_corner,
             aperture,
             u,
             v,
+            w,
+            focal_length,
+        }
+    }
+
+    // The following functions describe the camera as a sensor, which is needed by integrators
+    // that trace paths starting from lights. The importance is normalized such that it integrates
+    // to one over the film and the lens.
+
+    // film position hit by a ray leaving the lens at lens_point in direction dir
+    pub fn film_position(&self, lens_point: Vec3, dir: Vec3) -> Option<(f64, f64)> {
+        let dir = dir.normalized();
+        let cosine = -Vec3::dot(&dir, &self.w);
+        if cosine <= 0.0 {
+            return None;
         }
+
+        let on_film = lens_point + (self.focal_length / cosine) * dir - self.lower_left_corner;
+        let s = Vec3::dot(&on_film, &self.u) / self.horizontal.norm();
+        let t = Vec3::dot(&on_film, &self.v) / self.vertical.norm();
+
+        if (0.0..1.0).contains(&s) && (0.0..1.0).contains(&t) {
+            Some((s, t))
+        } else {
+            None
+        }
+    }
+
+    pub fn importance(&self, lens_point: Vec3, dir: Vec3) -> f64 {
+        if self.film_position(lens_point, dir).is_none() {
+            return 0.0;
+        }
+        let cosine = -Vec3::dot(&dir.normalized(), &self.w);
+        1.0 / (self.film_area() * self.lens_area() * cosine.powi(4))
+    }
+
+    // solid angle density of generate_ray producing direction dir
+    pub fn pdf_dir(&self, lens_point: Vec3, dir: Vec3) -> f64 {
+        if self.film_position(lens_point, dir).is_none() {
+            return 0.0;
+        }
+        let cosine = -Vec3::dot(&dir.normalized(), &self.w);
+        1.0 / (self.film_area() * cosine.powi(3))
+    }
+
+    // samples a point on the lens that sees the given point
+    pub fn sample_lens(&self, point: Vec3, sampler: &mut dyn Sampler) -> Option<LensSample> {
+        let rd = (self.aperture / 2.0) * Vec3::random_in_unit_disk(sampler);
+        let lens_point = self.origin + self.u * rd.x + self.v * rd.y;
+
+        let to_point = point - lens_point;
+        let dist = to_point.norm();
+        let dir = to_point / dist;
+
+        let (s, t) = self.film_position(lens_point, dir)?;
+        let cosine = -Vec3::dot(&dir, &self.w);
+
+        Some(LensSample {
+            point: lens_point,
+            s,
+            t,
+            importance: self.importance(lens_point, dir),
+            pdf: dist * dist / (cosine * self.lens_area()),
+        })
+    }
+
+    // area of the film at unit distance from the lens
+    fn film_area(&self) -> f64 {
+        self.horizontal.norm() * self.vertical.norm() / (self.focal_length * self.focal_length)
+    }
+
+    // a pinhole is treated as having unit area
+    fn lens_area(&self) -> f64 {
+        let radius = self.aperture / 2.0;
+        if radius > 0.0 { PI * radius * radius } else { 1.0 }
     }
 
-    pub fn generate_ray(&self, s: f64, t: f64) -> Ray {
-        let rd = (self.aperture / 2.0) * Vec3::random_in_unit_disk();
+    pub fn generate_ray(&self, s: f64, t: f64, sampler: &mut dyn Sampler) -> Ray {
+        let rd = (self.aperture / 2.0) * Vec3::random_in_unit_disk(sampler);
         let offset = self.u * rd.x + self.v * rd.y;
 
         Ray {
             t: 0.0,
+            wavelengths: None,
             origin: self.origin + offset,
             dir: self.lower_left_corner + s * self.horizontal + t * self.vertical
                 - self.origin
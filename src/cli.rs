@@ -1,11 +1,15 @@
 pub struct CliOptions {
     pub img_x: u32,
     pub img_y: u32,
-    pub max_depth: u32,
+    // whether the resolution was given, scene files that set their own resolution then use it
+    pub custom_resolution: bool,
+    pub max_depth: Option<u32>,
+    pub integrator: Option<String>,
     pub num_samples: u32,
     pub output_file: String,
     pub scene_file: String,
     pub silent: bool,
+    pub spectral: bool,
     pub aspect_ratio: f64,
 }
 
@@ -15,7 +19,7 @@ pub fn read_cli() -> CliOptions {
     fn is_uint_validator(s: &str) -> Result<(), String> {
         let re = regex::Regex::new(r"^[0-9]+$").unwrap();
 
-        if re.is_match(&s) {
+        if re.is_match(s) {
             Ok(())
         } else {
             Err(String::from("Expected input of the form: 16/9"))
@@ -36,6 +40,14 @@ pub fn read_cli() -> CliOptions {
                 .takes_value(false)
                 .help("If set, do not print progressbar or render duration"),
         )
+        // spectral flag
+        .arg(
+            clap::Arg::with_name("spectral")
+                .long("spectral")
+                .required(false)
+                .takes_value(false)
+                .help("If set, render with wavelengths instead of RGB"),
+        )
         // output file
         .arg(
             clap::Arg::with_name("output-file")
@@ -50,7 +62,7 @@ pub fn read_cli() -> CliOptions {
         .arg(
             clap::Arg::with_name("scene-file")
                 .value_name("SCENE")
-                .help("The Scene JSON file")
+                .help("The scene file, in rayo JSON, glTF (.gltf, .glb), pbrt-v4 (.pbrt) or Mitsuba 3 (.xml)")
                 .required(true),
         )
         // resolution
@@ -88,10 +100,18 @@ pub fn read_cli() -> CliOptions {
                 .short("d")
                 .long("depth")
                 .value_name("MAX-DEPTH")
-                .help("Maximum recursion depth")
-                .default_value("30")
+                .help("Maximum path depth [default: 30]")
                 .validator(|s| is_uint_validator(&s)),
         )
+        // integrator
+        .arg(
+            clap::Arg::with_name("integrator")
+                .short("i")
+                .long("integrator")
+                .value_name("INTEGRATOR")
+                .help("Light transport algorithm, overrides the one in the scene file [default: path]")
+                .possible_values(&["path", "bdpt", "mlt", "ppm", "normals", "albedo", "depth", "uv", "object-id", "ao"]),
+        )
         // number of smaples per pixel
         .arg(
             clap::Arg::with_name("num-samples")
@@ -129,13 +149,15 @@ pub fn read_cli() -> CliOptions {
         .parse()
         .unwrap();
     let img_y: u32 = (img_x as f64 / aspect_ratio) as u32;
+    let custom_resolution = matches.occurrences_of("resolution") > 0;
 
     // max depth
-    let max_depth: u32 = matches
+    let max_depth: Option<u32> = matches
         .value_of("max-depth")
-        .unwrap_or_default()
-        .parse()
-        .unwrap();
+        .map(|s| s.parse().unwrap());
+
+    // integrator
+    let integrator = matches.value_of("integrator").map(String::from);
 
     // num samples
     let num_samples: u32 = matches
@@ -147,14 +169,20 @@ pub fn read_cli() -> CliOptions {
     // silent
     let silent: bool = matches.is_present("silent");
 
+    // spectral
+    let spectral: bool = matches.is_present("spectral");
+
     CliOptions {
         output_file,
         scene_file,
         img_x,
         img_y,
+        custom_resolution,
         max_depth,
+        integrator,
         num_samples,
         silent,
+        spectral,
         aspect_ratio,
     }
 }
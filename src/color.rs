@@ -1,5 +1,6 @@
 use std::ops;
 use crate::ray;
+use crate::spectrum;
 
 #[derive(Debug, Clone, Copy)]
 pub struct Color {
@@ -96,6 +97,24 @@ impl Color {
         (1.0-t) * Color::new(1.0, 1.0, 1.0) + t * Color::new(0.5, 0.7, 1.0)
     }
 
+    // color of a blackbody at the given temperature in Kelvin, normalized to unit luminance
+    pub fn blackbody(temperature: f64) -> Color {
+        let (mut x, mut y, mut z) = (0.0, 0.0, 0.0);
+
+        let mut lambda = spectrum::LAMBDA_MIN;
+        while lambda <= spectrum::LAMBDA_MAX {
+            let radiance = spectrum::planck(lambda, temperature);
+            let (xbar, ybar, zbar) = spectrum::cie_xyz(lambda);
+            x += radiance * xbar;
+            y += radiance * ybar;
+            z += radiance * zbar;
+            lambda += 1.0;
+        }
+
+        let rgb = spectrum::xyz_to_rgb(x / y, 1.0, z / y);
+        Color::new(rgb.r.max(0.0), rgb.g.max(0.0), rgb.b.max(0.0))
+    }
+
     pub fn white() -> Color {
         Color {r: 1.0, g: 1.0, b: 1.0}
     }
@@ -104,7 +123,7 @@ impl Color {
         Color {r: 0.0, g: 0.0, b: 0.0}
     }
 
-    pub fn to_pixel(&self, num_samples: u32) -> image::Rgb<u8> {
+    pub fn to_pixel(self, num_samples: u32) -> image::Rgb<u8> {
 
         let mut r = self.r;
         let mut g = self.g;
@@ -123,9 +142,22 @@ impl Color {
         image::Rgb([ri, gi, bi])
     }
 
+    // relative luminance of linear sRGB
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.r + 0.7152 * self.g + 0.0722 * self.b
+    }
+
+    pub fn average(&self) -> f64 {
+        (self.r + self.g + self.b) / 3.0
+    }
+
+    pub fn max_component(&self) -> f64 {
+        self.r.max(self.g).max(self.b)
+    }
+
     pub fn near_zero(&self) -> bool {
         let s = 1e-8;
-        return (self.r.abs() < s) && (self.g.abs() < s) && (self.b.abs() < s);
+        (self.r.abs() < s) && (self.g.abs() < s) && (self.b.abs() < s)
     }
 
     pub fn is_close(&self, vec: &Color) -> bool {
@@ -0,0 +1,65 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use image::{ImageBuffer, RgbImage};
+
+use crate::color::Color;
+
+// Accumulates the radiance of all samples. Pixels can be written from several threads at
+// once, which allows integrators to splat contributions to arbitrary pixels.
+pub struct Film {
+    pub width: u32,
+    pub height: u32,
+    pixels: Vec<[AtomicU64; 3]>,
+}
+
+impl Film {
+
+    pub fn new(width: u32, height: u32) -> Film {
+        let pixels = (0..width * height)
+            .map(|_| [AtomicU64::new(0), AtomicU64::new(0), AtomicU64::new(0)])
+            .collect();
+
+        Film { width, height, pixels }
+    }
+
+    // adds color to pixel (x, y), where y points up like the t coordinate of the camera
+    pub fn add(&self, x: u32, y: u32, color: Color) {
+        let pixel = &self.pixels[((self.height - 1 - y) * self.width + x) as usize];
+        atomic_add(&pixel[0], color.r);
+        atomic_add(&pixel[1], color.g);
+        atomic_add(&pixel[2], color.b);
+    }
+
+    // adds color to the pixel containing film position (s, t)
+    pub fn splat(&self, s: f64, t: f64, color: Color) {
+        let x = ((s * self.width as f64) as u32).min(self.width - 1);
+        let y = ((t * self.height as f64) as u32).min(self.height - 1);
+        self.add(x, y, color);
+    }
+
+    pub fn to_image(&self, num_samples: u32) -> RgbImage {
+        let mut img: RgbImage = ImageBuffer::new(self.width, self.height);
+
+        for (pixel, sum) in img.pixels_mut().zip(&self.pixels) {
+            let color = Color::new(
+                f64::from_bits(sum[0].load(Ordering::Relaxed)),
+                f64::from_bits(sum[1].load(Ordering::Relaxed)),
+                f64::from_bits(sum[2].load(Ordering::Relaxed)),
+            );
+            *pixel = color.to_pixel(num_samples);
+        }
+
+        img
+    }
+}
+
+fn atomic_add(target: &AtomicU64, value: f64) {
+    let mut current = target.load(Ordering::Relaxed);
+    loop {
+        let new = (f64::from_bits(current) + value).to_bits();
+        match target.compare_exchange_weak(current, new, Ordering::Relaxed, Ordering::Relaxed) {
+            Ok(_) => break,
+            Err(actual) => current = actual,
+        }
+    }
+}
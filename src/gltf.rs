@@ -0,0 +1,773 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use json::JsonValue;
+
+use crate::camera::Camera;
+use crate::cli::CliOptions;
+use crate::color::Color;
+use crate::import::{self, Warnings};
+use crate::integrator::Integrator;
+use crate::intersection::{bvh::Aabb, mesh::{Mesh, MeshData}, object::Object, scene::Scene};
+use crate::ior::Ior;
+use crate::material::{Emission, Material};
+use crate::parsing::parse_integrator;
+use crate::texture::{ImageTexture, Texture};
+use crate::transform::Transform;
+use crate::vec::Vec3;
+
+// extensions that are imported, others are reported when the file requires them
+const SUPPORTED_EXTENSIONS: [&str; 4] = ["KHR_lights_punctual", "KHR_materials_emissive_strength", "KHR_materials_ior", "KHR_materials_transmission"];
+
+// Imports a glTF 2.0 file (.gltf or binary .glb) as the scene and camera to render. Features
+// rayo does not have are reported as warnings and left out
+pub fn load(path: &str, opts: &CliOptions) -> (Scene, Camera, Box<dyn Integrator>) {
+    let bytes = fs::read(path).unwrap_or_else(|e| panic!("Could not read glTF file {}: {}", path, e));
+    let directory = Path::new(path).parent().unwrap_or_else(|| Path::new("")).to_path_buf();
+
+    let (text, binary) = if bytes.starts_with(b"glTF") { split_glb(&bytes, path) } else { (bytes, None) };
+    let text = String::from_utf8(text).unwrap_or_else(|_| panic!("{}: invalid text", path));
+    let json = json::parse(&text).unwrap_or_else(|e| panic!("Invalid JSON in {}: {}", path, e));
+
+    // the lights are small, which the bidirectional path tracer handles much better
+    let (objects, camera) = Importer::new(json, binary, directory).import(opts.aspect_ratio);
+    (Scene::new(objects), camera, parse_integrator(&json::object! {"type": "bdpt"}, opts))
+}
+
+// the JSON and binary chunks of a .glb file
+fn split_glb(bytes: &[u8], path: &str) -> (Vec<u8>, Option<Vec<u8>>) {
+    let word = |i: usize| -> u32 {
+        let b = bytes.get(i..i + 4).unwrap_or_else(|| panic!("{}: file ends too early", path));
+        u32::from_le_bytes([b[0], b[1], b[2], b[3]])
+    };
+
+    let mut json = None;
+    let mut binary = None;
+    let mut position = 12;
+    while position + 8 <= bytes.len() {
+        let length = word(position) as usize;
+        let data = bytes.get(position + 8..position + 8 + length).unwrap_or_else(|| panic!("{}: file ends too early", path));
+        match word(position + 4) {
+            0x4E4F534A => json = Some(data.to_vec()),
+            0x004E4942 => binary = Some(data.to_vec()),
+            _ => {}
+        }
+        position += 8 + length;
+    }
+
+    (json.unwrap_or_else(|| panic!("{}: no JSON chunk", path)), binary)
+}
+
+// decodes base64 (also the URL safe variant), None for invalid characters
+fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    let mut bytes = vec![];
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for c in text.bytes().filter(|c| !c.is_ascii_whitespace()) {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            b'=' => break,
+            _ => return None
+        };
+        buffer = (buffer << 6) | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+    Some(bytes)
+}
+
+struct Importer {
+    json: JsonValue,
+    buffers: Vec<Vec<u8>>,
+    directory: PathBuf,
+    // images by index, None if they could not be read
+    images: RefCell<HashMap<usize, Option<Arc<ImageTexture>>>>,
+    warnings: Warnings,
+}
+
+impl Importer {
+
+    fn new(json: JsonValue, binary: Option<Vec<u8>>, directory: PathBuf) -> Importer {
+        let mut importer = Importer { json, buffers: vec![], directory, images: RefCell::default(), warnings: Warnings::default() };
+
+        let mut binary = binary;
+        importer.buffers = importer.json["buffers"]
+            .members()
+            .map(|buffer| match buffer["uri"].as_str() {
+                Some(uri) => importer.read_uri(uri).unwrap_or_default(),
+                // the binary chunk of a .glb file
+                None => binary.take().unwrap_or_default(),
+            })
+            .collect();
+
+        importer
+    }
+
+    // contents of a data URI or of a file relative to the glTF file
+    fn read_uri(&self, uri: &str) -> Option<Vec<u8>> {
+        if let Some(data) = uri.strip_prefix("data:") {
+            let decoded = data.split_once(";base64,").and_then(|(_, data)| decode_base64(data));
+            if decoded.is_none() {
+                self.warnings.warn("only base64 data URIs are supported");
+            }
+            return decoded;
+        }
+
+        // file names in URIs escape spaces and other characters
+        let path = uri.replace("%20", " ");
+        match fs::read(self.directory.join(&path)) {
+            Ok(bytes) => Some(bytes),
+            Err(e) => {
+                self.warnings.warn(&format!("could not read {}: {}", path, e));
+                None
+            }
+        }
+    }
+
+    // The elements of an accessor, with all their components one after another. None, with a
+    // warning, if the accessor can not be read
+    fn accessor(&self, index: &JsonValue) -> Option<Vec<f64>> {
+        let accessor = &self.json["accessors"][index.as_usize().unwrap_or(usize::MAX)];
+        if accessor.is_null() {
+            self.warnings.warn(&format!("accessor {} does not exist", index));
+            return None;
+        }
+        let count = accessor["count"].as_usize().unwrap_or(0);
+        let components = match accessor["type"].as_str().unwrap_or("") {
+            "SCALAR" => 1,
+            "VEC2" => 2,
+            "VEC3" => 3,
+            "VEC4" => 4,
+            other => {
+                self.warnings.warn(&format!("accessors of type {} are not supported", other));
+                return None;
+            }
+        };
+        if !accessor["sparse"].is_null() {
+            self.warnings.warn("sparse accessors are not supported");
+        }
+
+        let component_type = accessor["componentType"].as_u32().unwrap_or(0);
+        let size = match component_type {
+            5120 | 5121 => 1,
+            5122 | 5123 => 2,
+            5125 | 5126 => 4,
+            _ => {
+                self.warnings.warn(&format!("unknown accessor component type {}", component_type));
+                return None;
+            }
+        };
+        let normalized = accessor["normalized"].as_bool().unwrap_or(false);
+
+        // accessors without a buffer view are all zeros
+        let view = &self.json["bufferViews"][accessor["bufferView"].as_usize().unwrap_or(usize::MAX)];
+        if view.is_null() {
+            return Some(vec![0.0; count * components]);
+        }
+        let offset = view["byteOffset"].as_usize().unwrap_or(0) + accessor["byteOffset"].as_usize().unwrap_or(0);
+        let stride = view["byteStride"].as_usize().unwrap_or(components * size);
+
+        // buffers that could not be read are empty
+        let buffer = self.buffers.get(view["buffer"].as_usize().unwrap_or(usize::MAX)).map_or(&[][..], |b| &b[..]);
+        if count > 0 && buffer.len() < offset + (count - 1) * stride + components * size {
+            self.warnings.warn("skipping data whose accessor reaches past the end of its buffer");
+            return None;
+        }
+
+        let mut values = Vec::with_capacity(count * components);
+        for element in 0..count {
+            for component in 0..components {
+                let start = offset + element * stride + component * size;
+                let b = &buffer[start..start + size];
+                let value = match component_type {
+                    5120 => b[0] as i8 as f64,
+                    5121 => b[0] as f64,
+                    5122 => i16::from_le_bytes([b[0], b[1]]) as f64,
+                    5123 => u16::from_le_bytes([b[0], b[1]]) as f64,
+                    5125 => u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+                    _ => f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+                };
+                values.push(match (normalized, component_type) {
+                    (true, 5120) => (value / 127.0).max(-1.0),
+                    (true, 5121) => value / 255.0,
+                    (true, 5122) => (value / 32767.0).max(-1.0),
+                    (true, 5123) => value / 65535.0,
+                    _ => value
+                });
+            }
+        }
+        Some(values)
+    }
+
+    // the objects of the default scene and the camera of the first node that has one
+    fn import(&self, aspect_ratio: f64) -> (Vec<Object>, Camera) {
+        let scene = &self.json["scenes"][self.json["scene"].as_usize().unwrap_or(0)];
+        let roots: Vec<usize> = scene["nodes"].members().filter_map(|n| n.as_usize()).collect();
+        if !self.json["animations"].is_empty() {
+            self.warnings.warn("animations are not supported, the scene is rendered at rest");
+        }
+        if !self.json["skins"].is_empty() {
+            self.warnings.warn("skins are not supported, skinned meshes are rendered at rest");
+        }
+        for extension in self.json["extensionsRequired"].members().filter_map(|e| e.as_str()) {
+            if !SUPPORTED_EXTENSIONS.contains(&extension) {
+                self.warnings.warn(&format!("the required extension {} is not supported", extension));
+            }
+        }
+
+        // the nodes are flattened with their transformations to the world
+        let mut nodes = vec![];
+        let mut visited = HashSet::new();
+        for root in roots {
+            self.flatten(root, Transform::identity(), &mut nodes, &mut visited);
+        }
+
+        let materials: Vec<Material> = (0..self.json["materials"].len()).map(|index| self.material(index)).collect();
+        let mut objects = vec![];
+        let mut bounds = Aabb::empty();
+        let mut camera = None;
+
+        for (node, to_world) in &nodes {
+            let node = &self.json["nodes"][*node];
+
+            for primitive in self.json["meshes"][node["mesh"].as_usize().unwrap_or(usize::MAX)]["primitives"].members() {
+                if let Some(mut data) = self.primitive(primitive) {
+                    data.transform(to_world);
+                    bounds = bounds.union(&Aabb::around(&data.positions));
+
+                    let index = primitive["material"].as_usize();
+                    let material = match index.map(|index| materials.get(index)) {
+                        Some(Some(material)) => material.clone(),
+                        None if !data.colors.is_empty() => Material::Lambertian { albedo: Texture::VertexColor },
+                        missing => {
+                            if missing.is_some() {
+                                self.warnings.warn("a mesh primitive refers to a material that does not exist, using the default one");
+                            }
+                            Material::Lambertian { albedo: Texture::Constant { color: Color::new(0.8, 0.8, 0.8) } }
+                        }
+                    };
+                    objects.push(Object::Mesh(Mesh::new(data, material, None)));
+                }
+            }
+
+            if let (None, Some(index)) = (&camera, node["camera"].as_usize()) {
+                camera = self.camera(index, to_world, aspect_ratio);
+            }
+        }
+
+        // lights are sized by the scene
+        let (center, size) = import::extent(&bounds, Vec3::zero());
+        for (node, to_world) in &nodes {
+            let light = &self.json["nodes"][*node]["extensions"]["KHR_lights_punctual"]["light"];
+            if let Some(index) = light.as_usize() {
+                objects.extend(self.light(index, to_world, center, size));
+            }
+        }
+
+        let camera = camera.unwrap_or_else(|| {
+            self.warnings.warn("the scene has no camera, looking at it from the front");
+            let lookfrom = center + Vec3::new(0.0, 0.0, 1.5 * size);
+            Camera::new(lookfrom, center, Vec3::new(0.0, 1.0, 0.0), 40.0, 1.0, 0.0, aspect_ratio)
+        });
+
+        (objects, camera)
+    }
+
+    fn flatten(&self, index: usize, parent: Transform, nodes: &mut Vec<(usize, Transform)>, visited: &mut HashSet<usize>) {
+        // every node has at most one parent, so a node reached twice is part of a cycle or shared
+        if !visited.insert(index) {
+            self.warnings.warn(&format!("node {} is reached more than once, skipping it", index));
+            return;
+        }
+        let node = &self.json["nodes"][index];
+        if node.is_null() {
+            self.warnings.warn(&format!("node {} does not exist", index));
+            return;
+        }
+        let to_world = match self.node_transform(node) {
+            Some(transform) => transform.then(&parent),
+            None => {
+                self.warnings.warn(&format!("node {} has an invalid transformation, skipping it", index));
+                return;
+            }
+        };
+        nodes.push((index, to_world));
+        for child in node["children"].members().filter_map(|c| c.as_usize()) {
+            self.flatten(child, to_world, nodes, visited);
+        }
+    }
+
+    // transformation of a node relative to its parent, a matrix or scale, rotation and translation.
+    // None if one of them has the wrong number of values
+    fn node_transform(&self, node: &JsonValue) -> Option<Transform> {
+        let numbers = |value: &JsonValue, default: &[f64]| -> Option<Vec<f64>> {
+            let numbers: Vec<f64> = if value.is_null() { default.to_vec() } else { value.members().map(|v| v.as_f64().unwrap_or(0.0)).collect() };
+            Some(numbers).filter(|n| n.len() == default.len())
+        };
+
+        if !node["matrix"].is_null() {
+            // stored column by column
+            let m = numbers(&node["matrix"], &[0.0; 16])?;
+            return Some(Transform::from_rows([
+                [m[0], m[4], m[8], m[12]],
+                [m[1], m[5], m[9], m[13]],
+                [m[2], m[6], m[10], m[14]],
+            ]));
+        }
+
+        let s = numbers(&node["scale"], &[1.0, 1.0, 1.0])?;
+        let t = numbers(&node["translation"], &[0.0, 0.0, 0.0])?;
+        let q = numbers(&node["rotation"], &[0.0, 0.0, 0.0, 1.0])?;
+        let (x, y, z, w) = (q[0], q[1], q[2], q[3]);
+        let rotation = Transform::from_rows([
+            [1.0 - 2.0 * (y * y + z * z), 2.0 * (x * y - z * w), 2.0 * (x * z + y * w), 0.0],
+            [2.0 * (x * y + z * w), 1.0 - 2.0 * (x * x + z * z), 2.0 * (y * z - x * w), 0.0],
+            [2.0 * (x * z - y * w), 2.0 * (y * z + x * w), 1.0 - 2.0 * (x * x + y * y), 0.0],
+        ]);
+
+        Some(Transform::scale(Vec3::new(s[0], s[1], s[2]))
+            .then(&rotation)
+            .then(&Transform::translation(Vec3::new(t[0], t[1], t[2]))))
+    }
+
+    // the triangles of a mesh primitive, in the coordinates of its node
+    fn primitive(&self, primitive: &JsonValue) -> Option<MeshData> {
+        let mode = primitive["mode"].as_u32().unwrap_or(4);
+        if mode != 4 {
+            self.warnings.warn(&format!("only triangle primitives are supported, not mode {}", mode));
+            return None;
+        }
+        if !primitive["targets"].is_null() {
+            self.warnings.warn("morph targets are not supported");
+        }
+        // compressed primitives have no buffer views, their accessors would read as zeros
+        if !primitive["extensions"]["KHR_draco_mesh_compression"].is_null() {
+            self.warnings.warn("skipping a Draco compressed mesh primitive, KHR_draco_mesh_compression is not supported");
+            return None;
+        }
+
+        let attributes = &primitive["attributes"];
+        let triples = |values: Vec<f64>, stride: usize| -> Vec<Vec3> {
+            values.chunks_exact(stride).map(|v| Vec3::new(v[0], v[1], v[2])).collect()
+        };
+
+        // primitives with data that can not be read are skipped
+        let mut data = MeshData { positions: triples(self.accessor(&attributes["POSITION"])?, 3), ..MeshData::default() };
+        let count = data.positions.len();
+        if !attributes["NORMAL"].is_null() {
+            data.normals = triples(self.accessor(&attributes["NORMAL"])?, 3);
+        }
+        if !attributes["TEXCOORD_0"].is_null() {
+            // glTF puts v = 0 at the top of images
+            data.uvs = self.accessor(&attributes["TEXCOORD_0"])?.chunks_exact(2).map(|uv| (uv[0], 1.0 - uv[1])).collect();
+        }
+        if !attributes["COLOR_0"].is_null() {
+            let stride = if self.json["accessors"][attributes["COLOR_0"].as_usize().unwrap_or(0)]["type"] == "VEC4" { 4 } else { 3 };
+            data.colors = triples(self.accessor(&attributes["COLOR_0"])?, stride).iter().map(|c| Color::new(c.x, c.y, c.z)).collect();
+        }
+
+        // without indices every three vertices are a triangle
+        let indices: Vec<usize> = if primitive["indices"].is_null() {
+            (0..count).collect()
+        } else {
+            self.accessor(&primitive["indices"])?.iter().map(|i| *i as usize).collect()
+        };
+        data.triangles = indices.chunks_exact(3).map(|t| [t[0], t[1], t[2]]).collect();
+
+        let attributes_match = [data.normals.len(), data.uvs.len(), data.colors.len()].iter().all(|&n| n == 0 || n == count);
+        if !attributes_match || indices.iter().any(|&i| i >= count) {
+            self.warnings.warn("skipping a mesh primitive with invalid vertex attributes or indices");
+            return None;
+        }
+
+        Some(data)
+    }
+
+    // Maps a metallic-roughness material onto layered plastic, metal or a mix of both. Materials
+    // with transmission become glass
+    fn material(&self, index: usize) -> Material {
+        let material = &self.json["materials"][index];
+        let pbr = &material["pbrMetallicRoughness"];
+        let extensions = &material["extensions"];
+
+        for (name, _) in extensions.entries() {
+            if !["KHR_materials_emissive_strength", "KHR_materials_ior", "KHR_materials_transmission"].contains(&name) {
+                self.warnings.warn(&format!("the material extension {} is not supported", name));
+            }
+        }
+        if !pbr["metallicRoughnessTexture"].is_null() {
+            self.warnings.warn("metallic roughness textures are not supported, the metallic and roughness factors are used");
+        }
+        if !material["normalTexture"].is_null() {
+            self.warnings.warn("normal maps are not supported");
+        }
+
+        let factor = |value: &JsonValue, default: f64| -> Vec<f64> {
+            let values: Vec<f64> = value.members().filter_map(|v| v.as_f64()).collect();
+            if values.len() >= 3 { values } else { vec![default; 4] }
+        };
+        let base_factor = factor(&pbr["baseColorFactor"], 1.0);
+        let base_color = match self.texture(&pbr["baseColorTexture"]) {
+            Some(texture) => {
+                if base_factor[..3].iter().any(|f| *f != 1.0) {
+                    self.warnings.warn("base color factors are ignored for materials with a base color texture");
+                }
+                texture
+            },
+            None => Texture::Constant { color: Color::new(base_factor[0], base_factor[1], base_factor[2]) }
+        };
+
+        let metallic = pbr["metallicFactor"].as_f64().unwrap_or(1.0).clamp(0.0, 1.0);
+        let roughness = pbr["roughnessFactor"].as_f64().unwrap_or(1.0).clamp(0.0, 1.0);
+        let ior = extensions["KHR_materials_ior"]["ior"].as_f64().unwrap_or(1.5);
+        let transmission = extensions["KHR_materials_transmission"]["transmissionFactor"].as_f64().unwrap_or(0.0);
+
+        // the roughness of glTF is squared for the microfacet distribution, like the one of layered
+        let metal = Material::Metal { albedo: base_color.clone(), fuzz: roughness * roughness, film: None };
+        let plastic = Material::Layered {
+            base: Box::new(Material::Lambertian { albedo: base_color.clone() }),
+            ior,
+            roughness,
+            thickness: 1.0,
+            tint: Color::white()
+        };
+        let mut result = if transmission > 0.0 {
+            if transmission < 1.0 || roughness > 0.0 {
+                self.warnings.warn("partial and rough transmission are rendered as clear glass");
+            }
+            Material::Dielectric { ior: Ior::Constant(ior), color: base_color.clone(), film: None }
+        } else if metallic >= 1.0 {
+            metal
+        } else if metallic <= 0.0 {
+            plastic
+        } else {
+            Material::Mix { first: Box::new(plastic), second: Box::new(metal), mask: Texture::Constant { color: Color::new(metallic, metallic, metallic) } }
+        };
+
+        let emissive = factor(&material["emissiveFactor"], 0.0);
+        let strength = extensions["KHR_materials_emissive_strength"]["emissiveStrength"].as_f64().unwrap_or(1.0);
+        let emission = match self.texture(&material["emissiveTexture"]) {
+            Some(texture) => Some(Emission { strength: strength * emissive[..3].iter().cloned().fold(0.0, f64::max), ..Emission::new(texture) }),
+            None if emissive[..3].iter().any(|e| *e > 0.0) => Some(Emission {
+                strength,
+                ..Emission::new(Texture::Constant { color: Color::new(emissive[0], emissive[1], emissive[2]) })
+            }),
+            None => None
+        };
+        if let Some(emission) = emission {
+            result = Material::Emissive { base: Box::new(result), emission };
+        }
+
+        // the alpha is the one of the base color factor times the one of the base color texture
+        let alpha = base_factor.get(3).copied().unwrap_or(1.0);
+        let (alpha_texture, opaque) = match &base_color {
+            Texture::Image { image } if image.has_alpha() => (Texture::Alpha { image: image.clone(), factor: alpha }, false),
+            _ => (Texture::Constant { color: Color::new(alpha, alpha, alpha) }, alpha >= 1.0)
+        };
+        match material["alphaMode"].as_str() {
+            Some("MASK") => {
+                let threshold = Some(material["alphaCutoff"].as_f64().unwrap_or(0.5));
+                result = Material::Cutout { base: Box::new(result), alpha: alpha_texture, threshold };
+            },
+            Some("BLEND") if !opaque => {
+                result = Material::Cutout { base: Box::new(result), alpha: alpha_texture, threshold: None };
+            },
+            _ => {}
+        }
+
+        result
+    }
+
+    // image texture of a texture info, None if there is none or it can not be read
+    fn texture(&self, info: &JsonValue) -> Option<Texture> {
+        let texture = &self.json["textures"][info["index"].as_usize()?];
+        if info["texCoord"].as_u32().unwrap_or(0) > 0 {
+            self.warnings.warn("only the first set of texture coordinates is supported");
+        }
+        if !info["extensions"]["KHR_texture_transform"].is_null() {
+            self.warnings.warn("texture transforms are not supported");
+        }
+
+        let Some(source) = texture["source"].as_usize() else {
+            self.warnings.warn("textures need a PNG or JPEG source");
+            return None;
+        };
+        if let Some(image) = self.images.borrow().get(&source) {
+            return image.clone().map(|image| Texture::Image { image });
+        }
+
+        let image = &self.json["images"][source];
+        let bytes = match image["uri"].as_str() {
+            Some(uri) => self.read_uri(uri),
+            None => {
+                let view = &self.json["bufferViews"][image["bufferView"].as_usize()?];
+                let offset = view["byteOffset"].as_usize().unwrap_or(0);
+                let length = view["byteLength"].as_usize().unwrap_or(0);
+                self.buffers.get(view["buffer"].as_usize()?).and_then(|b| b.get(offset..offset + length)).map(|b| b.to_vec())
+            }
+        };
+        let decoded = bytes.and_then(|bytes| ImageTexture::decode(&bytes, true)).map(Arc::new);
+        if decoded.is_none() {
+            self.warnings.warn(&format!("could not read image {}", source));
+        }
+
+        self.images.borrow_mut().insert(source, decoded.clone());
+        decoded.map(|image| Texture::Image { image })
+    }
+
+    // cameras look along their negative z axis, with y pointing up
+    fn camera(&self, index: usize, to_world: &Transform, aspect_ratio: f64) -> Option<Camera> {
+        let camera = &self.json["cameras"][index];
+        let yfov = match camera["type"].as_str() {
+            Some("perspective") => camera["perspective"]["yfov"].as_f64().unwrap_or(0.8),
+            _ => {
+                self.warnings.warn("only perspective cameras are supported");
+                return None;
+            }
+        };
+
+        let lookfrom = to_world.point(Vec3::zero());
+        let lookat = lookfrom + to_world.vector(Vec3::new(0.0, 0.0, -1.0));
+        let vup = to_world.vector(Vec3::new(0.0, 1.0, 0.0));
+        Some(Camera::new(lookfrom, lookat, vup, yfov.to_degrees(), 1.0, 0.0, aspect_ratio))
+    }
+
+    // Punctual lights become small glowing spheres with the same intensity. Directional ones are
+    // placed far away, in the direction the light comes from
+    fn light(&self, index: usize, to_world: &Transform, center: Vec3, size: f64) -> Option<Object> {
+        let light = &self.json["extensions"]["KHR_lights_punctual"]["lights"][index];
+        let color = match light["color"].members().filter_map(|c| c.as_f64()).collect::<Vec<f64>>()[..] {
+            [r, g, b] => Color::new(r, g, b),
+            _ => Color::white()
+        };
+        let intensity = light["intensity"].as_f64().unwrap_or(1.0);
+
+        // the intensity is in candela and the irradiance of directional lights in lux
+        match light["type"].as_str() {
+            Some(kind @ ("point" | "spot")) => {
+                if kind == "spot" {
+                    self.warnings.warn("spot lights are rendered as point lights");
+                }
+                Some(import::point_light(to_world.point(Vec3::zero()), intensity * color, size))
+            },
+            Some("directional") => {
+                let direction = to_world.vector(Vec3::new(0.0, 0.0, -1.0));
+                Some(import::directional_light(center, direction, intensity * color, size))
+            },
+            other => {
+                self.warnings.warn(&format!("unknown light type {}", other.unwrap_or("")));
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::intersection::Hittable;
+    use crate::ray::Ray;
+
+    // a triangle as floats followed by its indices as shorts
+    fn triangle_buffer() -> Vec<u8> {
+        let mut binary = vec![];
+        for value in [-1.0f32, -1.0, 0.0, 1.0, -1.0, 0.0, 0.0, 1.0, 0.0] {
+            binary.extend(value.to_le_bytes());
+        }
+        for index in [0u16, 1, 2, 0] {
+            binary.extend(index.to_le_bytes());
+        }
+        binary
+    }
+
+    // an importer for the triangle buffer with the given top level glTF properties
+    fn importer(properties: &str) -> Importer {
+        let json = json::parse(&format!(r#"{{
+            "accessors": [
+                {{"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3"}},
+                {{"bufferView": 0, "byteOffset": 36, "componentType": 5123, "count": 3, "type": "SCALAR"}}
+            ],
+            "bufferViews": [{{"buffer": 0, "byteLength": 44}}],
+            "buffers": [{{"byteLength": 44}}],
+            {}
+        }}"#, properties)).unwrap();
+        Importer::new(json, Some(triangle_buffer()), PathBuf::new())
+    }
+
+    #[test]
+    fn test_decode_base64() {
+        assert_eq!(decode_base64("aGVsbG8gZ2x0Zg=="), Some(b"hello gltf".to_vec()));
+        assert_eq!(decode_base64("aGk="), Some(b"hi".to_vec()));
+        assert_eq!(decode_base64("a$=="), None);
+    }
+
+    #[test]
+    fn test_accessor() {
+        let mut importer = importer(r#""asset": {}"#);
+        importer.buffers.push(vec![0, 255, 7, 0, 51, 7]);
+        importer.json["bufferViews"].push(json::object! { "buffer": 1, "byteLength": 6, "byteStride": 3 }).unwrap();
+        importer.json["bufferViews"].push(json::object! { "buffer": 7, "byteLength": 6 }).unwrap();
+        let accessors = [
+            // normalized bytes with a stride
+            json::object! { "bufferView": 1, "componentType": 5121, "normalized": true, "count": 2, "type": "VEC2" },
+            // no buffer view
+            json::object! { "componentType": 5126, "count": 2, "type": "SCALAR" },
+            json::object! { "bufferView": 1, "componentType": 5124, "count": 1, "type": "SCALAR" },
+            json::object! { "bufferView": 0, "byteOffset": 40, "componentType": 5123, "count": 3, "type": "SCALAR" },
+            json::object! { "bufferView": 2, "componentType": 5121, "count": 1, "type": "SCALAR" },
+        ];
+        for accessor in accessors.iter() {
+            importer.json["accessors"].push(accessor.clone()).unwrap();
+        }
+
+        assert_eq!(importer.accessor(&1.into()), Some(vec![0.0, 1.0, 2.0]));
+        assert_eq!(importer.accessor(&2.into()), Some(vec![0.0, 1.0, 0.0, 0.2]));
+        assert_eq!(importer.accessor(&3.into()), Some(vec![0.0, 0.0]));
+
+        // unknown component types, short and missing buffers are warned about instead of panicking
+        assert_eq!(importer.accessor(&4.into()), None);
+        assert!(importer.warnings.contains("unknown accessor component type 5124"));
+        assert_eq!(importer.accessor(&5.into()), None);
+        assert_eq!(importer.accessor(&6.into()), None);
+        assert!(importer.warnings.contains("past the end of its buffer"));
+        assert_eq!(importer.accessor(&20.into()), None);
+    }
+
+    #[test]
+    fn test_skipped_primitives() {
+        let importer = importer(r#"
+            "extensionsRequired": ["KHR_draco_mesh_compression", "KHR_materials_ior"],
+            "scenes": [{"nodes": [0]}],
+            "nodes": [{"mesh": 0}],
+            "meshes": [{"primitives": [
+                {"attributes": {"POSITION": 0}, "indices": 1},
+                {"attributes": {"POSITION": 0}, "mode": 1},
+                {"attributes": {"POSITION": 0}, "extensions": {"KHR_draco_mesh_compression": {"bufferView": 0}}},
+                {"attributes": {"POSITION": 0, "NORMAL": 9}}
+            ]}]
+        "#);
+        let (objects, _) = importer.import(1.0);
+
+        // only the triangle list is left
+        assert_eq!(objects.len(), 1);
+        assert!(importer.warnings.contains("the required extension KHR_draco_mesh_compression is not supported"));
+        assert!(!importer.warnings.contains("KHR_materials_ior"));
+        assert!(importer.warnings.contains("Draco compressed"));
+        assert!(importer.warnings.contains("accessor 9 does not exist"));
+    }
+
+    #[test]
+    fn test_node_transforms() {
+        let importer = importer(r#"
+            "scene": 0,
+            "scenes": [{"nodes": [0, 1, 3]}],
+            "nodes": [
+                {"mesh": 0, "translation": [0, 0, -5], "children": [2]},
+                {"camera": 0, "rotation": [0, 0, 0, 1]},
+                {"extensions": {"KHR_lights_punctual": {"light": 0}}, "translation": [0, 2, 0]},
+                {"mesh": 0, "matrix": [2, 0, 0, 0, 0, 2, 0, 0, 0, 0, 2, 0, 0, 0, -10, 1]}
+            ],
+            "meshes": [{"primitives": [{"attributes": {"POSITION": 0}, "indices": 1}]}],
+            "cameras": [{"type": "perspective", "perspective": {"yfov": 0.7, "znear": 0.1}}],
+            "extensions": {"KHR_lights_punctual": {"lights": [{"type": "point", "intensity": 10}]}}
+        "#);
+        let (objects, camera) = importer.import(1.0);
+        assert!(camera.origin.is_close(&Vec3::zero()));
+        assert_eq!(objects.len(), 3);
+
+        // the translated mesh is hit first, the scaled instance behind it is larger
+        let ray = Ray::new(Vec3::zero(), Vec3::new(0.0, 0.0, -1.0));
+        let hit = objects[0].intersect(&ray, 1e-6, f64::MAX).unwrap();
+        assert!((hit.t - 5.0).abs() < 1e-9);
+        let ray = Ray::new(Vec3::new(1.5, -1.5, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(objects[0].intersect(&ray, 1e-6, f64::MAX).is_none());
+        assert!((objects[1].intersect(&ray, 1e-6, f64::MAX).unwrap().t - 10.0).abs() < 1e-9);
+
+        // the light is a sphere moved with its parent, after the meshes
+        match &objects[2] {
+            Object::Sphere(sphere) => assert!(sphere.center.is_close(&Vec3::new(0.0, 2.0, -5.0))),
+            other => panic!("Expected a sphere, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn test_invalid_nodes() {
+        // a cycle, a node with too short a translation and a missing child are skipped
+        let importer = importer(r#"
+            "scenes": [{"nodes": [0, 2]}],
+            "nodes": [
+                {"mesh": 0, "children": [1]},
+                {"mesh": 0, "children": [0, 5]},
+                {"mesh": 0, "translation": [1, 2]}
+            ],
+            "meshes": [{"primitives": [{"attributes": {"POSITION": 0}, "indices": 1}]}]
+        "#);
+        let (objects, _) = importer.import(1.0);
+        assert_eq!(objects.len(), 2);
+        assert!(importer.warnings.contains("node 0 is reached more than once"));
+        assert!(importer.warnings.contains("node 5 does not exist"));
+        assert!(importer.warnings.contains("node 2 has an invalid transformation"));
+
+        let node = json::object! { "rotation": [0, 0, 1] };
+        assert!(importer.node_transform(&node).is_none());
+        let node = json::object! { "matrix": [1, 0, 0, 1] };
+        assert!(importer.node_transform(&node).is_none());
+    }
+
+    #[test]
+    fn test_materials() {
+        let importer = importer(r#"
+            "materials": [
+                {"pbrMetallicRoughness": {"metallicFactor": 0.0, "roughnessFactor": 0.2}, "extensions": {"KHR_materials_sheen": {}}},
+                {"pbrMetallicRoughness": {"roughnessFactor": 0.5}},
+                {"pbrMetallicRoughness": {"metallicFactor": 0.25}},
+                {"extensions": {"KHR_materials_transmission": {"transmissionFactor": 1.0}, "KHR_materials_ior": {"ior": 1.33}}},
+                {"emissiveFactor": [1, 0.5, 0], "extensions": {"KHR_materials_emissive_strength": {"emissiveStrength": 4}}},
+                {"alphaMode": "MASK", "alphaCutoff": 0.3, "pbrMetallicRoughness": {"baseColorFactor": [1, 1, 1, 0.2]}},
+                {"alphaMode": "BLEND", "pbrMetallicRoughness": {"baseColorFactor": [1, 1, 1, 0.5], "baseColorTexture": {"index": 0}}}
+            ],
+            "textures": [{"source": 0}]
+        "#);
+        // an already decoded image with an alpha channel
+        let image = image::RgbaImage::from_pixel(1, 1, image::Rgba([255, 255, 255, 128]));
+        importer.images.borrow_mut().insert(0, Some(Arc::new(ImageTexture::from_image(&image, true))));
+
+        assert!(matches!(importer.material(0), Material::Layered { roughness, .. } if (roughness - 0.2).abs() < 1e-9));
+        assert!(importer.warnings.contains("KHR_materials_sheen"));
+        assert!(matches!(importer.material(1), Material::Metal { fuzz, .. } if (fuzz - 0.25).abs() < 1e-9));
+        assert!(matches!(importer.material(2), Material::Mix { .. }));
+        assert!(matches!(importer.material(3), Material::Dielectric { ior: Ior::Constant(ior), .. } if (ior - 1.33).abs() < 1e-9));
+        assert!(matches!(importer.material(4), Material::Emissive { emission, .. } if emission.strength == 4.0));
+        assert!(matches!(importer.material(5), Material::Cutout { threshold: Some(t), .. } if t == 0.3));
+        // the alpha of the texture is scaled by the one of the factor
+        assert!(matches!(importer.material(6), Material::Cutout { alpha: Texture::Alpha { factor, .. }, threshold: None, .. } if factor == 0.5));
+    }
+
+    #[test]
+    fn test_missing_material() {
+        let importer = importer(r#"
+            "scenes": [{"nodes": [0]}],
+            "nodes": [{"mesh": 0}],
+            "meshes": [{"primitives": [{"attributes": {"POSITION": 0}, "indices": 1, "material": 3}]}],
+            "materials": [{}]
+        "#);
+        let (objects, _) = importer.import(1.0);
+
+        // the default material is used instead
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit = objects[0].intersect(&ray, 1e-6, f64::MAX).unwrap();
+        assert!(matches!(hit.material, Material::Lambertian { .. }));
+        assert!(importer.warnings.contains("material that does not exist"));
+    }
+}
@@ -0,0 +1,259 @@
+use std::fs;
+use std::path::Path;
+
+use crate::ray::Ray;
+use crate::transform::Transform;
+use crate::vec::Vec3;
+
+// number of majorant cells along every axis (at most, small grids use one cell per voxel)
+const MAJORANT_CELLS: usize = 16;
+
+// Voxel grid of densities filling a box in the scene. Densities are interpolated trilinearly
+// between the voxel centers. A coarse grid stores the largest density in each of its cells,
+// which gives tight bounds for delta tracking through sparse volumes like smoke.
+#[derive(Debug)]
+pub struct DensityGrid {
+    resolution: [usize; 3],
+    densities: Vec<f32>,
+    majorant_resolution: [usize; 3],
+    majorants: Vec<f64>,
+    // maps world space to the unit cube covered by the grid
+    world_to_grid: Transform,
+}
+
+impl DensityGrid {
+
+    // Reads a grid from a JSON header of the form {"resolution": [nx, ny, nz], "data": "file.raw"},
+    // where the data file holds nx * ny * nz little endian float32 densities, x varying fastest
+    pub fn load(header_path: &str, grid_to_world: Transform) -> DensityGrid {
+        let header = fs::read_to_string(header_path)
+            .unwrap_or_else(|e| panic!("Could not read grid header {}: {}", header_path, e));
+        let header = json::parse(&header).unwrap_or_else(|e| panic!("Invalid grid header {}: {}", header_path, e));
+
+        let mut resolution = [0; 3];
+        for (axis, size) in resolution.iter_mut().enumerate() {
+            *size = header["resolution"][axis].as_usize().unwrap_or_else(|| panic!("Grid resolution should be three integers"));
+            if *size == 0 {
+                panic!("Grid resolution in {} should be at least 1 along every axis", header_path);
+            }
+        }
+
+        // the data file is relative to the header
+        let data_file = header["data"].as_str().unwrap_or_else(|| panic!("Grid header should name its data file"));
+        let data_path = Path::new(header_path).parent().unwrap_or_else(|| Path::new("")).join(data_file);
+        let bytes = fs::read(&data_path)
+            .unwrap_or_else(|e| panic!("Could not read grid data {}: {}", data_path.display(), e));
+
+        let voxels = resolution[0] * resolution[1] * resolution[2];
+        if bytes.len() != 4 * voxels {
+            panic!("Grid data {} holds {} bytes, but the resolution {:?} needs {} float32 values ({} bytes)",
+                data_path.display(), bytes.len(), resolution, voxels, 4 * voxels);
+        }
+
+        let densities = bytes
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]).max(0.0))
+            .collect();
+
+        DensityGrid::new(resolution, densities, grid_to_world)
+    }
+
+    // grid_to_world maps the unit cube onto the box the grid fills in the scene
+    pub fn new(resolution: [usize; 3], densities: Vec<f32>, grid_to_world: Transform) -> DensityGrid {
+        if resolution.contains(&0) {
+            panic!("Grid resolution should be at least 1 along every axis, got {:?}", resolution);
+        }
+        if densities.len() != resolution[0] * resolution[1] * resolution[2] {
+            panic!("Grid has {} densities, but its resolution {:?} needs {}", densities.len(), resolution, resolution[0] * resolution[1] * resolution[2]);
+        }
+        // a flat box can not be mapped back onto the grid
+        let determinant = grid_to_world.determinant();
+        if determinant == 0.0 || !determinant.is_finite() {
+            panic!("Grid box should have a size along every axis, its min and max should differ in x, y and z");
+        }
+
+        let majorant_resolution = [
+            resolution[0].clamp(1, MAJORANT_CELLS),
+            resolution[1].clamp(1, MAJORANT_CELLS),
+            resolution[2].clamp(1, MAJORANT_CELLS),
+        ];
+
+        let mut grid = DensityGrid {
+            resolution,
+            densities,
+            majorant_resolution,
+            majorants: vec![0.0; majorant_resolution[0] * majorant_resolution[1] * majorant_resolution[2]],
+            world_to_grid: grid_to_world.inverse(),
+        };
+
+        // a cell is bounded by all voxels that take part in the interpolation inside of it
+        for z in 0..majorant_resolution[2] {
+            for y in 0..majorant_resolution[1] {
+                for x in 0..majorant_resolution[0] {
+                    let cell = [x, y, z];
+                    let mut ranges = [(0, 0); 3];
+                    for axis in 0..3 {
+                        let n = resolution[axis] as f64;
+                        let m = majorant_resolution[axis] as f64;
+                        let low = (cell[axis] as f64 / m * n - 0.5).floor().max(0.0) as usize;
+                        let high = ((cell[axis] + 1) as f64 / m * n - 0.5).ceil().max(0.0) as usize;
+                        ranges[axis] = (low, high.min(resolution[axis] - 1));
+                    }
+
+                    let mut majorant = 0.0f64;
+                    for vz in ranges[2].0..=ranges[2].1 {
+                        for vy in ranges[1].0..=ranges[1].1 {
+                            for vx in ranges[0].0..=ranges[0].1 {
+                                majorant = majorant.max(grid.voxel(vx, vy, vz));
+                            }
+                        }
+                    }
+
+                    let index = (z * majorant_resolution[1] + y) * majorant_resolution[0] + x;
+                    grid.majorants[index] = majorant;
+                }
+            }
+        }
+
+        grid
+    }
+
+    fn voxel(&self, x: usize, y: usize, z: usize) -> f64 {
+        self.densities[(z * self.resolution[1] + y) * self.resolution[0] + x] as f64
+    }
+
+    // trilinearly interpolated density at a point in world space, zero outside of the grid
+    pub fn density(&self, point: Vec3) -> f64 {
+        let p = self.world_to_grid.point(point);
+        if p.x < 0.0 || p.y < 0.0 || p.z < 0.0 || p.x > 1.0 || p.y > 1.0 || p.z > 1.0 {
+            return 0.0;
+        }
+
+        // continuous voxel coordinates, voxel centers are at integer positions
+        let mut low = [0; 3];
+        let mut high = [0; 3];
+        let mut t = [0.0; 3];
+        for axis in 0..3 {
+            let n = self.resolution[axis];
+            let v = (p[axis] * n as f64 - 0.5).clamp(0.0, (n - 1) as f64);
+            low[axis] = v.floor() as usize;
+            high[axis] = (low[axis] + 1).min(n - 1);
+            t[axis] = v - low[axis] as f64;
+        }
+
+        let lerp = |a: f64, b: f64, t: f64| (1.0 - t) * a + t * b;
+        let along_x = |y, z| lerp(self.voxel(low[0], y, z), self.voxel(high[0], y, z), t[0]);
+        let along_y = |z| lerp(along_x(low[1], z), along_x(high[1], z), t[1]);
+        lerp(along_y(low[2]), along_y(high[2]), t[2])
+    }
+
+    // Splits the first t_max of the ray into the segments (t_start, t_end, majorant) crossing
+    // the cells of the majorant grid, in order. Parts outside of the grid have no density.
+    pub fn majorant_segments(&self, ray: &Ray, t_max: f64) -> Vec<(f64, f64, f64)> {
+        let origin = self.world_to_grid.point(ray.origin);
+        let dir = self.world_to_grid.vector(ray.dir);
+
+        // clip the ray against the unit cube
+        let mut t_enter = 0.0f64;
+        let mut t_exit = t_max;
+        for axis in 0..3 {
+            if dir[axis] == 0.0 {
+                if origin[axis] < 0.0 || origin[axis] > 1.0 {
+                    return vec![];
+                }
+                continue;
+            }
+            let t0 = -origin[axis] / dir[axis];
+            let t1 = (1.0 - origin[axis]) / dir[axis];
+            t_enter = t_enter.max(t0.min(t1));
+            t_exit = t_exit.min(t0.max(t1));
+        }
+        if t_enter >= t_exit {
+            return vec![];
+        }
+
+        // walk through the cells with a 3D DDA
+        let start = origin + dir * t_enter;
+        let mut cell = [0i64; 3];
+        let mut step = [0i64; 3];
+        let mut next_t = [f64::INFINITY; 3];
+        let mut delta_t = [f64::INFINITY; 3];
+        for axis in 0..3 {
+            let m = self.majorant_resolution[axis] as f64;
+            cell[axis] = ((start[axis] * m) as i64).clamp(0, self.majorant_resolution[axis] as i64 - 1);
+
+            if dir[axis] > 0.0 {
+                step[axis] = 1;
+                next_t[axis] = t_enter + ((cell[axis] + 1) as f64 / m - start[axis]) / dir[axis];
+                delta_t[axis] = 1.0 / (m * dir[axis]);
+            } else if dir[axis] < 0.0 {
+                step[axis] = -1;
+                next_t[axis] = t_enter + (cell[axis] as f64 / m - start[axis]) / dir[axis];
+                delta_t[axis] = -1.0 / (m * dir[axis]);
+            }
+        }
+
+        let mut segments = vec![];
+        let mut t = t_enter;
+        loop {
+            let axis = if next_t[0] < next_t[1] && next_t[0] < next_t[2] {
+                0
+            } else if next_t[1] < next_t[2] {
+                1
+            } else {
+                2
+            };
+
+            let index = (cell[2] as usize * self.majorant_resolution[1] + cell[1] as usize) * self.majorant_resolution[0] + cell[0] as usize;
+            let t_end = next_t[axis].min(t_exit);
+            segments.push((t, t_end, self.majorants[index]));
+
+            if t_end >= t_exit {
+                return segments;
+            }
+
+            t = t_end;
+            cell[axis] += step[axis];
+            if cell[axis] < 0 || cell[axis] >= self.majorant_resolution[axis] as i64 {
+                return segments;
+            }
+            next_t[axis] += delta_t[axis];
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_majorants_bound_density() {
+        let resolution = [20, 10, 5];
+        let densities = (0..20 * 10 * 5).map(|i| ((i * 7919) % 13) as f32).collect();
+        let grid_to_world = Transform::scale(Vec3::new(2.0, 1.0, 1.0)).then(&Transform::rotation(Vec3::new(0.0, 30.0, 0.0)));
+        let grid = DensityGrid::new(resolution, densities, grid_to_world);
+
+        let ray = Ray::new(Vec3::new(-1.0, 0.3, -0.5), Vec3::new(1.0, 0.1, 0.4).normalized());
+        let segments = grid.majorant_segments(&ray, 10.0);
+        assert!(!segments.is_empty());
+
+        for (t_start, t_end, majorant) in segments {
+            for i in 0..=10 {
+                let t = t_start + (t_end - t_start) * i as f64 / 10.0;
+                assert!(grid.density(ray.at(t)) <= majorant + 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Grid resolution should be at least 1")]
+    fn test_empty_resolution() {
+        DensityGrid::new([4, 0, 4], vec![], Transform::identity());
+    }
+
+    #[test]
+    #[should_panic(expected = "Grid box should have a size along every axis")]
+    fn test_flat_box() {
+        DensityGrid::new([2, 2, 2], vec![1.0; 8], Transform::scale(Vec3::new(1.0, 0.0, 1.0)));
+    }
+}
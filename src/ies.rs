@@ -0,0 +1,177 @@
+use std::fs;
+
+use crate::vec::Vec3;
+
+// Photometric profile read from an IESNA LM-63 file. Only type C photometry (by far the
+// most common one) is supported. Candela values are normalized so that the brightest
+// direction has intensity 1, which lets the profile modulate the color of an emitter.
+#[derive(Debug, Clone)]
+pub struct IesProfile {
+    vertical_angles: Vec<f64>,
+    horizontal_angles: Vec<f64>,
+    candela: Vec<Vec<f64>>, // indexed as [horizontal][vertical]
+}
+
+impl IesProfile {
+
+    pub fn load(path: &str) -> Result<IesProfile, String> {
+        let contents = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        IesProfile::parse(&contents)
+    }
+
+    // the profile, or what is wrong with the file
+    pub fn parse(contents: &str) -> Result<IesProfile, String> {
+        let mut lines = contents.lines();
+
+        // skip the header and keywords, everything of interest comes after the TILT line
+        let tilt = lines
+            .by_ref()
+            .map(|line| line.trim())
+            .find(|line| line.starts_with("TILT="))
+            .ok_or("the file has no TILT line")?;
+
+        let mut numbers = lines
+            .flat_map(|line| line.split(|c: char| c.is_whitespace() || c == ','))
+            .filter(|token| !token.is_empty())
+            .map(|token| token.parse::<f64>().map_err(|_| format!("invalid number {}", token)));
+
+        let mut next = || numbers.next().unwrap_or_else(|| Err("the file ended unexpectedly".to_string()));
+
+        // tilt data only matters for tilted lamps, skip it
+        if tilt == "TILT=INCLUDE" {
+            let _geometry = next()?;
+            let num_tilt_angles = next()? as usize;
+            for _ in 0..2 * num_tilt_angles {
+                next()?;
+            }
+        }
+
+        let _num_lamps = next()?;
+        let _lumens_per_lamp = next()?;
+        let multiplier = next()?;
+        let num_vertical = next()? as usize;
+        let num_horizontal = next()? as usize;
+        let photometric_type = next()? as u32;
+        let _units = next()?;
+        let _width = next()?;
+        let _length = next()?;
+        let _height = next()?;
+        let _ballast_factor = next()?;
+        let _future_use = next()?;
+        let _input_watts = next()?;
+
+        if photometric_type != 1 {
+            return Err("only type C profiles are supported".to_string());
+        }
+        if num_vertical == 0 || num_horizontal == 0 {
+            return Err("the profile has no angles".to_string());
+        }
+
+        let vertical_angles = (0..num_vertical).map(|_| next()).collect::<Result<Vec<f64>, String>>()?;
+        let horizontal_angles = (0..num_horizontal).map(|_| next()).collect::<Result<Vec<f64>, String>>()?;
+
+        let mut candela = (0..num_horizontal)
+            .map(|_| (0..num_vertical).map(|_| Ok(multiplier * next()?)).collect())
+            .collect::<Result<Vec<Vec<f64>>, String>>()?;
+
+        // normalize the intensities
+        let max = candela.iter().flatten().cloned().fold(0.0, f64::max);
+        if max > 0.0 {
+            for value in candela.iter_mut().flatten() {
+                *value /= max;
+            }
+        }
+
+        Ok(IesProfile { vertical_angles, horizontal_angles, candela })
+    }
+
+    // relative intensity in the direction dir, where axis is the direction the fixture points at
+    pub fn intensity(&self, axis: Vec3, dir: Vec3) -> f64 {
+        let axis = axis.normalized();
+        let dir = dir.normalized();
+        let (tangent, bitangent) = axis.orthonormal_basis();
+
+        let theta = Vec3::dot(&axis, &dir).clamp(-1.0, 1.0).acos().to_degrees();
+        let phi = Vec3::dot(&bitangent, &dir).atan2(Vec3::dot(&tangent, &dir)).to_degrees();
+
+        self.intensity_at(theta, phi.rem_euclid(360.0))
+    }
+
+    // relative intensity at a vertical angle theta and horizontal angle phi, both in degrees
+    fn intensity_at(&self, theta: f64, phi: f64) -> f64 {
+        let first_v = self.vertical_angles[0];
+        let last_v = self.vertical_angles[self.vertical_angles.len() - 1];
+        if theta < first_v || theta > last_v {
+            return 0.0;
+        }
+
+        // fold phi into the range covered by the file using its symmetry
+        let last_h = self.horizontal_angles[self.horizontal_angles.len() - 1];
+        let phi = if last_h == 0.0 {
+            0.0
+        } else if last_h <= 90.0 {
+            let p = if phi > 180.0 { 360.0 - phi } else { phi };
+            if p > 90.0 { 180.0 - p } else { p }
+        } else if last_h <= 180.0 {
+            if phi > 180.0 { 360.0 - phi } else { phi }
+        } else {
+            phi
+        };
+
+        let (h0, h1, ht) = interpolation_weights(&self.horizontal_angles, phi);
+        let (v0, v1, vt) = interpolation_weights(&self.vertical_angles, theta);
+
+        let lower = (1.0 - vt) * self.candela[h0][v0] + vt * self.candela[h0][v1];
+        let upper = (1.0 - vt) * self.candela[h1][v0] + vt * self.candela[h1][v1];
+        (1.0 - ht) * lower + ht * upper
+    }
+}
+
+// indices of the two angles surrounding x and the interpolation factor between them
+fn interpolation_weights(angles: &[f64], x: f64) -> (usize, usize, f64) {
+    let upper = angles.partition_point(|&a| a < x);
+
+    if upper == 0 {
+        (0, 0, 0.0)
+    } else if upper == angles.len() {
+        (angles.len() - 1, angles.len() - 1, 0.0)
+    } else {
+        let lower = upper - 1;
+        let t = (x - angles[lower]) / (angles[upper] - angles[lower]);
+        (lower, upper, t)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const PROFILE: &str = "IESNA:LM-63-2002
+[TEST] example
+[MANUFAC] rayo
+TILT=NONE
+1 1000 1 3 1 1 2 0 0 0
+1 1 100
+0 45 90
+0
+200 100 0
+";
+
+    #[test]
+    fn test_parse_ies() {
+        let profile = IesProfile::parse(PROFILE).unwrap();
+        let down = Vec3::new(0.0, -1.0, 0.0);
+
+        assert!((profile.intensity(down, down) - 1.0).abs() < 1e-9);
+        assert!((profile.intensity(down, Vec3::new(1.0, -1.0, 0.0)) - 0.5).abs() < 1e-9);
+        assert!(profile.intensity(down, Vec3::new(0.0, 1.0, 0.0)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_invalid_ies() {
+        assert_eq!(IesProfile::parse("IESNA:LM-63-2002").unwrap_err(), "the file has no TILT line");
+        assert_eq!(IesProfile::parse(&PROFILE.replace("200", "2x0")).unwrap_err(), "invalid number 2x0");
+        assert_eq!(IesProfile::parse(&PROFILE[..PROFILE.len() - 8]).unwrap_err(), "the file ended unexpectedly");
+        assert!(IesProfile::load("missing.ies").is_err());
+    }
+}
@@ -0,0 +1,128 @@
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::f64::consts::PI;
+
+use crate::cli::CliOptions;
+use crate::color::Color;
+use crate::intersection::{bvh::Aabb, object::Object, sphere::Sphere};
+use crate::material::{Emission, Material};
+use crate::texture::Texture;
+use crate::vec::Vec3;
+
+// Helpers shared by the importers of glTF, pbrt and Mitsuba scenes. Lights rayo does not have are
+// replaced by glowing spheres, sized by the scene
+
+// radius of the spheres standing in for point lights, relative to the size of the scene
+const POINT_LIGHT_RADIUS: f64 = 0.005;
+// distance of the spheres standing in for directional lights relative to the size of the scene,
+// and their radius relative to the distance
+const DIRECTIONAL_LIGHT_DISTANCE: f64 = 100.0;
+const DIRECTIONAL_LIGHT_RADIUS: f64 = 0.01;
+// radius of the sphere standing in for environment lights, relative to the size of the scene
+const ENVIRONMENT_RADIUS: f64 = 100.0;
+
+// Features of the imported file that are left out, each warning is only printed once
+#[derive(Default)]
+pub struct Warnings {
+    printed: RefCell<HashSet<String>>,
+}
+
+impl Warnings {
+    pub fn warn(&self, message: &str) {
+        if self.printed.borrow_mut().insert(message.to_string()) {
+            eprintln!("Warning: {}", message);
+        }
+    }
+
+    // wether a warning containing the text was printed
+    #[cfg(test)]
+    pub fn contains(&self, text: &str) -> bool {
+        self.printed.borrow().iter().any(|w| w.contains(text))
+    }
+}
+
+// center and size (the diagonal) of the bounds, the fallback with size one if they are empty
+pub fn extent(bounds: &Aabb, fallback: Vec3) -> (Vec3, f64) {
+    if bounds.min.x.is_finite() {
+        (bounds.centroid(), (bounds.max - bounds.min).norm().max(1e-3))
+    } else {
+        (fallback, 1.0)
+    }
+}
+
+// a small sphere at the position, the intensity is its radiance times its projected area
+pub fn point_light(position: Vec3, intensity: Color, size: f64) -> Object {
+    let radius = POINT_LIGHT_RADIUS * size;
+    light_sphere(position, radius, intensity / (PI * radius * radius))
+}
+
+// A sphere far away from the center, where the light comes from. direction is the one the light
+// travels in, the irradiance is the radiance of the sphere times its solid angle
+pub fn directional_light(center: Vec3, direction: Vec3, irradiance: Color, size: f64) -> Object {
+    let distance = DIRECTIONAL_LIGHT_DISTANCE * size;
+    let radiance = irradiance / (PI * DIRECTIONAL_LIGHT_RADIUS * DIRECTIONAL_LIGHT_RADIUS);
+    light_sphere(center - distance * direction.normalized(), DIRECTIONAL_LIGHT_RADIUS * distance, radiance)
+}
+
+// a sphere enclosing the scene that glows inwards, size should include the camera
+pub fn environment_light(center: Vec3, radiance: Color, size: f64) -> Object {
+    Object::environment(center, ENVIRONMENT_RADIUS * size, radiance)
+}
+
+fn light_sphere(center: Vec3, radius: f64, radiance: Color) -> Object {
+    let emission = Emission::new(Texture::Constant { color: radiance });
+    Object::Sphere(Sphere { center, radius, material: Material::DiffuseLight { emission }, medium: None })
+}
+
+// Renders at the resolution of the file unless the command line gives a width, which then keeps
+// the aspect ratio of the file
+pub fn apply_resolution(opts: &mut CliOptions, (x, y): (u32, u32)) {
+    opts.aspect_ratio = x as f64 / y as f64;
+    if !opts.custom_resolution {
+        opts.img_x = x;
+    }
+    opts.img_y = (opts.img_x as f64 / opts.aspect_ratio) as u32;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_light_spheres() {
+        // the intensity of a point light is kept
+        match point_light(Vec3::new(1.0, 2.0, 3.0), Color::new(2.0, 2.0, 2.0), 10.0) {
+            Object::Sphere(sphere) => {
+                assert!(sphere.center.is_close(&Vec3::new(1.0, 2.0, 3.0)));
+                let radiance = match &sphere.material {
+                    Material::DiffuseLight { emission: Emission { texture: Texture::Constant { color }, .. } } => *color,
+                    other => panic!("Expected a light, got {:?}", other)
+                };
+                assert!((radiance.r * PI * sphere.radius * sphere.radius - 2.0).abs() < 1e-9);
+            },
+            other => panic!("Expected a sphere, got {:?}", other)
+        }
+
+        // directional lights are far away, opposite to the direction the light travels in
+        match directional_light(Vec3::zero(), Vec3::new(0.0, -2.0, 0.0), Color::white(), 1.0) {
+            Object::Sphere(sphere) => assert!(sphere.center.is_close(&Vec3::new(0.0, DIRECTIONAL_LIGHT_DISTANCE, 0.0))),
+            other => panic!("Expected a sphere, got {:?}", other)
+        }
+
+        match environment_light(Vec3::zero(), Color::white(), 2.0) {
+            Object::Environment(sphere) => assert_eq!(sphere.radius, 2.0 * ENVIRONMENT_RADIUS),
+            other => panic!("Expected an environment, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn test_extent() {
+        let (center, size) = extent(&Aabb::around(&[Vec3::zero(), Vec3::new(2.0, 2.0, 1.0)]), Vec3::zero());
+        assert!(center.is_close(&Vec3::new(1.0, 1.0, 0.5)));
+        assert!((size - 3.0).abs() < 1e-9);
+
+        let (center, size) = extent(&Aabb::empty(), Vec3::new(0.0, 1.0, 0.0));
+        assert!(center.is_close(&Vec3::new(0.0, 1.0, 0.0)));
+        assert_eq!(size, 1.0);
+    }
+}
@@ -0,0 +1,478 @@
+use std::f64::consts::PI;
+
+use indicatif::ProgressBar;
+
+use crate::camera::Camera;
+use crate::color::Color;
+use crate::film::Film;
+use crate::intersection::{hitinfo::HitInfo, scene::Scene, Hittable};
+use crate::ray::Ray;
+use crate::sampler::{RandomSampler, Sampler};
+use crate::spectrum::{self, Wavelengths};
+use crate::vec::Vec3;
+use super::{camera_ray, for_each_pixel, Integrator};
+
+const EPSILON: f64 = 1e-4;
+
+// Bidirectional path tracer. For every camera sample a camera subpath and a light subpath
+// are traced, and every pair of their vertices is connected. The resulting strategies are
+// combined with multiple importance sampling (balance heuristic), following Veach's thesis
+// and the formulation of pbrt. Connections to the camera are splatted onto the film.
+pub struct BidirectionalPathTracer {
+    pub max_depth: u32,
+    pub spectral: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum VertexKind<'a> {
+    Camera,
+    Light(HitInfo<'a>),
+    Surface(HitInfo<'a>),
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Vertex<'a> {
+    kind: VertexKind<'a>,
+    point: Vec3,
+    // zero for the camera
+    normal: Vec3,
+    // direction towards the previous vertex of the subpath
+    wo: Vec3,
+    beta: Color,
+    delta: bool,
+    // area densities of sampling this vertex from the previous and from the next vertex
+    pdf_fwd: f64,
+    pdf_rev: f64,
+}
+
+impl<'a> Vertex<'a> {
+
+    fn camera(point: Vec3, beta: Color) -> Vertex<'a> {
+        Vertex {
+            kind: VertexKind::Camera,
+            point,
+            normal: Vec3::zero(),
+            wo: Vec3::zero(),
+            beta,
+            delta: false,
+            pdf_fwd: 0.0,
+            pdf_rev: 0.0,
+        }
+    }
+
+    // hit faces outwards, beta is the emitted radiance divided by the sampling density
+    fn light(hit: HitInfo<'a>, beta: Color, pdf_fwd: f64) -> Vertex<'a> {
+        Vertex {
+            kind: VertexKind::Light(hit),
+            point: hit.point,
+            normal: hit.normal,
+            wo: Vec3::zero(),
+            beta,
+            delta: false,
+            pdf_fwd,
+            pdf_rev: 0.0,
+        }
+    }
+
+    fn surface(hit: HitInfo<'a>, wo: Vec3, beta: Color) -> Vertex<'a> {
+        Vertex {
+            kind: VertexKind::Surface(hit),
+            point: hit.point,
+            normal: hit.normal,
+            wo,
+            beta,
+            delta: false,
+            pdf_fwd: 0.0,
+            pdf_rev: 0.0,
+        }
+    }
+
+    fn is_on_surface(&self) -> bool {
+        !matches!(self.kind, VertexKind::Camera)
+    }
+
+    // wavelengths the subpath through this vertex was traced at, none in RGB mode
+    fn wavelengths(&self) -> Option<Wavelengths> {
+        match self.kind {
+            VertexKind::Surface(hit) | VertexKind::Light(hit) => hit.wavelengths,
+            VertexKind::Camera => None
+        }
+    }
+
+    fn is_connectible(&self) -> bool {
+        match self.kind {
+            VertexKind::Surface(hit) => !hit.material.is_specular(),
+            _ => true
+        }
+    }
+
+    // bsdf times cosine for light going from next to the previous vertex
+    fn f(&self, next: &Vertex) -> Color {
+        match self.kind {
+            VertexKind::Surface(hit) => hit.material.eval(&hit, self.wo, next.point - self.point),
+            _ => Color::black()
+        }
+    }
+
+    // radiance emitted towards the given vertex, lights only emit from their outside
+    fn le(&self, towards: &Vertex) -> Color {
+        match self.kind {
+            VertexKind::Surface(hit) | VertexKind::Light(hit) => {
+                let dir = (towards.point - self.point).normalized();
+                if !hit.front_face || Vec3::dot(&dir, &hit.normal) <= 0.0 {
+                    return Color::black();
+                }
+                hit.material.emmit(&Ray::new(towards.point, -dir), &hit)
+            },
+            VertexKind::Camera => Color::black()
+        }
+    }
+
+    // turns a solid angle density at this vertex into an area density at next
+    fn convert_density(&self, pdf: f64, next: &Vertex) -> f64 {
+        let w = next.point - self.point;
+        let dist_squared = w.norm_sqared();
+        let mut pdf = pdf / dist_squared;
+        if next.is_on_surface() {
+            pdf *= Vec3::dot(&next.normal, &(w / dist_squared.sqrt())).abs();
+        }
+        pdf
+    }
+
+    // area density of sampling next from this vertex, having arrived from prev
+    fn pdf(&self, camera: &Camera, prev: Option<&Vertex>, next: &Vertex) -> f64 {
+        let wn = next.point - self.point;
+
+        let pdf = match self.kind {
+            VertexKind::Light(_) => return self.pdf_light(next),
+            VertexKind::Camera => camera.pdf_dir(self.point, wn),
+            VertexKind::Surface(hit) => {
+                let wp = prev.map_or(self.wo, |prev| prev.point - self.point);
+                hit.material.pdf(&hit, wp, wn)
+            }
+        };
+
+        self.convert_density(pdf, next)
+    }
+
+    // area density of a light leaving this (emitting) vertex reaching next
+    fn pdf_light(&self, next: &Vertex) -> f64 {
+        let w = next.point - self.point;
+        let dist_squared = w.norm_sqared();
+        let w = w / dist_squared.sqrt();
+
+        // lights emit cosine weighted
+        let pdf_dir = Vec3::dot(&self.normal, &w).max(0.0) / PI;
+        let mut pdf = pdf_dir / dist_squared;
+        if next.is_on_surface() {
+            pdf *= Vec3::dot(&next.normal, &w).abs();
+        }
+        pdf
+    }
+
+    // area density of a light subpath starting at this (emitting) vertex
+    fn pdf_light_origin(&self, scene: &Scene) -> f64 {
+        match self.kind {
+            VertexKind::Surface(hit) | VertexKind::Light(hit) => scene.light_pdf(hit.object_id),
+            VertexKind::Camera => 0.0
+        }
+    }
+}
+
+impl Integrator for BidirectionalPathTracer {
+    fn render(&self, scene: &Scene, camera: &Camera, film: &Film, num_samples: u32, bar: &ProgressBar) {
+        for_each_pixel(film, bar, |x, y| {
+            let mut sampler = RandomSampler::new();
+            let mut color = Color::black();
+
+            for _ in 0..num_samples {
+                color += self.sample(scene, camera, film, x, y, &mut sampler);
+            }
+
+            film.add(x, y, color);
+        });
+    }
+}
+
+impl BidirectionalPathTracer {
+
+    // traces one pair of subpaths, returns the radiance for pixel (x, y) and splats the rest
+    fn sample(&self, scene: &Scene, camera: &Camera, film: &Film, x: u32, y: u32, sampler: &mut dyn Sampler) -> Color {
+        let max_depth = self.max_depth as usize;
+
+        // both subpaths carry the same wavelengths, so they can be connected
+        let wavelengths = if self.spectral { Some(Wavelengths::sample(sampler)) } else { None };
+
+        let ray = Ray { wavelengths, ..camera_ray(camera, film, x, y, sampler) };
+        let camera_path = camera_subpath(scene, camera, ray, max_depth + 2, sampler);
+        let light_path = light_subpath(scene, max_depth + 1, wavelengths, sampler);
+
+        let mut radiance = Color::black();
+
+        for t in 1..=camera_path.len() {
+            for s in 0..=light_path.len() {
+                let depth = s as i64 + t as i64 - 2;
+                if (s == 1 && t == 1) || depth < 0 || depth > max_depth as i64 {
+                    continue;
+                }
+
+                let (contribution, film_position) = connect(scene, camera, &light_path, &camera_path, s, t, sampler);
+
+                if contribution.r.is_nan() || contribution.g.is_nan() || contribution.b.is_nan() {
+                    continue;
+                }
+
+                match film_position {
+                    Some((fs, ft)) => film.splat(fs, ft, spectrum::to_rgb(wavelengths, contribution)),
+                    None => radiance += contribution
+                }
+            }
+        }
+
+        spectrum::to_rgb(wavelengths, radiance)
+    }
+}
+
+fn camera_subpath<'a>(scene: &'a Scene, camera: &Camera, ray: Ray, max_vertices: usize, sampler: &mut dyn Sampler) -> Vec<Vertex<'a>> {
+    let mut path = vec![Vertex::camera(ray.origin, Color::white())];
+    let pdf_dir = camera.pdf_dir(ray.origin, ray.dir);
+    random_walk(scene, ray, Color::white(), pdf_dir, max_vertices, &mut path, sampler);
+    path
+}
+
+fn light_subpath<'a>(scene: &'a Scene, max_vertices: usize, wavelengths: Option<Wavelengths>, sampler: &mut dyn Sampler) -> Vec<Vertex<'a>> {
+    let sample = match scene.sample_light(sampler) {
+        Some(sample) => sample,
+        None => return vec![]
+    };
+
+    let hit = HitInfo { wavelengths, ..sample.hit };
+    let dir = Vec3::random_cosine_direction(&hit.normal, sampler);
+    let pdf_dir = Vec3::dot(&dir, &hit.normal) / PI;
+
+    let le = hit.material.emmit(&Ray::new(hit.point + dir, -dir), &hit);
+
+    let mut path = vec![Vertex::light(hit, le / sample.pdf, sample.pdf)];
+    let beta = le * (Vec3::dot(&dir, &hit.normal) / (sample.pdf * pdf_dir));
+    random_walk(scene, hit.spawn_ray(dir), beta, pdf_dir, max_vertices, &mut path, sampler);
+    path
+}
+
+// extends the path by sampling the materials, pdf is the solid angle density of the first ray
+fn random_walk<'a>(scene: &'a Scene, ray: Ray, beta: Color, pdf: f64, max_vertices: usize, path: &mut Vec<Vertex<'a>>, sampler: &mut dyn Sampler) {
+    let mut ray = Ray { dir: ray.dir.normalized(), ..ray };
+    let mut beta = beta;
+    let mut pdf_fwd = pdf;
+
+    while path.len() < max_vertices {
+        let hit = match scene.intersect(&ray, EPSILON, f64::MAX) {
+            Some(hit) => hit,
+            None => break
+        };
+
+        let prev = path.len() - 1;
+        let mut vertex = Vertex::surface(hit, -ray.dir, beta);
+        vertex.pdf_fwd = path[prev].convert_density(pdf_fwd, &vertex);
+        path.push(vertex);
+
+        if path.len() >= max_vertices {
+            break;
+        }
+
+        let record = match hit.material.scatter(&ray, hit, sampler) {
+            Some(record) => record,
+            None => break
+        };
+
+        let wi = record.ray.dir.normalized();
+        beta = beta * record.attenuation;
+
+        // density of sampling the path in the opposite direction
+        let current = path.len() - 1;
+        let pdf_rev = if record.specular {
+            path[current].delta = true;
+            pdf_fwd = 0.0;
+            0.0
+        } else {
+            pdf_fwd = record.pdf;
+            hit.material.pdf(&hit, wi, -ray.dir)
+        };
+        path[prev].pdf_rev = path[current].convert_density(pdf_rev, &path[prev]);
+
+        ray = Ray { dir: wi, ..record.ray };
+    }
+}
+
+// contribution of the strategy using s light and t camera vertices, weighted by MIS.
+// If the camera subpath is resampled (t = 1) the film position to splat to is returned as well
+fn connect(scene: &Scene, camera: &Camera, light_path: &[Vertex], camera_path: &[Vertex], s: usize, t: usize, sampler: &mut dyn Sampler) -> (Color, Option<(f64, f64)>) {
+    let mut sampled = None;
+    let mut film_position = None;
+
+    let radiance = if s == 0 {
+        // the camera subpath hit a light by itself
+        let pt = &camera_path[t - 1];
+        pt.le(&camera_path[t - 2]) * pt.beta
+    } else if t == 1 {
+        // connect the light subpath to the camera
+        let qs = &light_path[s - 1];
+        if !qs.is_connectible() {
+            return (Color::black(), None);
+        }
+
+        let lens = match camera.sample_lens(qs.point, sampler) {
+            Some(lens) if lens.importance > 0.0 => lens,
+            _ => return (Color::black(), None)
+        };
+
+        let vertex = Vertex::camera(lens.point, Color::white() * (lens.importance / lens.pdf));
+        let radiance = qs.beta * qs.f(&vertex) * vertex.beta;
+
+        if radiance.near_zero() || !visible(scene, qs.point, lens.point) {
+            return (Color::black(), None);
+        }
+
+        sampled = Some(vertex);
+        film_position = Some((lens.s, lens.t));
+        radiance
+    } else if s == 1 {
+        // connect the camera subpath to a freshly sampled point on a light
+        let pt = &camera_path[t - 1];
+        if !pt.is_connectible() {
+            return (Color::black(), None);
+        }
+
+        let sample = match scene.sample_light(sampler) {
+            Some(sample) => sample,
+            None => return (Color::black(), None)
+        };
+
+        let light = Vertex::light(HitInfo { wavelengths: pt.wavelengths(), ..sample.hit }, Color::black(), sample.pdf);
+        let le = light.le(pt);
+
+        // solid angle density of the light sample as seen from pt
+        let to_pt = pt.point - light.point;
+        let cosine = Vec3::dot(&light.normal, &to_pt.normalized());
+        if cosine <= 0.0 {
+            return (Color::black(), None);
+        }
+        let pdf = sample.pdf * to_pt.norm_sqared() / cosine;
+
+        let light = Vertex { beta: le / pdf, ..light };
+        let radiance = pt.beta * pt.f(&light) * light.beta;
+
+        if radiance.near_zero() || !visible(scene, pt.point, light.point) {
+            return (Color::black(), None);
+        }
+
+        sampled = Some(light);
+        radiance
+    } else {
+        // connect the two subpaths
+        let qs = &light_path[s - 1];
+        let pt = &camera_path[t - 1];
+        if !qs.is_connectible() || !pt.is_connectible() {
+            return (Color::black(), None);
+        }
+
+        // the cosines are part of f
+        let mut radiance = qs.beta * qs.f(pt) * pt.f(qs) * pt.beta / (qs.point - pt.point).norm_sqared();
+
+        // when both subpaths went through dispersion the loss of the secondary wavelengths was made up for twice
+        let terminated = |v: &Vertex| v.wavelengths().is_some_and(|w| w.is_terminated());
+        if terminated(qs) && terminated(pt) {
+            radiance /= qs.wavelengths().unwrap().lambda.len() as f64;
+        }
+
+        if radiance.near_zero() || !visible(scene, qs.point, pt.point) {
+            return (Color::black(), None);
+        }
+
+        radiance
+    };
+
+    if radiance.near_zero() {
+        return (Color::black(), None);
+    }
+
+    let weight = mis_weight(scene, camera, light_path, camera_path, sampled, s, t);
+    (radiance * weight, film_position)
+}
+
+// balance heuristic weight of the strategy using s light and t camera vertices
+fn mis_weight(scene: &Scene, camera: &Camera, light_path: &[Vertex], camera_path: &[Vertex], sampled: Option<Vertex>, s: usize, t: usize) -> f64 {
+    if s + t == 2 {
+        return 1.0;
+    }
+
+    // work on copies of the vertices used by this strategy
+    let mut light = light_path[..s].to_vec();
+    let mut cam = camera_path[..t].to_vec();
+
+    if let Some(vertex) = sampled {
+        if s == 1 {
+            light[0] = vertex;
+        } else if t == 1 {
+            cam[0] = vertex;
+        }
+    }
+
+    // the connection vertices are not degenerate
+    cam[t - 1].delta = false;
+    if s > 0 {
+        light[s - 1].delta = false;
+    }
+
+    // update the reverse densities of the vertices around the connection
+    cam[t - 1].pdf_rev = if s > 0 {
+        light[s - 1].pdf(camera, if s > 1 { Some(&light[s - 2]) } else { None }, &cam[t - 1])
+    } else {
+        cam[t - 1].pdf_light_origin(scene)
+    };
+
+    if t > 1 {
+        cam[t - 2].pdf_rev = if s > 0 {
+            cam[t - 1].pdf(camera, Some(&light[s - 1]), &cam[t - 2])
+        } else {
+            cam[t - 1].pdf_light(&cam[t - 2])
+        };
+    }
+
+    if s > 0 {
+        light[s - 1].pdf_rev = cam[t - 1].pdf(camera, if t > 1 { Some(&cam[t - 2]) } else { None }, &light[s - 1]);
+    }
+
+    if s > 1 {
+        light[s - 2].pdf_rev = light[s - 1].pdf(camera, Some(&cam[t - 1]), &light[s - 2]);
+    }
+
+    // sum the relative densities of all other strategies that could have produced this path
+    let remap = |pdf: f64| if pdf != 0.0 { pdf } else { 1.0 };
+    let mut sum = 0.0;
+
+    let mut ratio = 1.0;
+    for i in (1..t).rev() {
+        ratio *= remap(cam[i].pdf_rev) / remap(cam[i].pdf_fwd);
+        if !cam[i].delta && !cam[i - 1].delta {
+            sum += ratio;
+        }
+    }
+
+    let mut ratio = 1.0;
+    for i in (0..s).rev() {
+        ratio *= remap(light[i].pdf_rev) / remap(light[i].pdf_fwd);
+        let delta_before = i > 0 && light[i - 1].delta;
+        if !light[i].delta && !delta_before {
+            sum += ratio;
+        }
+    }
+
+    1.0 / (1.0 + sum)
+}
+
+fn visible(scene: &Scene, from: Vec3, to: Vec3) -> bool {
+    let dir = to - from;
+    let dist = dir.norm();
+    let shadow_ray = Ray::new(from, dir / dist);
+    scene.intersect(&shadow_ray, EPSILON, dist - EPSILON).is_none()
+}
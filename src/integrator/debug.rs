@@ -0,0 +1,113 @@
+use crate::color::Color;
+use crate::intersection::{scene::Scene, Hittable};
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::vec::Vec3;
+use super::RayIntegrator;
+
+pub enum DebugMode {
+    Normals,
+    Albedo,
+    // hit distance, mapped linearly from black (at the camera) to white (at far)
+    Depth { far: f64 },
+    Uv,
+    ObjectId,
+    AmbientOcclusion { radius: f64 },
+}
+
+// Cheap integrators that visualize a single property of the first hit
+pub struct DebugIntegrator {
+    pub mode: DebugMode,
+}
+
+impl RayIntegrator for DebugIntegrator {
+    fn radiance(&self, ray: &Ray, scene: &Scene, sampler: &mut dyn Sampler) -> Color {
+
+        let hit = match scene.intersect(ray, 0.1, f64::MAX) {
+            Some(hit) => hit,
+            None => return Color::black()
+        };
+
+        match self.mode {
+            DebugMode::Normals => {
+                let n = hit.normal;
+                Color::new(0.5 * (n.x + 1.0), 0.5 * (n.y + 1.0), 0.5 * (n.z + 1.0))
+            },
+            DebugMode::Albedo => hit.material.albedo(&hit),
+            DebugMode::Depth { far } => {
+                let depth = (hit.t * ray.dir.norm() / far).min(1.0);
+                Color::new(depth, depth, depth)
+            },
+            DebugMode::Uv => Color::new(hit.u, hit.v, 0.0),
+            DebugMode::ObjectId => id_color(hit.object_id),
+            DebugMode::AmbientOcclusion { radius } => {
+                let dir = Vec3::random_cosine_direction(&hit.normal, sampler);
+                let occlusion_ray = Ray::new(hit.point, dir);
+
+                // dir is normalized so t is the distance to the occluder
+                match scene.intersect(&occlusion_ray, 1e-4, radius) {
+                    Some(_) => Color::black(),
+                    None => Color::white()
+                }
+            }
+        }
+    }
+}
+
+// a distinct color for every id, spreading the hues with the golden ratio
+fn id_color(id: usize) -> Color {
+    let hue = (id as f64 * 0.618_033_988_75).fract() * 6.0;
+    let x = 1.0 - (hue % 2.0 - 1.0).abs();
+
+    match hue as u32 {
+        0 => Color::new(1.0, x, 0.0),
+        1 => Color::new(x, 1.0, 0.0),
+        2 => Color::new(0.0, 1.0, x),
+        3 => Color::new(0.0, x, 1.0),
+        4 => Color::new(x, 0.0, 1.0),
+        _ => Color::new(1.0, 0.0, x),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::intersection::{object::Object, sphere::Sphere};
+    use crate::material::Material;
+    use crate::sampler::RandomSampler;
+    use crate::texture::Texture;
+
+    #[test]
+    fn test_debug_modes() {
+        // a unit sphere seen straight from the front, 4 units away
+        let material = Material::Lambertian { albedo: Texture::Constant { color: Color::new(0.2, 0.4, 0.6) } };
+        let scene = Scene::new(vec![Object::Sphere(Sphere { center: Vec3::zero(), radius: 1.0, material, medium: None })]);
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -2.0));
+        let radiance = |mode: DebugMode| DebugIntegrator { mode }.radiance(&ray, &scene, &mut RandomSampler::new());
+
+        // the normal points back at the camera, along z
+        assert!(radiance(DebugMode::Normals).is_close(&Color::new(0.5, 0.5, 1.0)));
+        assert!(radiance(DebugMode::Albedo).is_close(&Color::new(0.2, 0.4, 0.6)));
+        // the depth is a distance, it does not depend on the length of the ray direction
+        assert!(radiance(DebugMode::Depth { far: 8.0 }).is_close(&Color::new(0.5, 0.5, 0.5)));
+        assert!(radiance(DebugMode::Depth { far: 2.0 }).is_close(&Color::white()));
+        // a quarter of the way around the sphere, halfway up
+        assert!(radiance(DebugMode::Uv).is_close(&Color::new(0.25, 0.5, 0.0)));
+        assert!(radiance(DebugMode::ObjectId).is_close(&Color::new(1.0, 0.0, 0.0)));
+        // nothing can block the view of the sky from a lone sphere
+        assert!(radiance(DebugMode::AmbientOcclusion { radius: 10.0 }).is_close(&Color::white()));
+
+        // rays that miss are black
+        let miss = Ray::new(Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 1.0, 0.0));
+        assert!(DebugIntegrator { mode: DebugMode::Normals }.radiance(&miss, &scene, &mut RandomSampler::new()).is_close(&Color::black()));
+    }
+
+    #[test]
+    fn test_id_colors() {
+        // neighbouring ids get clearly different colors
+        for id in 0..20 {
+            let (a, b) = (id_color(id), id_color(id + 1));
+            assert!((a.r - b.r).abs() + (a.g - b.g).abs() + (a.b - b.b).abs() > 0.3);
+        }
+    }
+}
@@ -0,0 +1,254 @@
+use indicatif::ProgressBar;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use rayon::iter::*;
+
+use crate::camera::Camera;
+use crate::color::Color;
+use crate::film::Film;
+use crate::intersection::scene::Scene;
+use crate::sampler::Sampler;
+use crate::spectrum::{self, Wavelengths};
+use super::{path::PathTracer, Integrator, RayIntegrator};
+
+// Primary sample space Metropolis light transport (Kelemen et al. 2002). Paths are produced by
+// the path tracer, and a Markov chain explores the random numbers that drive it, mutating them
+// slightly (small steps) or replacing them entirely (large steps). The chains are started from
+// a set of bootstrap paths, which also estimate the overall image brightness.
+pub struct Pssmlt {
+    pub path_tracer: PathTracer,
+    pub bootstrap_samples: u32,
+    pub chains: u32,
+    pub large_step_probability: f64,
+}
+
+impl Integrator for Pssmlt {
+    fn render(&self, scene: &Scene, camera: &Camera, film: &Film, num_samples: u32, bar: &ProgressBar) {
+
+        // bootstrap, sample independent paths to normalize the image and seed the chains
+        let bootstrap_weights: Vec<f64> = (0..self.bootstrap_samples as u64)
+            .into_par_iter()
+            .map(|seed| {
+                let mut sampler = PssSampler::new(seed, self.large_step_probability);
+                self.sample(scene, camera, &mut sampler).2.luminance().max(0.0)
+            })
+            .collect();
+
+        let total_weight: f64 = bootstrap_weights.iter().sum();
+        let pixels = (film.width * film.height) as u64;
+
+        // nothing is visible, leave the film black
+        if total_weight <= 0.0 {
+            bar.inc(pixels);
+            return;
+        }
+
+        let brightness = total_weight / self.bootstrap_samples as f64;
+
+        let mut cdf = Vec::with_capacity(bootstrap_weights.len());
+        let mut accumulated = 0.0;
+        for weight in &bootstrap_weights {
+            accumulated += weight;
+            cdf.push(accumulated / total_weight);
+        }
+
+        // the film divides by num_samples, so splats carry the brightness of the image
+        let chains = self.chains as u64;
+        let mutations = num_samples as u64 * pixels;
+
+        (0..chains).into_par_iter().for_each(|chain| {
+            let chain_mutations = mutations * (chain + 1) / chains - mutations * chain / chains;
+
+            // pick the bootstrap path to start from proportional to its contribution
+            let mut rng = StdRng::seed_from_u64(self.bootstrap_samples as u64 + chain);
+            let u: f64 = rng.gen();
+            let seed = cdf.partition_point(|&c| c < u).min(cdf.len() - 1) as u64;
+
+            // recreate the chosen bootstrap path, then give the chain its own random numbers so
+            // chains starting from the same path do not make the same mutations
+            let mut sampler = PssSampler::new(seed, self.large_step_probability);
+            let mut current = self.sample(scene, camera, &mut sampler);
+            sampler.reseed(rng.gen());
+
+            for _ in 0..chain_mutations {
+                sampler.start_iteration();
+                let proposed = self.sample(scene, camera, &mut sampler);
+
+                let current_luminance = current.2.luminance();
+                let proposed_luminance = proposed.2.luminance();
+                let accept = if proposed_luminance > 0.0 {
+                    (proposed_luminance / current_luminance).min(1.0)
+                } else {
+                    0.0
+                };
+
+                // expected values, both states contribute according to the acceptance probability
+                if accept > 0.0 {
+                    film.splat(proposed.0, proposed.1, proposed.2 * (accept * brightness / proposed_luminance));
+                }
+                if accept < 1.0 {
+                    film.splat(current.0, current.1, current.2 * ((1.0 - accept) * brightness / current_luminance));
+                }
+
+                if rng.gen::<f64>() < accept {
+                    current = proposed;
+                    sampler.accept();
+                } else {
+                    sampler.reject();
+                }
+            }
+
+            bar.inc(pixels * (chain + 1) / chains - pixels * chain / chains);
+        });
+    }
+}
+
+impl Pssmlt {
+
+    // traces a path from the film position given by the first two primary samples, the
+    // wavelengths of spectral paths are primary samples as well so they get mutated too
+    fn sample(&self, scene: &Scene, camera: &Camera, sampler: &mut PssSampler) -> (f64, f64, Color) {
+        let s = sampler.next_f64();
+        let t = sampler.next_f64();
+        let mut ray = camera.generate_ray(s, t, sampler);
+        if self.path_tracer.spectral {
+            ray.wavelengths = Some(Wavelengths::sample(sampler));
+        }
+        (s, t, spectrum::to_rgb(ray.wavelengths, self.path_tracer.radiance(&ray, scene, sampler)))
+    }
+}
+
+#[derive(Clone, Copy)]
+struct PrimarySample {
+    value: f64,
+    // iteration in which the value was last changed
+    last_modification: u64,
+    // state before the current iteration, restored if the mutation is rejected
+    value_backup: f64,
+    modification_backup: u64,
+}
+
+// Sampler whose numbers are mutated between iterations. Samples are created and brought up to
+// date lazily, since paths of different lengths consume different amounts of numbers.
+struct PssSampler {
+    rng: StdRng,
+    large_step_probability: f64,
+    samples: Vec<PrimarySample>,
+    index: usize,
+    iteration: u64,
+    large_step: bool,
+    last_large_step: u64,
+}
+
+impl PssSampler {
+
+    // the first iteration is a large step, so all samples are drawn from the seeded rng
+    fn new(seed: u64, large_step_probability: f64) -> PssSampler {
+        PssSampler {
+            rng: StdRng::seed_from_u64(seed),
+            large_step_probability,
+            samples: vec![],
+            index: 0,
+            iteration: 0,
+            large_step: true,
+            last_large_step: 0,
+        }
+    }
+
+    // keeps the samples but draws the following mutations from a new sequence
+    fn reseed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    fn start_iteration(&mut self) {
+        self.iteration += 1;
+        self.large_step = self.rng.gen::<f64>() < self.large_step_probability;
+        self.index = 0;
+    }
+
+    fn accept(&mut self) {
+        if self.large_step {
+            self.last_large_step = self.iteration;
+        }
+    }
+
+    fn reject(&mut self) {
+        for sample in self.samples.iter_mut() {
+            if sample.last_modification == self.iteration {
+                sample.value = sample.value_backup;
+                sample.last_modification = sample.modification_backup;
+            }
+        }
+        self.iteration -= 1;
+    }
+
+    // applies the mutations that happened since the sample was last used
+    fn ensure_ready(&mut self, index: usize) {
+        if index >= self.samples.len() {
+            self.samples.resize(index + 1, PrimarySample { value: 0.0, last_modification: 0, value_backup: 0.0, modification_backup: 0 });
+        }
+
+        let sample = &mut self.samples[index];
+
+        // a large step happened in between, start from a fresh value
+        if sample.last_modification < self.last_large_step {
+            sample.value = self.rng.gen();
+            sample.last_modification = self.last_large_step;
+        }
+
+        sample.value_backup = sample.value;
+        sample.modification_backup = sample.last_modification;
+
+        if self.large_step {
+            sample.value = self.rng.gen();
+        } else {
+            for _ in sample.last_modification..self.iteration {
+                sample.value = mutate(sample.value, self.rng.gen(), self.rng.gen());
+            }
+        }
+
+        sample.last_modification = self.iteration;
+    }
+}
+
+impl Sampler for PssSampler {
+    fn next_f64(&mut self) -> f64 {
+        self.ensure_ready(self.index);
+        self.index += 1;
+        self.samples[self.index - 1].value
+    }
+}
+
+// Kelemen's small step, an exponentially distributed perturbation between 1/1024 and 1/64
+fn mutate(value: f64, u1: f64, u2: f64) -> f64 {
+    let s1: f64 = 1.0 / 1024.0;
+    let s2: f64 = 1.0 / 64.0;
+    let dv = s2 * (-(s2 / s1).ln() * u1).exp();
+
+    let mutated = if u2 < 0.5 { value + dv } else { value - dv };
+    mutated - mutated.floor()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_reseeded_chains() {
+        // two chains starting from the same bootstrap path
+        let chain = |seed: u64| {
+            let mut sampler = PssSampler::new(7, 0.0);
+            let start: Vec<f64> = (0..4).map(|_| sampler.next_f64()).collect();
+            sampler.reseed(seed);
+            sampler.start_iteration();
+            let proposal: Vec<f64> = (0..4).map(|_| sampler.next_f64()).collect();
+            (start, proposal)
+        };
+        let (start, proposal) = chain(1);
+        let (other_start, other_proposal) = chain(2);
+
+        // they recreate the same path but propose different mutations of it
+        assert_eq!(start, other_start);
+        assert_ne!(proposal, other_proposal);
+        assert_ne!(proposal, start);
+    }
+}
@@ -0,0 +1,68 @@
+use indicatif::ProgressBar;
+use rayon::iter::*;
+
+use crate::camera::Camera;
+use crate::color::Color;
+use crate::film::Film;
+use crate::intersection::scene::Scene;
+use crate::ray::Ray;
+use crate::sampler::{RandomSampler, Sampler};
+use crate::spectrum::{self, Wavelengths};
+
+pub mod bdpt;
+pub mod debug;
+pub mod mlt;
+pub mod path;
+pub mod ppm;
+
+// An integrator renders the scene onto the film, taking num_samples samples per pixel
+pub trait Integrator: Sync {
+    fn render(&self, scene: &Scene, camera: &Camera, film: &Film, num_samples: u32, bar: &ProgressBar);
+}
+
+// An integrator that computes the radiance arriving along every camera ray independently
+pub trait RayIntegrator: Sync {
+    fn radiance(&self, ray: &Ray, scene: &Scene, sampler: &mut dyn Sampler) -> Color;
+
+    // whether camera rays should carry wavelengths, the radiance is then spectral
+    fn spectral(&self) -> bool {
+        false
+    }
+}
+
+impl<T: RayIntegrator> Integrator for T {
+    fn render(&self, scene: &Scene, camera: &Camera, film: &Film, num_samples: u32, bar: &ProgressBar) {
+        for_each_pixel(film, bar, |x, y| {
+            let mut sampler = RandomSampler::new();
+
+            // start with a black color
+            let mut color = Color::black();
+
+            // sample several times
+            for _ in 0..num_samples {
+                let mut ray = camera_ray(camera, film, x, y, &mut sampler);
+                if self.spectral() {
+                    ray.wavelengths = Some(Wavelengths::sample(&mut sampler));
+                }
+                color += spectrum::to_rgb(ray.wavelengths, self.radiance(&ray, scene, &mut sampler));
+            }
+
+            film.add(x, y, color);
+        });
+    }
+}
+
+// calls f for every pixel of the film in parallel, advancing the progressbar after every pixel
+pub fn for_each_pixel<F: Fn(u32, u32) + Sync>(film: &Film, bar: &ProgressBar, f: F) {
+    (0..film.width * film.height).into_par_iter().for_each(|i| {
+        f(i % film.width, i / film.width);
+        bar.inc(1);
+    });
+}
+
+// camera ray through a random point of pixel (x, y)
+pub fn camera_ray(camera: &Camera, film: &Film, x: u32, y: u32, sampler: &mut dyn Sampler) -> Ray {
+    let s = ((x as f64) + sampler.next_f64()) / film.width as f64;
+    let t = ((y as f64) + sampler.next_f64()) / film.height as f64;
+    camera.generate_ray(s, t, sampler)
+}
@@ -0,0 +1,244 @@
+use crate::color::Color;
+use crate::intersection::{hitinfo::HitInfo, scene::Scene, Hittable};
+use crate::medium::{Medium, MediumEvent};
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::spectrum::Wavelengths;
+use crate::vec::Vec3;
+use super::RayIntegrator;
+
+const EPSILON: f64 = 1e-4;
+
+// Unidirectional volumetric path tracer, paths are extended by sampling the materials and
+// the media they pass through, and terminated with russian roulette once they are longer
+// than rr_depth. At scattering events inside a medium the lights are sampled directly.
+pub struct PathTracer {
+    pub max_depth: u32,
+    pub rr_depth: u32,
+    pub spectral: bool,
+}
+
+impl RayIntegrator for PathTracer {
+    fn spectral(&self) -> bool {
+        self.spectral
+    }
+
+    fn radiance(&self, ray: &Ray, scene: &Scene, sampler: &mut dyn Sampler) -> Color {
+
+        let mut radiance = Color::black();
+        let mut throughput = Color::white();
+        let mut ray = Ray { dir: ray.dir.normalized(), ..*ray };
+
+        // the camera is assumed to be outside of all objects
+        let mut medium = scene.medium.as_ref();
+
+        // origin and density of the last direction sampled from a phase function, which is
+        // needed to weight emission found by it against the one found by sampling the lights
+        let mut phase_sample: Option<(Vec3, f64)> = None;
+
+        let mut depth = 0;
+        while depth <= self.max_depth {
+
+            let hit = scene.intersect(&ray, EPSILON, f64::MAX);
+
+            // the medium might scatter the ray before it reaches the next surface
+            let mut medium_scatter = None;
+            if let Some(current) = medium {
+                let t_max = hit.map_or(f64::INFINITY, |hit| hit.t);
+                match current.sample_interaction(&ray, t_max, sampler) {
+                    MediumEvent::Scatter { point, weight } => {
+                        throughput = throughput * weight;
+                        medium_scatter = Some((current, point));
+                    },
+                    MediumEvent::Absorb => break,
+                    MediumEvent::Pass { weight } => throughput = throughput * weight
+                }
+            }
+
+            if let Some((current, point)) = medium_scatter {
+                radiance += throughput * sample_light(scene, current, point, &ray, sampler);
+
+                // the phase function is sampled exactly, so the throughput does not change
+                let dir = current.sample_phase(ray.dir, sampler);
+                phase_sample = Some((point, current.phase(ray.dir, dir)));
+                ray = Ray { origin: point, dir, ..ray };
+            } else {
+
+                // if no hit no more light is gathered
+                let hit = match hit {
+                    Some(hit) => hit,
+                    None => break
+                };
+
+                // gather emission from hit
+                let emitted = hit.material.emmit(&ray, &hit);
+                if !emitted.near_zero() {
+                    let weight = match phase_sample {
+                        Some((origin, phase_pdf)) => {
+                            let light_pdf = light_pdf(scene, &hit, origin);
+                            phase_pdf * phase_pdf / (phase_pdf * phase_pdf + light_pdf * light_pdf)
+                        },
+                        None => 1.0
+                    };
+                    radiance += throughput * emitted * weight;
+                }
+
+                // medium boundaries are crossed without counting as a bounce
+                if hit.material.is_interface() {
+                    medium = medium_after(scene, &hit, ray.dir, medium);
+                    ray = Ray { origin: hit.point, ..ray };
+                    continue;
+                }
+
+                phase_sample = None;
+
+                // scatter, if the material absorbs the path ends here. Subsurface materials walk
+                // through the inside of the object and leave it again, so the medium stays the same
+                let (material, hit) = hit.material.pick(hit, sampler);
+                let walks = material.is_subsurface();
+                let record = if walks {
+                    material.random_walk(hit, &scene.objects[hit.object_id], sampler)
+                } else {
+                    material.scatter(&ray, hit, sampler)
+                };
+
+                match record {
+                    Some(record) => {
+                        throughput = throughput * record.attenuation;
+                        ray = Ray { dir: record.ray.dir.normalized(), ..record.ray };
+                        if !walks {
+                            medium = medium_after(scene, &hit, ray.dir, medium);
+                        }
+                    },
+                    None => break
+                }
+            }
+
+            // russian roulette, survivors are reweighted to keep the estimate unbiased
+            if depth >= self.rr_depth {
+                let survival = throughput.max_component().min(0.95);
+                if sampler.next_f64() >= survival {
+                    break;
+                }
+                throughput /= survival;
+            }
+
+            depth += 1;
+        }
+
+        radiance
+    }
+}
+
+// emission arriving at a point inside a medium from a random point on a light, weighted by the
+// phase function for light continuing the ray and multiple importance sampled against
+// sampling the phase function
+fn sample_light(scene: &Scene, medium: &Medium, point: Vec3, ray: &Ray, sampler: &mut dyn Sampler) -> Color {
+    let sample = match scene.sample_light(sampler) {
+        Some(sample) => sample,
+        None => return Color::black()
+    };
+
+    let light = HitInfo { wavelengths: ray.wavelengths, ..sample.hit };
+    let to_light = light.point - point;
+    let distance = to_light.norm();
+    let dir = to_light / distance;
+
+    let cosine = Vec3::dot(&light.normal, &dir).abs();
+    if cosine <= 0.0 {
+        return Color::black();
+    }
+
+    let emitted = light.material.emmit(&Ray::new(point, dir), &light);
+    if emitted.near_zero() {
+        return Color::black();
+    }
+
+    let transmittance = transmittance(scene, point, light.point, Some(medium), ray.wavelengths, sampler);
+    let light_pdf = sample.pdf * distance * distance / cosine;
+    let phase = medium.phase(ray.dir, dir);
+    let weight = light_pdf * light_pdf / (light_pdf * light_pdf + phase * phase);
+
+    emitted * transmittance * (phase * weight / light_pdf)
+}
+
+// solid angle density with which sample_light picks the emitting hit when looking from origin
+fn light_pdf(scene: &Scene, hit: &HitInfo, origin: Vec3) -> f64 {
+    let to_light = hit.point - origin;
+    let cosine = Vec3::dot(&hit.normal, &to_light.normalized()).abs();
+    scene.light_pdf(hit.object_id) * to_light.norm_sqared() / cosine
+}
+
+// fraction of light travelling from one point to another, crossing any medium boundaries in between
+fn transmittance(scene: &Scene, from: Vec3, to: Vec3, medium: Option<&Medium>, wavelengths: Option<Wavelengths>, sampler: &mut dyn Sampler) -> Color {
+    let mut transmittance = Color::white();
+    let mut origin = from;
+    let mut medium = medium;
+
+    loop {
+        let to_target = to - origin;
+        let distance = to_target.norm();
+        let ray = Ray { wavelengths, ..Ray::new(origin, to_target / distance) };
+        let hit = scene.intersect(&ray, EPSILON, distance - EPSILON);
+
+        if let Some(current) = medium {
+            let t_max = hit.map_or(distance, |hit| hit.t);
+            transmittance = transmittance * current.transmittance(&ray, t_max, sampler);
+        }
+
+        match hit {
+            Some(hit) if hit.material.is_interface() => {
+                medium = medium_after(scene, &hit, ray.dir, medium);
+                origin = hit.point;
+            },
+            Some(_) => return Color::black(),
+            None => return transmittance
+        }
+    }
+}
+
+// medium a ray leaving the hit in direction dir travels through. Rays going through the
+// surface enter the medium of the object, or leave it for the medium of the world
+fn medium_after<'a>(scene: &'a Scene, hit: &HitInfo<'a>, dir: Vec3, current: Option<&'a Medium>) -> Option<&'a Medium> {
+    if Vec3::dot(&dir, &hit.normal) >= 0.0 {
+        current
+    } else if hit.front_face {
+        hit.medium
+    } else {
+        scene.medium.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::intersection::{object::Object, sphere::Sphere};
+    use crate::material::{Emission, Material};
+    use crate::sampler::RandomSampler;
+    use crate::texture::Texture;
+
+    #[test]
+    fn test_furnace() {
+        // Inside a closed sphere that glows with radiance 1 and reflects half of the light
+        // diffusely, the light bouncing around adds up to 1 / (1 - 0.5) everywhere
+        let material = Material::Emissive {
+            base: Box::new(Material::Lambertian { albedo: Texture::Constant { color: Color::new(0.5, 0.5, 0.5) } }),
+            emission: Emission::new(Texture::Constant { color: Color::white() })
+        };
+        let scene = Scene::new(vec![Object::Sphere(Sphere { center: Vec3::zero(), radius: 1.0, material, medium: None })]);
+        let path_tracer = PathTracer { max_depth: 100, rr_depth: 3, spectral: false };
+
+        let mut sampler = RandomSampler::new();
+        let samples = 20000;
+        let mut total = Color::black();
+        for i in 0..samples {
+            let f = i as f64;
+            let ray = Ray::new(Vec3::new(0.0, 0.2, 0.0), Vec3::new(f.sin(), (0.7 * f).cos(), 1.0));
+            total += path_tracer.radiance(&ray, &scene, &mut sampler);
+        }
+
+        // russian roulette keeps the estimate unbiased
+        let mean = total.average() / samples as f64;
+        assert!((mean - 2.0).abs() < 0.05, "radiance {}", mean);
+    }
+}
@@ -0,0 +1,320 @@
+use std::f64::consts::PI;
+
+use indicatif::ProgressBar;
+use rayon::iter::*;
+
+use crate::camera::Camera;
+use crate::color::Color;
+use crate::film::Film;
+use crate::intersection::{bvh::Aabb, hitinfo::HitInfo, object::Object, scene::Scene, Hittable};
+use crate::ray::Ray;
+use crate::sampler::{RandomSampler, Sampler};
+use crate::vec::Vec3;
+use super::{camera_ray, Integrator};
+
+const EPSILON: f64 = 1e-4;
+
+// Stochastic progressive photon mapping (Hachisuka and Jensen 2009). Every iteration traces a
+// camera ray through specular bounces until it hits a diffuse surface, then emits photons from
+// the lights and estimates the light arriving at that point from the photons around it. The
+// gather radius of every pixel shrinks over the iterations, so the result converges.
+pub struct ProgressivePhotonMapper {
+    pub max_depth: u32,
+    pub rr_depth: u32,
+    pub photons_per_iteration: u32,
+    pub initial_radius: f64,
+    // fraction of the new photons that is kept when shrinking the radius
+    pub alpha: f64,
+}
+
+// Statistics of a single pixel that are kept across iterations
+#[derive(Clone, Copy)]
+struct PixelStats {
+    radius: f64,
+    // number of photons that contributed so far, scaled down by alpha at every iteration
+    photon_count: f64,
+    // accumulated reflected flux
+    flux: Color,
+    // emission seen directly or through specular bounces
+    direct: Color,
+}
+
+// First diffuse surface hit by the camera ray of a pixel
+struct VisiblePoint<'a> {
+    hit: HitInfo<'a>,
+    wo: Vec3,
+    beta: Color,
+}
+
+#[derive(Clone, Copy)]
+struct Photon {
+    point: Vec3,
+    // direction the photon arrived from, pointing away from the surface
+    wi: Vec3,
+    power: Color,
+    // splitting axis of the k-d tree node holding this photon
+    axis: usize,
+}
+
+impl Integrator for ProgressivePhotonMapper {
+    fn render(&self, scene: &Scene, camera: &Camera, film: &Film, num_samples: u32, bar: &ProgressBar) {
+        let pixels = (film.width * film.height) as u64;
+        let iterations = num_samples as u64;
+
+        let mut stats = vec![PixelStats {
+            radius: self.initial_radius,
+            photon_count: 0.0,
+            flux: Color::black(),
+            direct: Color::black(),
+        }; pixels as usize];
+        let target = bounding_sphere(scene);
+
+        for iteration in 0..iterations {
+
+            // find the visible points of this iteration
+            let visible_points: Vec<Option<VisiblePoint>> = stats
+                .par_iter_mut()
+                .enumerate()
+                .map(|(i, pixel)| {
+                    let mut sampler = RandomSampler::new();
+                    let (x, y) = (i as u32 % film.width, i as u32 / film.width);
+                    let ray = camera_ray(camera, film, x, y, &mut sampler);
+                    self.visible_point(&ray, scene, pixel, &mut sampler)
+                })
+                .collect();
+
+            // shoot photons and sort them into a k-d tree
+            let mut photons: Vec<Photon> = (0..self.photons_per_iteration)
+                .into_par_iter()
+                .flat_map_iter(|_| self.trace_photon(scene, target, &mut RandomSampler::new()))
+                .collect();
+            build_kd_tree(&mut photons);
+
+            // gather the photons around every visible point and shrink the radius
+            stats.par_iter_mut().zip(visible_points).for_each(|(pixel, visible_point)| {
+                let vp = match visible_point {
+                    Some(vp) => vp,
+                    None => return
+                };
+
+                let mut flux = Color::black();
+                let mut count = 0.0;
+                let radius_squared = pixel.radius * pixel.radius;
+
+                query_kd_tree(&photons, vp.hit.point, radius_squared, &mut |photon| {
+                    let cosine = Vec3::dot(&photon.wi, &vp.hit.normal).abs();
+                    if cosine > 0.0 {
+                        flux += photon.power * vp.hit.material.eval(&vp.hit, vp.wo, photon.wi) / cosine;
+                    }
+                    count += 1.0;
+                });
+
+                if count > 0.0 {
+                    let new_count = pixel.photon_count + self.alpha * count;
+                    let new_radius = pixel.radius * (new_count / (pixel.photon_count + count)).sqrt();
+                    let shrink = (new_radius * new_radius) / radius_squared;
+
+                    pixel.flux = (pixel.flux + vp.beta * flux) * shrink;
+                    pixel.photon_count = new_count;
+                    pixel.radius = new_radius;
+                }
+            });
+
+            bar.inc(pixels * (iteration + 1) / iterations - pixels * iteration / iterations);
+        }
+
+        // the film divides by num_samples, which is the number of iterations
+        let emitted = (iterations * self.photons_per_iteration as u64) as f64;
+        for (i, pixel) in stats.iter().enumerate() {
+            let reflected = pixel.flux / (emitted * PI * pixel.radius * pixel.radius);
+            let color = pixel.direct + reflected * iterations as f64;
+            film.add(i as u32 % film.width, i as u32 / film.width, color);
+        }
+    }
+}
+
+impl ProgressivePhotonMapper {
+
+    // follows the camera ray through specular bounces, collecting the emission it sees
+    fn visible_point<'a>(&self, ray: &Ray, scene: &'a Scene, pixel: &mut PixelStats, sampler: &mut dyn Sampler) -> Option<VisiblePoint<'a>> {
+        let mut ray = *ray;
+        let mut beta = Color::white();
+
+        for _ in 0..=self.max_depth {
+            let hit = scene.intersect(&ray, EPSILON, f64::MAX)?;
+
+            pixel.direct += beta * hit.material.emmit(&ray, &hit);
+
+            if !hit.material.is_specular() {
+                return Some(VisiblePoint { hit, wo: -ray.dir.normalized(), beta });
+            }
+
+            let record = hit.material.scatter(&ray, hit, sampler)?;
+            beta = beta * record.attenuation;
+            ray = record.ray;
+        }
+
+        None
+    }
+
+    // Emits a photon from a random point on a light and stores it at every diffuse surface it hits.
+    // target is the sphere around the scene that photons from environment spheres are aimed at
+    fn trace_photon(&self, scene: &Scene, target: Option<(Vec3, f64)>, sampler: &mut dyn Sampler) -> Vec<Photon> {
+        let mut photons = vec![];
+
+        let sample = match scene.sample_light(sampler) {
+            Some(sample) => sample,
+            None => return photons
+        };
+
+        // Cosine weighted emission. Environments are mostly far from the scene, their photons are
+        // sent into the cone towards it instead, as the others would miss it
+        let light = sample.hit;
+        let (dir, dir_pdf) = match (&scene.objects[light.object_id], target) {
+            (Object::Environment(_), Some((center, radius))) if (center - light.point).norm() > radius => {
+                let distance = (center - light.point).norm();
+                let cos_max = (1.0 - (radius / distance).powi(2)).sqrt();
+                let dir = Vec3::random_cone_direction(&((center - light.point) / distance), cos_max, sampler);
+                (dir, 1.0 / (2.0 * PI * (1.0 - cos_max)))
+            },
+            _ => {
+                let dir = Vec3::random_cosine_direction(&light.normal, sampler);
+                (dir, Vec3::dot(&dir, &light.normal) / PI)
+            }
+        };
+        let cosine = Vec3::dot(&dir, &light.normal);
+        if cosine <= 0.0 || dir_pdf <= 0.0 {
+            return photons;
+        }
+        let le = light.material.emmit(&Ray::new(light.point + dir, -dir), &light);
+
+        let mut power = le * (cosine / (sample.pdf * dir_pdf));
+        let mut ray = Ray::new(light.point, dir);
+
+        for depth in 0..=self.max_depth {
+            let hit = match scene.intersect(&ray, EPSILON, f64::MAX) {
+                Some(hit) => hit,
+                None => break
+            };
+
+            if !hit.material.is_specular() {
+                photons.push(Photon { point: hit.point, wi: -ray.dir.normalized(), power, axis: 0 });
+            }
+
+            match hit.material.scatter(&ray, hit, sampler) {
+                Some(record) => {
+                    power = power * record.attenuation;
+                    ray = record.ray;
+                },
+                None => break
+            }
+
+            // russian roulette, survivors are reweighted to keep the estimate unbiased
+            if depth >= self.rr_depth {
+                let survival = power.max_component().min(0.95);
+                if sampler.next_f64() >= survival {
+                    break;
+                }
+                power /= survival;
+            }
+        }
+
+        photons
+    }
+}
+
+// center and radius of a sphere around all objects but the environments
+fn bounding_sphere(scene: &Scene) -> Option<(Vec3, f64)> {
+    let bounds = scene.objects
+        .iter()
+        .filter(|object| !matches!(object, Object::Environment(_)))
+        .fold(Aabb::empty(), |bounds, object| bounds.union(&object.bounds()));
+    if !bounds.min.x.is_finite() {
+        return None;
+    }
+    Some((bounds.centroid(), 0.5 * (bounds.max - bounds.min).norm()))
+}
+
+// Reorders the photons into an implicit k-d tree: the median of every slice is the node,
+// the photons before it form the left subtree and the ones after it the right subtree
+fn build_kd_tree(photons: &mut [Photon]) {
+    if photons.len() <= 1 {
+        return;
+    }
+
+    // split along the axis in which the photons are spread out the most
+    let mut min = photons[0].point;
+    let mut max = photons[0].point;
+    for photon in photons.iter() {
+        min = Vec3::new(min.x.min(photon.point.x), min.y.min(photon.point.y), min.z.min(photon.point.z));
+        max = Vec3::new(max.x.max(photon.point.x), max.y.max(photon.point.y), max.z.max(photon.point.z));
+    }
+    let extent = max - min;
+    let axis = if extent.x > extent.y && extent.x > extent.z {
+        0
+    } else if extent.y > extent.z {
+        1
+    } else {
+        2
+    };
+
+    let mid = photons.len() / 2;
+    photons.select_nth_unstable_by(mid, |a, b| a.point[axis].total_cmp(&b.point[axis]));
+    photons[mid].axis = axis;
+
+    let (left, right) = photons.split_at_mut(mid);
+    build_kd_tree(left);
+    build_kd_tree(&mut right[1..]);
+}
+
+// calls f for every photon closer to point than the square root of radius_squared
+fn query_kd_tree<F: FnMut(&Photon)>(photons: &[Photon], point: Vec3, radius_squared: f64, f: &mut F) {
+    if photons.is_empty() {
+        return;
+    }
+
+    let mid = photons.len() / 2;
+    let node = &photons[mid];
+
+    if (node.point - point).norm_sqared() <= radius_squared {
+        f(node);
+    }
+
+    // leaves have no children and thus no meaningful axis
+    if photons.len() == 1 {
+        return;
+    }
+
+    let (left, right) = (&photons[..mid], &photons[mid + 1..]);
+    let distance = point[node.axis] - node.point[node.axis];
+    let (near, far) = if distance < 0.0 { (left, right) } else { (right, left) };
+
+    query_kd_tree(near, point, radius_squared, f);
+    if distance * distance <= radius_squared {
+        query_kd_tree(far, point, radius_squared, f);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_kd_tree_query() {
+        let mut photons: Vec<Photon> = (0..1000)
+            .map(|i| {
+                let point = Vec3::new((i % 10) as f64, (i / 10 % 10) as f64, (i / 100) as f64);
+                Photon { point, wi: Vec3::zero(), power: Color::white(), axis: 0 }
+            })
+            .collect();
+        build_kd_tree(&mut photons);
+
+        let center = Vec3::new(4.2, 5.1, 3.0);
+        let mut found = 0;
+        query_kd_tree(&photons, center, 1.5, &mut |_| found += 1);
+
+        let expected = photons.iter().filter(|p| (p.point - center).norm_sqared() <= 1.5).count();
+        assert_eq!(found, expected);
+        assert!(found > 0);
+    }
+}
@@ -0,0 +1,139 @@
+use crate::ray::Ray;
+use crate::vec::Vec3;
+
+// maximum number of primitives in a leaf
+const MAX_LEAF_SIZE: usize = 4;
+
+// axis aligned bounding box
+#[derive(Debug, Clone, Copy)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+
+    pub fn empty() -> Aabb {
+        Aabb { min: Vec3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY), max: Vec3::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY) }
+    }
+
+    pub fn around(points: &[Vec3]) -> Aabb {
+        points.iter().fold(Aabb::empty(), |bounds, &p| bounds.union(&Aabb { min: p, max: p }))
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: Vec3::new(self.min.x.min(other.min.x), self.min.y.min(other.min.y), self.min.z.min(other.min.z)),
+            max: Vec3::new(self.max.x.max(other.max.x), self.max.y.max(other.max.y), self.max.z.max(other.max.z)),
+        }
+    }
+
+    pub fn centroid(&self) -> Vec3 {
+        0.5 * (self.min + self.max)
+    }
+
+    // wether the ray crosses the box between t_min and t_max, inv_dir holds the inverse ray direction
+    fn hit(&self, ray: &Ray, inv_dir: Vec3, t_min: f64, t_max: f64) -> bool {
+        let mut t_min = t_min;
+        let mut t_max = t_max;
+        for axis in 0..3 {
+            let t0 = (self.min[axis] - ray.origin[axis]) * inv_dir[axis];
+            let t1 = (self.max[axis] - ray.origin[axis]) * inv_dir[axis];
+            let (t0, t1) = if inv_dir[axis] < 0.0 { (t1, t0) } else { (t0, t1) };
+
+            // written so that NaNs (from 0 * infinity) do not reject the box
+            t_min = if t0 > t_min { t0 } else { t_min };
+            t_max = if t1 < t_max { t1 } else { t_max };
+            if t_max < t_min {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+#[derive(Debug)]
+struct Node {
+    bounds: Aabb,
+    // leaves hold the primitives indices[start..start + count], interior nodes have count 0.
+    // Their first child directly follows them and the second one is at second_child
+    start: usize,
+    count: usize,
+    second_child: usize,
+}
+
+// Bounding volume hierarchy over primitives given by their boxes, split at the median of the
+// centroids along the longest axis
+#[derive(Debug)]
+pub struct Bvh {
+    nodes: Vec<Node>,
+    indices: Vec<usize>,
+}
+
+impl Bvh {
+
+    pub fn new(boxes: &[Aabb]) -> Bvh {
+        let mut bvh = Bvh { nodes: vec![], indices: (0..boxes.len()).collect() };
+        if !boxes.is_empty() {
+            bvh.build(boxes, 0, boxes.len());
+        }
+        bvh
+    }
+
+    fn build(&mut self, boxes: &[Aabb], start: usize, end: usize) -> usize {
+        let bounds = self.indices[start..end].iter().fold(Aabb::empty(), |b, &i| b.union(&boxes[i]));
+        let node = self.nodes.len();
+        self.nodes.push(Node { bounds, start, count: end - start, second_child: 0 });
+
+        if end - start <= MAX_LEAF_SIZE {
+            return node;
+        }
+
+        let centroids = self.indices[start..end]
+            .iter()
+            .fold(Aabb::empty(), |b, &i| { let c = boxes[i].centroid(); b.union(&Aabb { min: c, max: c }) });
+        let extent = centroids.max - centroids.min;
+        let axis = if extent.x > extent.y && extent.x > extent.z { 0 } else if extent.y > extent.z { 1 } else { 2 };
+
+        let middle = (start + end) / 2;
+        self.indices[start..end].select_nth_unstable_by(middle - start, |&a, &b| {
+            boxes[a].centroid()[axis].total_cmp(&boxes[b].centroid()[axis])
+        });
+
+        self.build(boxes, start, middle);
+        let second_child = self.build(boxes, middle, end);
+        self.nodes[node].count = 0;
+        self.nodes[node].second_child = second_child;
+        node
+    }
+
+    // Calls hit for every primitive whose box the ray crosses between t_min and the closest hit
+    // so far. hit returns the distance of the intersection with the primitive, if any
+    pub fn traverse(&self, ray: &Ray, t_min: f64, t_max: f64, mut hit: impl FnMut(usize, f64) -> Option<f64>) {
+        if self.nodes.is_empty() {
+            return;
+        }
+
+        let inv_dir = Vec3::new(1.0 / ray.dir.x, 1.0 / ray.dir.y, 1.0 / ray.dir.z);
+        let mut t_max = t_max;
+        let mut stack = vec![0];
+
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if !node.bounds.hit(ray, inv_dir, t_min, t_max) {
+                continue;
+            }
+
+            if node.count > 0 {
+                for &primitive in &self.indices[node.start..node.start + node.count] {
+                    if let Some(t) = hit(primitive, t_max) {
+                        t_max = t;
+                    }
+                }
+            } else {
+                stack.push(node.second_child);
+                stack.push(index + 1);
+            }
+        }
+    }
+}
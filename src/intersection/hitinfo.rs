@@ -1,37 +1,70 @@
+use crate::color::Color;
+use crate::spectrum::Wavelengths;
 use crate::vec::Vec3;
 use crate::material::Material;
+use crate::medium::Medium;
 use crate::ray::Ray;
 
 #[derive(Debug, Clone, Copy)]
-pub struct HitInfo {
+pub struct HitInfo<'a> {
+    // faces the ray, on smooth meshes it is interpolated from the vertex normals
     pub normal: Vec3,
     pub t: f64,
     pub point: Vec3,
     pub front_face: bool,
-    pub material: Material,
+    pub material: &'a Material,
     pub u: f64,
-    pub v: f64
+    pub v: f64,
+    // derivatives of the point along the texture coordinates, zero when not known
+    pub dpdu: Vec3,
+    pub dpdv: Vec3,
+    // interpolated vertex color, on meshes that have them
+    pub color: Option<Color>,
+    pub object_id: usize,
+    // medium inside the object, if any
+    pub medium: Option<&'a Medium>,
+    // wavelengths of the ray that found the hit, in spectral mode
+    pub wavelengths: Option<Wavelengths>
 }
 
-impl HitInfo {
-    pub fn new(t: f64, hit_ray: &Ray, outward_normal: Vec3, material: Material, u: f64, v: f64) -> HitInfo {
+impl<'a> HitInfo<'a> {
+    pub fn new(t: f64, hit_ray: &Ray, outward_normal: Vec3, material: &'a Material, u: f64, v: f64) -> HitInfo<'a> {
         let mut hit = HitInfo {
             front_face: false,
             point: hit_ray.at(t),
-            t: t.clone(),
+            t,
             normal: outward_normal,
             material,
             u, // u and v are texture coordinates
-            v
+            v,
+            dpdu: Vec3::zero(),
+            dpdv: Vec3::zero(),
+            color: None,
+            object_id: 0, // set by the scene
+            medium: None,
+            wavelengths: hit_ray.wavelengths
         };
 
-        hit.set_face_normal(&hit_ray, outward_normal.clone());
+        hit.set_face_normal(hit_ray, outward_normal);
 
         hit
     }
 
+    // an RGB color of the scene as seen by the ray, which in spectral mode is its spectrum at the wavelengths
+    pub fn spectrum(&self, rgb: Color) -> Color {
+        match self.wavelengths {
+            Some(wavelengths) => wavelengths.upsample(rgb),
+            None => rgb
+        }
+    }
+
+    // ray leaving the hit in direction dir, carrying the same wavelengths
+    pub fn spawn_ray(&self, dir: Vec3) -> Ray {
+        Ray { wavelengths: self.wavelengths, ..Ray::new(self.point, dir) }
+    }
+
     fn set_face_normal(&mut self, ray: &Ray, outward_normal: Vec3) {
         self.front_face = Vec3::dot(&ray.dir, &outward_normal) < 0.0;
         self.normal = if self.front_face { outward_normal } else { -outward_normal }
     }
-}
\ No newline at end of file
+}
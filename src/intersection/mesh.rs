@@ -0,0 +1,306 @@
+use std::collections::HashMap;
+
+use crate::{color::Color, material::Material, medium::Medium, ray::Ray, sampler::Sampler, transform::Transform, vec::Vec3};
+use super::{Hittable, bvh::{Aabb, Bvh}, hitinfo::HitInfo};
+
+// Triangles sharing their vertices, as read from a model file
+#[derive(Debug, Clone, Default)]
+pub struct MeshData {
+    pub positions: Vec<Vec3>,
+    // texture coordinates of each vertex, empty if the model has none
+    pub uvs: Vec<(f64, f64)>,
+    // normals and colors of each vertex, also empty if the model has none
+    pub normals: Vec<Vec3>,
+    pub colors: Vec<Color>,
+    pub triangles: Vec<[usize; 3]>,
+}
+
+#[derive(Debug)]
+pub struct Mesh {
+    data: MeshData,
+    pub material: Material,
+    pub medium: Option<Medium>,
+    bvh: Bvh,
+    // cumulative areas of the triangles, for sampling points uniformly
+    area_cdf: Vec<f64>,
+}
+
+impl Mesh {
+
+    pub fn new(data: MeshData, material: Material, medium: Option<Medium>) -> Mesh {
+        let boxes: Vec<Aabb> = (0..data.triangles.len())
+            .map(|index| Aabb::around(&data.vertices(index)))
+            .collect();
+
+        let mut area_cdf = Vec::with_capacity(data.triangles.len());
+        let mut total = 0.0;
+        for index in 0..data.triangles.len() {
+            let [p0, p1, p2] = data.vertices(index);
+            total += 0.5 * Vec3::cross(&(p1 - p0), &(p2 - p0)).norm();
+            area_cdf.push(total);
+        }
+
+        Mesh { bvh: Bvh::new(&boxes), data, material, medium, area_cdf }
+    }
+
+    pub fn bounds(&self) -> Aabb {
+        Aabb::around(&self.data.positions)
+    }
+
+    pub fn area(&self) -> f64 {
+        self.area_cdf.last().copied().unwrap_or(0.0)
+    }
+
+    // uniformly distributed point on the surface, the returned hit faces outwards
+    pub fn sample_surface(&self, sampler: &mut dyn Sampler) -> HitInfo<'_> {
+        let target = sampler.next_f64() * self.area();
+        let index = self.area_cdf.partition_point(|&c| c < target).min(self.area_cdf.len() - 1);
+
+        // uniform barycentric coordinates
+        let su = sampler.next_f64().sqrt();
+        let (b1, b2) = (su * sampler.next_f64(), 1.0 - su);
+
+        let [p0, p1, p2] = self.data.vertices(index);
+        let point = (1.0 - b1 - b2) * p0 + b1 * p1 + b2 * p2;
+        let normal = Vec3::cross(&(p1 - p0), &(p2 - p0)).normalized();
+
+        // a ray arriving from outside makes the hit face outwards
+        self.hit_info(index, &Ray::new(point, -normal), 0.0, b1, b2)
+    }
+
+    // distance and barycentric coordinates of the intersection with a triangle (Möller-Trumbore)
+    fn intersect_triangle(&self, index: usize, ray: &Ray, t_min: f64, t_max: f64) -> Option<(f64, f64, f64)> {
+        let [p0, p1, p2] = self.data.vertices(index);
+        let e1 = p1 - p0;
+        let e2 = p2 - p0;
+
+        let p = Vec3::cross(&ray.dir, &e2);
+        let det = Vec3::dot(&e1, &p);
+        if det.abs() < 1e-12 {
+            return None;
+        }
+        let inv_det = 1.0 / det;
+
+        let s = ray.origin - p0;
+        let b1 = Vec3::dot(&s, &p) * inv_det;
+        if !(0.0..=1.0).contains(&b1) {
+            return None;
+        }
+
+        let q = Vec3::cross(&s, &e1);
+        let b2 = Vec3::dot(&ray.dir, &q) * inv_det;
+        if b2 < 0.0 || b1 + b2 > 1.0 {
+            return None;
+        }
+
+        let t = Vec3::dot(&e2, &q) * inv_det;
+        if t < t_min || t_max < t {
+            return None;
+        }
+
+        Some((t, b1, b2))
+    }
+
+    fn hit_info(&self, index: usize, ray: &Ray, t: f64, b1: f64, b2: f64) -> HitInfo<'_> {
+        let [p0, p1, p2] = self.data.vertices(index);
+        let [i0, i1, i2] = self.data.triangles[index];
+        let outward_normal = Vec3::cross(&(p1 - p0), &(p2 - p0)).normalized();
+        let b0 = 1.0 - b1 - b2;
+
+        // without texture coordinates the barycentric ones are used
+        let (u, v, dpdu, dpdv) = if self.data.uvs.is_empty() {
+            (b1, b2, p1 - p0, p2 - p0)
+        } else {
+            let (uv0, uv1, uv2) = (self.data.uvs[i0], self.data.uvs[i1], self.data.uvs[i2]);
+            let u = b0 * uv0.0 + b1 * uv1.0 + b2 * uv2.0;
+            let v = b0 * uv0.1 + b1 * uv1.1 + b2 * uv2.1;
+
+            // solve for the derivatives of the position along u and v
+            let (du02, dv02) = (uv0.0 - uv2.0, uv0.1 - uv2.1);
+            let (du12, dv12) = (uv1.0 - uv2.0, uv1.1 - uv2.1);
+            let det = du02 * dv12 - dv02 * du12;
+            if det.abs() < 1e-12 {
+                (u, v, Vec3::zero(), Vec3::zero())
+            } else {
+                let (dp02, dp12) = (p0 - p2, p1 - p2);
+                (u, v, (dv12 * dp02 - dv02 * dp12) / det, (du02 * dp12 - du12 * dp02) / det)
+            }
+        };
+
+        let mut hit = HitInfo::new(t, ray, outward_normal, &self.material, u, v);
+
+        // the shading normal is on the same side of the surface as the geometric one
+        if !self.data.normals.is_empty() {
+            let normals = (self.data.normals[i0], self.data.normals[i1], self.data.normals[i2]);
+            let shading = (b0 * normals.0 + b1 * normals.1 + b2 * normals.2).normalized();
+            let shading = if Vec3::dot(&shading, &outward_normal) < 0.0 { -shading } else { shading };
+            hit.normal = if hit.front_face { shading } else { -shading };
+        }
+        if !self.data.colors.is_empty() {
+            hit.color = Some(b0 * self.data.colors[i0] + b1 * self.data.colors[i1] + b2 * self.data.colors[i2]);
+        }
+
+        hit.dpdu = dpdu;
+        hit.dpdv = dpdv;
+        hit.medium = self.medium.as_ref();
+        hit
+    }
+}
+
+impl MeshData {
+
+    pub fn transform(&mut self, transform: &Transform) {
+        for position in &mut self.positions {
+            *position = transform.point(*position);
+        }
+        // one inversion for all of the normals
+        let normal_transform = transform.normal_transform();
+        for normal in &mut self.normals {
+            *normal = normal_transform.vector(*normal).normalized();
+        }
+    }
+
+    // turns the triangles around, which changes the side they face
+    pub fn reverse_winding(&mut self) {
+        for triangle in &mut self.triangles {
+            triangle.swap(1, 2);
+        }
+    }
+
+    // Replaces the vertex normals by averages of the normals of the triangles around each vertex.
+    // Only triangles that meet at less than the crease angle (in degrees) are averaged, so
+    // sharper edges stay visible. Vertices on such edges are split
+    pub fn smooth_normals(&mut self, crease_angle: f64) {
+        // weighted by the area of the triangles
+        let face_normals: Vec<Vec3> = (0..self.triangles.len())
+            .map(|index| {
+                let [p0, p1, p2] = self.vertices(index);
+                Vec3::cross(&(p1 - p0), &(p2 - p0))
+            })
+            .collect();
+
+        // the triangles around each position, vertices can be duplicated for texture seams
+        let key = |p: Vec3| [p.x.to_bits(), p.y.to_bits(), p.z.to_bits()];
+        let mut around: HashMap<[u64; 3], Vec<usize>> = HashMap::new();
+        for (index, triangle) in self.triangles.iter().enumerate() {
+            for &vertex in triangle {
+                around.entry(key(self.positions[vertex])).or_default().push(index);
+            }
+        }
+
+        let min_cosine = crease_angle.to_radians().cos();
+        let mut data = MeshData::default();
+        let mut split: HashMap<(usize, [u64; 3]), usize> = HashMap::new();
+
+        for (index, triangle) in self.triangles.iter().enumerate() {
+            let face = face_normals[index].normalized();
+            let mut corners = [0; 3];
+            for (corner, &vertex) in triangle.iter().enumerate() {
+                let mut normal = Vec3::zero();
+                for &other in &around[&key(self.positions[vertex])] {
+                    if Vec3::dot(&face, &face_normals[other].normalized()) >= min_cosine - 1e-9 {
+                        normal += face_normals[other];
+                    }
+                }
+                let normal = if normal.near_zero() { face } else { normal.normalized() };
+
+                corners[corner] = *split.entry((vertex, key(normal))).or_insert_with(|| {
+                    data.positions.push(self.positions[vertex]);
+                    data.normals.push(normal);
+                    if !self.uvs.is_empty() {
+                        data.uvs.push(self.uvs[vertex]);
+                    }
+                    if !self.colors.is_empty() {
+                        data.colors.push(self.colors[vertex]);
+                    }
+                    data.positions.len() - 1
+                });
+            }
+            data.triangles.push(corners);
+        }
+
+        *self = data;
+    }
+
+    fn vertices(&self, index: usize) -> [Vec3; 3] {
+        let [i0, i1, i2] = self.triangles[index];
+        [self.positions[i0], self.positions[i1], self.positions[i2]]
+    }
+}
+
+impl Hittable for Mesh {
+    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitInfo<'_>> {
+        let mut closest: Option<HitInfo> = None;
+
+        self.bvh.traverse(ray, t_min, t_max, |index, t_max| {
+            let (t, b1, b2) = self.intersect_triangle(index, ray, t_min, t_max)?;
+            let hit = self.hit_info(index, ray, t, b1, b2);
+            if self.material.is_cut_out(ray, &hit) {
+                return None;
+            }
+            closest = Some(hit);
+            Some(t)
+        });
+
+        closest
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::texture::Texture;
+
+    // a unit cube, one quad split into two triangles per face
+    fn cube() -> MeshData {
+        let mut data = MeshData::default();
+        for i in 0..8 {
+            data.positions.push(Vec3::new((i & 1) as f64, ((i >> 1) & 1) as f64, ((i >> 2) & 1) as f64));
+        }
+        let quads = [[0, 2, 3, 1], [4, 5, 7, 6], [0, 1, 5, 4], [2, 6, 7, 3], [0, 4, 6, 2], [1, 3, 7, 5]];
+        for [a, b, c, d] in quads {
+            data.triangles.push([a, b, c]);
+            data.triangles.push([a, c, d]);
+        }
+        data
+    }
+
+    #[test]
+    fn test_mesh_intersection() {
+        let data = cube();
+        let material = Material::Lambertian { albedo: Texture::Constant { color: Color::white() } };
+        let mesh = Mesh::new(data, material, None);
+
+        assert!((mesh.area() - 6.0).abs() < 1e-9);
+
+        let hit = mesh.intersect(&Ray::new(Vec3::new(0.3, 0.6, -2.0), Vec3::new(0.0, 0.0, 1.0)), 1e-6, f64::MAX).unwrap();
+        assert!((hit.t - 2.0).abs() < 1e-9);
+        assert!(hit.normal.is_close(&Vec3::new(0.0, 0.0, -1.0)));
+        assert!(hit.front_face);
+
+        // from the inside the far side is hit and the normal faces the ray
+        let hit = mesh.intersect(&Ray::new(Vec3::new(0.5, 0.5, 0.5), Vec3::new(1.0, 0.0, 0.0)), 1e-6, f64::MAX).unwrap();
+        assert!((hit.t - 0.5).abs() < 1e-9);
+        assert!(!hit.front_face);
+
+        assert!(mesh.intersect(&Ray::new(Vec3::new(2.0, 2.0, -2.0), Vec3::new(0.0, 0.0, 1.0)), 1e-6, f64::MAX).is_none());
+    }
+
+    #[test]
+    fn test_smooth_normals() {
+        // the edges of the cube are sharper than the crease angle, so the faces stay flat
+        let mut flat = cube();
+        flat.smooth_normals(60.0);
+        assert_eq!(flat.positions.len(), 24);
+        assert!(flat.normals[0].is_close(&Vec3::new(0.0, 0.0, -1.0)));
+
+        // otherwise the corners share a normal pointing away from the center
+        let mut smooth = cube();
+        smooth.smooth_normals(180.0);
+        assert_eq!(smooth.positions.len(), 8);
+        for (position, normal) in smooth.positions.iter().zip(&smooth.normals) {
+            let outwards = (*position - Vec3::new(0.5, 0.5, 0.5)).normalized();
+            assert!(Vec3::dot(normal, &outwards) > 0.9);
+        }
+    }
+}
@@ -1,10 +1,13 @@
 use crate::ray::Ray;
 use self::hitinfo::HitInfo;
 
+pub mod bvh;
 pub mod hitinfo;
+pub mod mesh;
+pub mod object;
 pub mod sphere;
 pub mod scene;
 
 pub trait Hittable {
-    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitInfo>;
+    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitInfo<'_>>;
 }
@@ -0,0 +1,101 @@
+use crate::{color::Color, material::{Emission, Material}, ray::Ray, sampler::Sampler, texture::Texture, vec::Vec3};
+use super::{Hittable, bvh::Aabb, hitinfo::HitInfo, mesh::Mesh, sphere::Sphere};
+
+// anything that can be placed in the scene
+#[derive(Debug)]
+pub enum Object {
+    Sphere(Sphere),
+    Mesh(Mesh),
+    // a sphere around the scene whose inside is its front, standing in for the light of the environment
+    Environment(Sphere),
+}
+
+impl Object {
+
+    // an environment glowing towards its center with the given radiance, the radius should enclose the scene
+    pub fn environment(center: Vec3, radius: f64, radiance: Color) -> Object {
+        let emission = Emission::new(Texture::Constant { color: radiance });
+        Object::Environment(Sphere { center, radius: radius.abs(), material: Material::DiffuseLight { emission }, medium: None })
+    }
+
+    pub fn material(&self) -> &Material {
+        match self {
+            Object::Sphere(sphere) | Object::Environment(sphere) => &sphere.material,
+            Object::Mesh(mesh) => &mesh.material,
+        }
+    }
+
+    pub fn bounds(&self) -> Aabb {
+        match self {
+            Object::Sphere(sphere) | Object::Environment(sphere) => sphere.bounds(),
+            Object::Mesh(mesh) => mesh.bounds(),
+        }
+    }
+
+    pub fn area(&self) -> f64 {
+        match self {
+            Object::Sphere(sphere) | Object::Environment(sphere) => sphere.area(),
+            Object::Mesh(mesh) => mesh.area(),
+        }
+    }
+
+    // uniformly distributed point on the surface, the returned hit faces outwards (inwards for environments)
+    pub fn sample_surface(&self, sampler: &mut dyn Sampler) -> HitInfo<'_> {
+        match self {
+            Object::Sphere(sphere) => sphere.sample_surface(sampler),
+            Object::Mesh(mesh) => mesh.sample_surface(sampler),
+            Object::Environment(sphere) => {
+                let mut hit = sphere.sample_surface(sampler);
+                hit.normal = -hit.normal;
+                hit
+            }
+        }
+    }
+}
+
+impl Hittable for Object {
+    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitInfo<'_>> {
+        match self {
+            Object::Sphere(sphere) => sphere.intersect(ray, t_min, t_max),
+            Object::Mesh(mesh) => mesh.intersect(ray, t_min, t_max),
+            Object::Environment(sphere) => {
+                // rays from inside the environment hit its front
+                let mut hit = sphere.intersect(ray, t_min, t_max)?;
+                let inward = (sphere.center - hit.point).normalized();
+                hit.front_face = Vec3::dot(&ray.dir, &inward) < 0.0;
+                hit.normal = if hit.front_face { inward } else { -inward };
+                Some(hit)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::sampler::RandomSampler;
+
+    #[test]
+    fn test_environment() {
+        let environment = Object::environment(Vec3::new(1.0, 0.0, 0.0), 10.0, Color::white());
+
+        // hits from the inside are on the front face, with the normal towards the center
+        let hit = environment.intersect(&Ray::new(Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0)), 1e-6, f64::MAX).unwrap();
+        assert!(hit.front_face);
+        assert!(hit.normal.is_close(&Vec3::new(0.0, -1.0, 0.0)));
+
+        // as are sampled points, which lights emit from
+        let mut sampler = RandomSampler::new();
+        for _ in 0..10 {
+            let sample = environment.sample_surface(&mut sampler);
+            let to_center = Vec3::new(1.0, 0.0, 0.0) - sample.point;
+            assert!((to_center.norm() - 10.0).abs() < 1e-9);
+            assert!(Vec3::dot(&sample.normal, &to_center) > 0.0);
+        }
+
+        // negative radii keep the outward normal of the sphere
+        let sphere = Sphere { center: Vec3::zero(), radius: -1.0, material: Material::Interface, medium: None };
+        let hit = sphere.intersect(&Ray::new(Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0)), 1e-6, f64::MAX).unwrap();
+        assert!(hit.front_face && hit.normal.is_close(&Vec3::new(0.0, 0.0, 1.0)));
+    }
+}
@@ -1,47 +1,108 @@
-use crate::{material::Material, ray::Ray, texture::Texture, vec::Vec3};
-use super::{Hittable, hitinfo::HitInfo, sphere::Sphere};
+use crate::medium::Medium;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use super::{Hittable, bvh::{Aabb, Bvh}, hitinfo::HitInfo, object::Object};
 
 pub struct Scene {
-    pub spheres: Vec<Sphere>,
+    pub objects: Vec<Object>,
+    lights: Vec<usize>,
+    // over the objects, so rays only test the ones whose boxes they cross
+    bvh: Bvh,
+    // medium filling the space outside of all objects, like atmospheric haze
+    pub medium: Option<Medium>,
+}
+
+// A point on an emitter, pdf is its density with respect to surface area
+pub struct LightSample<'a> {
+    pub hit: HitInfo<'a>,
+    pub pdf: f64,
 }
 
 impl Scene {
-    pub fn empty() -> Scene {
-        Scene { spheres: vec![] }
+    pub fn new(objects: Vec<Object>) -> Scene {
+        let lights = objects
+            .iter()
+            .enumerate()
+            .filter(|(_, object)| object.material().is_emissive())
+            .map(|(object_id, _)| object_id)
+            .collect();
+
+        let boxes: Vec<Aabb> = objects.iter().map(Object::bounds).collect();
+        Scene { bvh: Bvh::new(&boxes), objects, lights, medium: None }
+    }
+
+    // picks an emitter uniformly and then a point uniformly on its surface
+    pub fn sample_light(&self, sampler: &mut dyn Sampler) -> Option<LightSample<'_>> {
+        if self.lights.is_empty() {
+            return None;
+        }
+
+        let index = (sampler.next_f64() * self.lights.len() as f64) as usize;
+        let object_id = self.lights[index.min(self.lights.len() - 1)];
+        let object = &self.objects[object_id];
+
+        let mut hit = object.sample_surface(sampler);
+        hit.object_id = object_id;
+
+        Some(LightSample { hit, pdf: self.light_pdf(object_id) })
+    }
+
+    // area density with which sample_light picks a point on the given object
+    pub fn light_pdf(&self, object_id: usize) -> f64 {
+        1.0 / (self.lights.len() as f64 * self.objects[object_id].area())
     }
 }
 
 impl Hittable for Scene {
-    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitInfo> {
-
-        let mut is_hit = false;
-        let mut closest_so_far = HitInfo {
-            normal: Vec3::zero(),
-            t: t_max,
-            point: Vec3::zero(),
-            front_face: false,
-            material: Material::Lambertian { albedo: Texture::new_constant(0.0, 0.0, 0.0)},
-            u: 0.0,
-            v: 0.0
-        };
+    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitInfo<'_>> {
 
-        for sphere in &(self.spheres) {
-            let sphere_hit = sphere.intersect(ray, t_min.clone(), t_max.clone());
+        let mut closest_so_far: Option<HitInfo> = None;
 
-            // if we hit the sphere return its hit info
-            if let Some(hit) = sphere_hit {
-                is_hit = true;
-                if hit.t < closest_so_far.t {
-                    closest_so_far = hit;
-                }
-            }
+        // the traversal only looks for hits closer than the current closest one
+        self.bvh.traverse(ray, t_min, t_max, |object_id, t_closest| {
+            let mut hit = self.objects[object_id].intersect(ray, t_min, t_closest)?;
+            hit.object_id = object_id;
+            closest_so_far = Some(hit);
+            Some(hit.t)
+        });
 
-        }
+        closest_so_far
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{color::Color, intersection::sphere::Sphere, material::Material, texture::Texture, vec::Vec3};
+
+    #[test]
+    fn test_closest_hit() {
+        let material = Material::Lambertian { albedo: Texture::Constant { color: Color::white() } };
+        let sphere = |i: usize| {
+            let f = i as f64;
+            // a few inside out spheres too, like the inner surface of a hollow glass ball
+            let radius = if i.is_multiple_of(7) { -0.3 } else { 0.2 + 0.1 * (f * 1.3).sin().abs() };
+            Object::Sphere(Sphere { center: Vec3::new((f * 2.1).sin() * 4.0, (f * 0.7).cos() * 4.0, -(f % 10.0)), radius, material: material.clone(), medium: None })
+        };
+        let scene = Scene::new((0..60).map(sphere).collect());
+
+        let mut hits = 0;
+        for i in 0..200 {
+            let f = i as f64;
+            // aimed close to the center of one of the spheres
+            let target = match sphere(i % 60) { Object::Sphere(sphere) => sphere.center, _ => unreachable!() };
+            let origin = Vec3::new(0.0, 0.0, 5.0);
+            let ray = Ray::new(origin, target - origin + 0.2 * Vec3::new((f * 0.37).sin(), (f * 0.91).cos(), 0.0));
+
+            // every object tested one after the other
+            let expected = scene.objects.iter().enumerate()
+                .filter_map(|(id, object)| object.intersect(&ray, 1e-6, f64::MAX).map(|hit| (id, hit.t)))
+                .min_by(|a, b| a.1.total_cmp(&b.1));
 
-        if is_hit {
-            Some(closest_so_far)
-        } else {
-            None
+            let hit = scene.intersect(&ray, 1e-6, f64::MAX).map(|hit| (hit.object_id, hit.t));
+            assert_eq!(hit, expected);
+            hits += hit.is_some() as usize;
         }
+        assert!(hits > 100);
     }
 }
@@ -1,15 +1,18 @@
-use crate::{material::Material, ray::Ray, vec::Vec3};
-use super::{Hittable, hitinfo::HitInfo};
+use std::f64::consts::PI;
 
-#[derive(Debug, Clone, Copy)]
+use crate::{material::Material, medium::Medium, ray::Ray, sampler::Sampler, vec::Vec3};
+use super::{Hittable, bvh::Aabb, hitinfo::HitInfo};
+
+#[derive(Debug, Clone)]
 pub struct Sphere {
     pub radius: f64,
     pub center: Vec3,
-    pub material: Material
+    pub material: Material,
+    pub medium: Option<Medium>
 }
 
 impl Hittable for Sphere {
-    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitInfo> {
+    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitInfo<'_>> {
         let oc = ray.origin - self.center;
         let a = ray.dir.norm_sqared();
         let half_b = Vec3::dot(&oc, &ray.dir);
@@ -26,19 +29,79 @@ impl Hittable for Sphere {
         // yes hit
         let sqrtd = discr.sqrt();
 
-        // Find the nearest root that lies in the acceptable range.
-        let mut root = (-half_b - sqrtd) / a;
-        if root < t_min || t_max < root {
-            root = (-half_b + sqrtd) / a;
+        // Find the nearest root that lies in the acceptable range and is not cut out
+        for root in [(-half_b - sqrtd) / a, (-half_b + sqrtd) / a] {
             if root < t_min || t_max < root {
-                return None;
+                continue;
+            }
+
+            let outward_normal = (ray.at(root) - self.center).normalized();
+            let (u, v) = Sphere::uv(&outward_normal);
+
+            let mut hit = HitInfo::new(root, ray, outward_normal, &self.material, u, v);
+            (hit.dpdu, hit.dpdv) = self.derivatives(&outward_normal);
+            hit.medium = self.medium.as_ref();
+
+            if !self.material.is_cut_out(ray, &hit) {
+                return Some(hit);
             }
         }
-        
-        let outward_normal = (ray.at(root) - self.center).normalized();
 
-        let hit = HitInfo::new(root, ray, outward_normal, self.material, 0.0, 0.0);
+        None
+    }
+}
+
+impl Sphere {
+
+    pub fn bounds(&self) -> Aabb {
+        // negative radii turn spheres inside out, the box stays the same
+        let r = self.radius.abs();
+        Aabb { min: self.center - Vec3::new(r, r, r), max: self.center + Vec3::new(r, r, r) }
+    }
+
+    pub fn area(&self) -> f64 {
+        4.0 * PI * self.radius * self.radius
+    }
+
+    // uniformly distributed point on the surface, the returned hit faces outwards
+    pub fn sample_surface(&self, sampler: &mut dyn Sampler) -> HitInfo<'_> {
+        let normal = Vec3::random_unit_vector(sampler);
+        let (u, v) = Sphere::uv(&normal);
+
+        HitInfo {
+            normal,
+            t: 0.0,
+            point: self.center + self.radius * normal,
+            front_face: true,
+            material: &self.material,
+            u,
+            v,
+            dpdu: Vec3::zero(),
+            dpdv: Vec3::zero(),
+            color: None,
+            object_id: 0,
+            medium: self.medium.as_ref(),
+            wavelengths: None
+        }
+    }
+
+    // derivatives of the point along the texture coordinates, zero at the poles where they are undefined
+    fn derivatives(&self, outward_normal: &Vec3) -> (Vec3, Vec3) {
+        let n = outward_normal;
+        let sin_theta = (1.0 - n.y * n.y).max(0.0).sqrt();
+        if sin_theta < 1e-6 {
+            return (Vec3::zero(), Vec3::zero());
+        }
+
+        let dpdu = 2.0 * PI * self.radius * Vec3::new(n.z, 0.0, -n.x);
+        let dpdv = PI * self.radius * Vec3::new(-n.y * n.x / sin_theta, sin_theta, -n.y * n.z / sin_theta);
+        (dpdu, dpdv)
+    }
 
-        Some(hit)
+    // spherical texture coordinates, u goes around the y axis and v from bottom to top
+    fn uv(outward_normal: &Vec3) -> (f64, f64) {
+        let theta = (-outward_normal.y).acos();
+        let phi = (-outward_normal.z).atan2(outward_normal.x) + PI;
+        (phi / (2.0 * PI), theta / PI)
     }
 }
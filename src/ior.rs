@@ -0,0 +1,96 @@
+use crate::color::Color;
+
+// wavelength (nanometers) of the Fraunhofer d line, at which glass catalogs list the refractive index
+const D_LINE: f64 = 587.56;
+
+// Index of refraction of a dielectric. Dispersive ones depend on the wavelength of the
+// light, with the coefficients given for wavelengths in micrometers like in glass catalogs.
+#[derive(Debug, Clone, Copy)]
+pub enum Ior {
+    Constant(f64),
+    // n = a + b / lambda^2
+    Cauchy { a: f64, b: f64 },
+    // n^2 = 1 + sum of b_i lambda^2 / (lambda^2 - c_i)
+    Sellmeier { b: [f64; 3], c: [f64; 3] },
+}
+
+impl Ior {
+
+    pub fn preset(name: &str) -> Option<Ior> {
+        match name {
+            "bk7" => Some(Ior::Sellmeier {
+                b: [1.03961212, 0.231792344, 1.01046945],
+                c: [0.00600069867, 0.0200179144, 103.560653],
+            }),
+            "fused-silica" => Some(Ior::Sellmeier {
+                b: [0.6961663, 0.4079426, 0.8974794],
+                c: [0.00467914826, 0.0135120631, 97.9340025],
+            }),
+            "diamond" => Some(Ior::Sellmeier {
+                b: [0.3306, 4.3356, 0.0],
+                c: [0.030625, 0.011236, 0.0],
+            }),
+            _ => None
+        }
+    }
+
+    pub fn is_dispersive(&self) -> bool {
+        !matches!(self, Ior::Constant(_))
+    }
+
+    // index of refraction at a wavelength in nanometers
+    pub fn at(&self, lambda: f64) -> f64 {
+        let l2 = (lambda * 1e-3).powi(2);
+        match *self {
+            Ior::Constant(ior) => ior,
+            Ior::Cauchy { a, b } => a + b / l2,
+            Ior::Sellmeier { b, c } => {
+                let sum: f64 = b.iter().zip(&c).map(|(b, c)| b * l2 / (l2 - c)).sum();
+                (1.0 + sum).sqrt()
+            }
+        }
+    }
+
+    // single index used when rendering in RGB
+    pub fn representative(&self) -> f64 {
+        self.at(D_LINE)
+    }
+}
+
+// Complex index of refraction (eta + i k) of metals, at red, green and blue wavelengths
+pub fn conductor_preset(name: &str) -> Option<(Color, Color)> {
+    match name {
+        "ag" => Some((Color::new(0.155, 0.117, 0.138), Color::new(4.828, 3.122, 2.147))),
+        "al" => Some((Color::new(1.657, 0.880, 0.521), Color::new(9.224, 6.270, 4.837))),
+        "au" => Some((Color::new(0.143, 0.375, 1.442), Color::new(3.983, 2.386, 1.603))),
+        "cu" => Some((Color::new(0.200, 0.924, 1.102), Color::new(3.913, 2.453, 2.142))),
+        "cuzn" => Some((Color::new(0.444, 0.527, 1.094), Color::new(3.695, 2.765, 1.829))),
+        _ => None
+    }
+}
+
+// reflectance of a metal at normal incidence, the albedo of the metal material
+pub fn conductor_reflectance(eta: Color, k: Color) -> Color {
+    let channel = |n: f64, k: f64| ((n - 1.0).powi(2) + k * k) / ((n + 1.0).powi(2) + k * k);
+    Color::new(channel(eta.r, k.r), channel(eta.g, k.g), channel(eta.b, k.b))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_presets() {
+        let bk7 = Ior::preset("bk7").unwrap();
+        assert!((bk7.representative() - 1.5168).abs() < 1e-3);
+        assert!(bk7.at(450.0) > bk7.at(650.0));
+
+        let diamond = Ior::preset("diamond").unwrap();
+        assert!((diamond.representative() - 2.417).abs() < 0.01);
+
+        // gold reflects red more than blue
+        let (eta, k) = conductor_preset("au").unwrap();
+        let gold = conductor_reflectance(eta, k);
+        assert!(gold.r > 0.9 && gold.b < 0.5);
+    }
+}
@@ -1,107 +1,76 @@
-use crate::color::Color;
-use crate::ray::Ray;
-use image::{ImageBuffer, Rgb, RgbImage};
+use crate::film::Film;
 use indicatif::ProgressBar;
-use intersection::{scene::Scene, Hittable};
-use rayon::iter::*;
-use std::{fs, ptr::NonNull, time};
+use std::{fs, path::Path, time};
 
 mod camera;
 mod cli;
 mod color;
+mod film;
+mod gltf;
+mod grid;
+mod ies;
+mod import;
+mod integrator;
 mod intersection;
+mod ior;
 mod material;
+mod medium;
+mod merl;
+mod mitsuba;
+mod obj;
 mod parsing;
+mod pbrt;
+mod ply;
 mod ray;
+mod sampler;
+mod spectrum;
+mod stl;
 mod texture;
+mod thin_film;
+mod transform;
 mod vec;
 
 
-fn ray_color(ray: &Ray, scene: &Scene, depth: u32, max_depth: u32) -> Color {
-    
-    // if we have exceeded the depth limit no more light is gathered
-    if depth > max_depth {
-        return Color::black();
-    }
-
-    // intersect scene
-    let hit = scene.intersect(ray, 0.1, f64::MAX);
-
-    match hit {
-
-        // if hit, scatter
-        Some(hit) => {
-
-            // get hit emmision from hit
-            let emitted = hit.material.emmit(hit.u, hit.v, hit.point);
-
-            // scatter
-            match hit.material.scatter(ray, hit) {
-                
-                // if material scatters, scatter
-                Some((attenuation, scattered_ray)) => 
-                    return emitted + attenuation * ray_color(&scattered_ray, scene, depth+1, max_depth),
-
-                // else illuminate scene
-                None => emitted
-            }
-        },
-
-        // if no hit return sky color
-        // None => Color::sky(ray),
-        None => Color::black()
-    }
-}
-
 fn main() {
     // read CLI args
-    let opts = cli::read_cli();
-
-    // Construct Scene
-    let scene_json = fs::read_to_string(&opts.scene_file).unwrap();
-    let (scene, camera) = parsing::parse_scene(scene_json, opts.aspect_ratio);
-
-    // Initialize image
-    let mut img: RgbImage = ImageBuffer::new(opts.img_x, opts.img_y);
+    let mut opts = cli::read_cli();
+
+    // Construct Scene, glTF, pbrt and Mitsuba files are imported
+    let scene_file = opts.scene_file.clone();
+    let extension = Path::new(&scene_file).extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase());
+    let (scene, camera, integrator) = match extension.as_deref() {
+        Some("gltf") | Some("glb") => gltf::load(&scene_file, &opts),
+        Some("pbrt") => pbrt::load(&scene_file, &mut opts),
+        Some("xml") => mitsuba::load(&scene_file, &mut opts),
+        _ => {
+            let scene_json = fs::read_to_string(&opts.scene_file).unwrap();
+            parsing::parse_scene(scene_json, &opts)
+        }
+    };
 
-    let mut pixels: Vec<(u32, u32, &mut Rgb<u8>)> = img.enumerate_pixels_mut().collect();
+    // Initialize film
+    let film = Film::new(opts.img_x, opts.img_y);
 
     // Initialize progressbar with number of pixels
-    let bar = ProgressBar::new((opts.img_x * opts.img_y) as u64);
+    let bar = if opts.silent {
+        ProgressBar::hidden()
+    } else {
+        ProgressBar::new((opts.img_x * opts.img_y) as u64)
+    };
 
     let start_time = time::Instant::now();
 
-    // parallelized ray tracing loop
-    pixels.par_iter_mut().for_each(|tup| {
-        let x = tup.0;
-        let y = opts.img_y - 1 - tup.1;
-
-        // start with a black color
-        let mut color = Color::black();
-
-        // sample several times
-        for _ in 0..opts.num_samples {
-            let u = ((x as f64) + rand::random::<f64>()) / (opts.img_x - 1) as f64;
-            let v = ((y as f64) + rand::random::<f64>() as f64) / (opts.img_y - 1) as f64;
-
-            let secondary_ray = camera.generate_ray(u, v);
-            color += ray_color(&secondary_ray, &scene, 0, opts.max_depth);
-        }
-
-        // write pixel to image buffer
-        let final_pix = color.to_pixel(opts.num_samples);
-        *(tup.2) = final_pix;
-
-        // increment progressbar
-        bar.inc(1);
-    });
+    // parallelized ray tracing
+    integrator.render(&scene, &camera, &film, opts.num_samples, &bar);
 
     let elapsed = start_time.elapsed();
 
     // finish progressbar
     bar.finish();
-    println!("rendering took: {} seconds", elapsed.as_secs());
+    if !opts.silent {
+        println!("rendering took: {} seconds", elapsed.as_secs());
+    }
 
     // write image to file
-    img.save(opts.output_file).unwrap();
+    film.to_image(opts.num_samples).save(opts.output_file).unwrap();
 }
@@ -1,51 +1,170 @@
+use std::collections::hash_map::DefaultHasher;
+use std::f64::consts::PI;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+
 use crate::color::Color;
-use crate::intersection::hitinfo::HitInfo;
+use crate::ies::IesProfile;
+use crate::intersection::{hitinfo::HitInfo, Hittable};
+use crate::ior::Ior;
+use crate::medium::sample_henyey_greenstein;
+use crate::merl::MerlBrdf;
 use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::spectrum::rgb_to_spectrum;
 use crate::texture::Texture;
+use crate::thin_film::{Substrate, ThinFilm};
 use crate::vec::Vec3;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 pub enum Material {
     Lambertian { albedo: Texture },
-    Metal { albedo: Texture, fuzz: f64 },
-    Dielectric { ior: f64, color: Texture },
-    DiffuseLight { texture: Texture }
+    // rough diffuse surface like clay or concrete, sigma is the roughness in degrees
+    OrenNayar { albedo: Texture, sigma: Texture },
+    // diffuse surface with the sheen of cloth or velvet at grazing angles on top
+    Sheen { albedo: Texture, sheen: Texture, roughness: Texture },
+    // reflectance measured from a real material
+    Measured { brdf: Arc<MerlBrdf> },
+    // both metals and dielectrics can have a thin film coating that makes them iridescent. The
+    // fuzz is the width of the microfacet distribution of rough metals, mirrors have none
+    Metal { albedo: Texture, fuzz: f64, film: Option<ThinFilm> },
+    Dielectric { ior: Ior, color: Texture, film: Option<ThinFilm> },
+    // light enters the object, scatters around inside of it and leaves somewhere else. The
+    // albedo is the overall color and the mean free path how far light travels inside
+    Subsurface { albedo: Texture, mean_free_path: Color, g: f64 },
+    DiffuseLight { emission: Emission },
+    // dielectric coating over any other material, like varnish or the clearcoat of car paint.
+    // Light passing through the coating to the base and back is tinted on the way
+    Layered { base: Box<Material>, ior: f64, roughness: f64, thickness: f64, tint: Color },
+    // invisible surface that only marks the boundary of a medium
+    Interface,
+    // blend of two materials, the mask picks the second one with its value at the hit
+    Mix { first: Box<Material>, second: Box<Material>, mask: Texture },
+    // any other material that additionally emits light
+    Emissive { base: Box<Material>, emission: Emission },
+    // any other material with holes where the alpha texture is low, like leaves and fences.
+    // With a threshold the surface is cut out below it, otherwise the alpha is the probability
+    // of a ray hitting the surface
+    Cutout { base: Box<Material>, alpha: Texture, threshold: Option<f64> },
+    // any other material on a surface with small bumps, like hammered metal or leather. The
+    // height texture (times the strength, in scene units) offsets the surface along its normal,
+    // which needs texture coordinates that map onto the surface, like the ones of meshes
+    Bump { base: Box<Material>, height: Texture, strength: f64 }
+}
+
+#[derive(Debug, Clone)]
+pub struct Emission {
+    pub texture: Texture,
+    pub strength: f64,
+    pub profile: Option<Arc<IesProfile>>,
+    // direction the light points at, only used by the (optional) IES profile
+    pub orientation: Vec3,
+    // whether the back of the surface glows too, like it does by default
+    pub two_sided: bool,
+}
+
+impl Emission {
+    pub fn new(texture: Texture) -> Emission {
+        Emission { texture, strength: 1.0, profile: None, orientation: Vec3::new(0.0, -1.0, 0.0), two_sided: true }
+    }
+
+    pub fn radiance(&self, ray_in: &Ray, hit: &HitInfo) -> Color {
+        // one sided lights are dark when seen from the back, sampled points always face outwards
+        if !self.two_sided && (!hit.front_face || Vec3::dot(&ray_in.dir, &hit.normal) >= 0.0) {
+            return Color::black();
+        }
+        let color = self.strength * hit.spectrum(self.texture.value(hit));
+        match &self.profile {
+            Some(profile) => color * profile.intensity(self.orientation, -ray_in.dir),
+            None => color
+        }
+    }
+}
+
+// Result of sampling a material, attenuation is the bsdf times the cosine divided by the pdf
+#[derive(Debug, Clone, Copy)]
+pub struct ScatterRecord {
+    pub attenuation: Color,
+    pub ray: Ray,
+    // solid angle density of the scattered direction, zero for specular scattering
+    pub pdf: f64,
+    pub specular: bool,
 }
 
+impl ScatterRecord {
+    fn specular(attenuation: Color, ray: Ray) -> ScatterRecord {
+        ScatterRecord { attenuation, ray, pdf: 0.0, specular: true }
+    }
+}
+
+// maximum number of scattering events of a subsurface random walk
+const MAX_WALK_STEPS: u32 = 256;
+
+// step in texture coordinates for the slope of a bump map
+const BUMP_DELTA: f64 = 1e-3;
+
 impl Material {
-    pub fn scatter(self, ray_in: &Ray, hit: HitInfo) -> Option<(Color, Ray)> {
+    pub fn scatter(&self, ray_in: &Ray, hit: HitInfo, sampler: &mut dyn Sampler) -> Option<ScatterRecord> {
         match self {
-            Material::Lambertian {albedo} => {
-                let mut scatter_dir = hit.normal + Vec3::random_unit();
+            // integrators that cannot do random walks see subsurface materials as diffuse
+            Material::Lambertian { albedo } | Material::Subsurface { albedo, .. } => {
+                let scatter_dir = Vec3::random_cosine_direction(&hit.normal, sampler);
 
-                // catch degenerate scatter direction
-                if scatter_dir.near_zero() {
-                    scatter_dir = hit.normal;
+                let scattered_ray = hit.spawn_ray(scatter_dir);
+                let attenuation = hit.spectrum(albedo.value(&hit));
+                let pdf = Vec3::dot(&scatter_dir, &hit.normal) / PI;
+                Some(ScatterRecord { attenuation, ray: scattered_ray, pdf, specular: false })
+            }
+            Material::OrenNayar { .. } | Material::Sheen { .. } => {
+                let scatter_dir = Vec3::random_cosine_direction(&hit.normal, sampler);
+                let pdf = Vec3::dot(&scatter_dir, &hit.normal) / PI;
+                if pdf <= 0.0 {
+                    return None;
                 }
 
-                let scattered_ray = Ray::new(hit.point, scatter_dir);
-                let attenuation = albedo.value(hit.point);
-                Some((attenuation, scattered_ray))
+                let attenuation = self.eval(&hit, -ray_in.dir.normalized(), scatter_dir) / pdf;
+                Some(ScatterRecord { attenuation, ray: hit.spawn_ray(scatter_dir), pdf, specular: false })
             }
-            Material::Metal {albedo, fuzz} => {
-                
-                let mut reflected = reflect(&ray_in.dir, &hit.normal).normalized();
-
-                // fuzz the reflection
-                reflected += fuzz * Vec3::random_unit();
-
-                let scattered_ray = Ray::new(hit.point, reflected);
-                let attenuation = albedo.value(hit.point);
+            Material::Measured { brdf } => {
+                let wo = -ray_in.dir.normalized();
+                let scatter_dir = brdf.sample(&hit.normal, wo, sampler);
+                let pdf = brdf.pdf(&hit.normal, wo, scatter_dir);
+                if pdf <= 0.0 {
+                    return None;
+                }
 
-                let should_scatter = Vec3::dot(&scattered_ray.dir, &hit.normal) > 0.0;
+                let attenuation = self.eval(&hit, wo, scatter_dir) / pdf;
+                Some(ScatterRecord { attenuation, ray: hit.spawn_ray(scatter_dir), pdf, specular: false })
+            }
+            Material::Metal { fuzz, .. } => {
+                let wo = -ray_in.dir.normalized();
+                if *fuzz == 0.0 {
+                    let cosine = Vec3::dot(&wo, &hit.normal).clamp(0.0, 1.0);
+                    let reflected = reflect(&ray_in.dir, &hit.normal).normalized();
+                    return Some(ScatterRecord::specular(self.metal_reflectance(&hit, cosine), hit.spawn_ray(reflected)));
+                }
 
-                if should_scatter {
-                    Some((attenuation, scattered_ray))
-                } else {
-                    None
+                // rough metals reflect at a microfacet normal
+                let wi = sample_ggx(&hit.normal, wo, *fuzz, sampler);
+                let pdf = ggx_pdf(&hit.normal, wo, wi, *fuzz);
+                if Vec3::dot(&wi, &hit.normal) <= 0.0 || pdf <= 0.0 {
+                    return None;
                 }
+                Some(ScatterRecord { attenuation: self.eval(&hit, wo, wi) / pdf, ray: hit.spawn_ray(wi), pdf, specular: false })
             }
-            Material::Dielectric { ior, color} => {
+            Material::Dielectric { ior, color, film} => {
+                // a dispersive dielectric refracts every wavelength in a different direction,
+                // so only the hero wavelength of a spectral path can continue
+                let mut hit = hit;
+                let mut weight = Color::white();
+                let ior = match &mut hit.wavelengths {
+                    Some(wavelengths) if ior.is_dispersive() => {
+                        weight = wavelengths.terminate_secondary();
+                        ior.at(wavelengths.lambda[0])
+                    },
+                    _ => ior.representative()
+                };
+
                 let refraction_ratio = if hit.front_face { 1.0/ior } else { ior };
                 let unit_dir = ray_in.dir.normalized();
 
@@ -54,26 +173,490 @@ impl Material {
                 let sintheta = (1.0 - costheta*costheta).sqrt();
 
                 let cannot_refract = refraction_ratio * sintheta > 1.0;
-                let reflectance_high = reflectance(costheta, refraction_ratio) > rand::random();
+                let u = sampler.next_f64();
+
+                // with a film the reflectance differs per channel, so reflection is picked with
+                // their average and the weight makes up for the difference
+                let reflects = match film {
+                    Some(film) if !cannot_refract => {
+                        let (n1, n3) = if hit.front_face { (1.0, ior) } else { (ior, 1.0) };
+                        let reflectance = film.reflectance(&hit, costheta, n1, |_| Substrate::Dielectric(n3));
+                        let p = reflectance.average().clamp(1e-3, 1.0 - 1e-3);
+                        if u < p {
+                            weight = weight * reflectance / p;
+                            true
+                        } else {
+                            weight = weight * (Color::white() - reflectance) / (1.0 - p);
+                            false
+                        }
+                    },
+                    _ => cannot_refract || reflectance(costheta, refraction_ratio) > u
+                };
 
-                let scatter_dir = if cannot_refract || reflectance_high {
+                let scatter_dir = if reflects {
                     reflect(&unit_dir, &hit.normal)
                 } else {
                     refract(&unit_dir, &hit.normal, refraction_ratio)
                 };
 
-                let scattered = Ray::new(hit.point, scatter_dir);
-                let attenuation = color.value(hit.point);
+                let scattered = hit.spawn_ray(scatter_dir);
+                let attenuation = hit.spectrum(color.value(&hit)) * weight;
+
+                Some(ScatterRecord::specular(attenuation, scattered))
+            }
+            Material::DiffuseLight { .. } => None,
+            Material::Layered { base, ior, roughness, .. } => {
+                let wo = -ray_in.dir.normalized();
+                let p_coat = self.coat_probability(&hit, wo);
+
+                let record = if sampler.next_f64() < p_coat {
+                    // reflect off the coating
+                    if *roughness == 0.0 {
+                        let fresnel = fresnel_dielectric(Vec3::dot(&wo, &hit.normal), *ior);
+                        let dir = reflect(&ray_in.dir.normalized(), &hit.normal);
+                        return Some(ScatterRecord::specular(Color::white() * (fresnel / p_coat), hit.spawn_ray(dir)));
+                    }
+
+                    let wi = sample_ggx(&hit.normal, wo, roughness * roughness, sampler);
+                    if Vec3::dot(&wi, &hit.normal) <= 0.0 {
+                        return None;
+                    }
+                    let pdf = p_coat * ggx_pdf(&hit.normal, wo, wi, roughness * roughness);
+                    let attenuation = self.coat_eval(&hit, wo, wi) / pdf;
+                    ScatterRecord { attenuation, ray: hit.spawn_ray(wi), pdf, specular: false }
+                } else {
+                    // pass through the coating to the base and back out
+                    let record = base.scatter(ray_in, hit, sampler)?;
+                    let transmission = self.coat_transmission(&hit, wo, record.ray.dir.normalized());
+                    ScatterRecord {
+                        attenuation: record.attenuation * transmission / (1.0 - p_coat),
+                        pdf: record.pdf * (1.0 - p_coat),
+                        ..record
+                    }
+                };
+
+                // when both layers can be evaluated the combined density of sampling either is used
+                if record.specular || self.has_specular_part() {
+                    return Some(record);
+                }
+                let wi = record.ray.dir.normalized();
+                let pdf = self.pdf(&hit, wo, wi);
+                if pdf <= 0.0 {
+                    return None;
+                }
+                Some(ScatterRecord { attenuation: self.eval(&hit, wo, wi) / pdf, pdf, ..record })
+            }
+            Material::Mix { first, second, mask } => {
+                let weight = Material::mix_weight(mask, &hit);
+                let (material, p) = if sampler.next_f64() < weight { (second, weight) } else { (first, 1.0 - weight) };
+                let record = material.scatter(ray_in, hit, sampler)?;
+
+                // the probability of the pick cancels out unless the other material can be evaluated too
+                if record.specular || self.has_specular_part() {
+                    return Some(ScatterRecord { pdf: record.pdf * p, ..record });
+                }
+                let (wo, wi) = (-ray_in.dir.normalized(), record.ray.dir.normalized());
+                let pdf = self.pdf(&hit, wo, wi);
+                if pdf <= 0.0 {
+                    return None;
+                }
+                Some(ScatterRecord { attenuation: self.eval(&hit, wo, wi) / pdf, pdf, ..record })
+            }
+            Material::Interface => Some(ScatterRecord::specular(Color::white(), hit.spawn_ray(ray_in.dir))),
+            Material::Emissive { base, .. } | Material::Cutout { base, .. } => base.scatter(ray_in, hit, sampler),
+            Material::Bump { base, height, strength } => base.scatter(ray_in, Material::bumped(height, *strength, &hit), sampler)
+        }
+    }
+
+    // bsdf times the cosine with the normal, for light arriving from wi and leaving towards wo.
+    // Both directions point away from the surface, specular materials evaluate to black
+    pub fn eval(&self, hit: &HitInfo, wo: Vec3, wi: Vec3) -> Color {
+        match self {
+            Material::Lambertian { albedo } | Material::Subsurface { albedo, .. } => {
+                if !same_hemisphere(&hit.normal, &wo, &wi) {
+                    return Color::black();
+                }
+                let cosine = Vec3::dot(&wi.normalized(), &hit.normal).abs();
+                hit.spectrum(albedo.value(hit)) * (cosine / PI)
+            },
+            Material::OrenNayar { albedo, sigma } => {
+                if !same_hemisphere(&hit.normal, &wo, &wi) {
+                    return Color::black();
+                }
+                let (wo, wi) = (wo.normalized(), wi.normalized());
+                let cos_o = Vec3::dot(&wo, &hit.normal).abs();
+                let cos_i = Vec3::dot(&wi, &hit.normal).abs();
+
+                let sigma = sigma.value(hit).average().to_radians();
+                let sigma2 = sigma * sigma;
+                let a = 1.0 - sigma2 / (2.0 * (sigma2 + 0.33));
+                let b = 0.45 * sigma2 / (sigma2 + 0.09);
+
+                // cosine of the azimuth between the directions, from their projections onto the surface
+                let proj_o = wo - Vec3::dot(&wo, &hit.normal) * hit.normal;
+                let proj_i = wi - Vec3::dot(&wi, &hit.normal) * hit.normal;
+                let lengths = proj_o.norm() * proj_i.norm();
+                let cos_phi = if lengths > 1e-8 { (Vec3::dot(&proj_o, &proj_i) / lengths).max(0.0) } else { 0.0 };
+
+                // sin(alpha) tan(beta) with alpha the larger and beta the smaller angle to the normal
+                let (sin_o, sin_i) = ((1.0 - cos_o * cos_o).max(0.0).sqrt(), (1.0 - cos_i * cos_i).max(0.0).sqrt());
+                let sin_tan = if cos_i > cos_o { sin_o * sin_i / cos_i } else { sin_i * sin_o / cos_o };
+
+                hit.spectrum(albedo.value(hit)) * ((a + b * cos_phi * sin_tan) * cos_i / PI)
+            },
+            Material::Sheen { albedo, sheen, roughness } => {
+                if !same_hemisphere(&hit.normal, &wo, &wi) {
+                    return Color::black();
+                }
+                let (wo, wi) = (wo.normalized(), wi.normalized());
+                let cos_o = Vec3::dot(&wo, &hit.normal).abs();
+                let cos_i = Vec3::dot(&wi, &hit.normal).abs();
+                let cos_h = Vec3::dot(&(wo + wi).normalized(), &hit.normal).abs();
+
+                // Charlie distribution (Estevez and Kulla) with the visibility term of Neubelt and Pettineo
+                let r = roughness.value(hit).average().clamp(0.01, 1.0);
+                let sin_h = (1.0 - cos_h * cos_h).max(0.0).sqrt();
+                let distribution = (2.0 + 1.0 / r) * sin_h.powf(1.0 / r) / (2.0 * PI);
+                let visibility = 1.0 / (4.0 * (cos_i + cos_o - cos_i * cos_o));
+
+                let diffuse = hit.spectrum(albedo.value(hit)) / PI;
+                let sheen = hit.spectrum(sheen.value(hit)) * (distribution * visibility);
+                (diffuse + sheen) * cos_i
+            },
+            Material::Measured { brdf } => {
+                let cosine = Vec3::dot(&wi.normalized(), &hit.normal).max(0.0);
+                hit.spectrum(brdf.eval(&hit.normal, wo, wi)) * cosine
+            },
+            Material::Metal { fuzz, .. } if *fuzz > 0.0 => {
+                let (wo, wi) = (wo.normalized(), wi.normalized());
+                let cos_o = Vec3::dot(&wo, &hit.normal);
+                let cos_i = Vec3::dot(&wi, &hit.normal);
+                if cos_o <= 0.0 || cos_i <= 0.0 {
+                    return Color::black();
+                }
+
+                let half = (wo + wi).normalized();
+                let shadowing = ggx_g1(cos_o, *fuzz) * ggx_g1(cos_i, *fuzz);
+                let reflectance = self.metal_reflectance(hit, Vec3::dot(&wo, &half).clamp(0.0, 1.0));
+                reflectance * (ggx_d(Vec3::dot(&half, &hit.normal), *fuzz) * shadowing / (4.0 * cos_o))
+            },
+            Material::Layered { base, .. } => {
+                let (wo, wi) = (wo.normalized(), wi.normalized());
+                let transmitted = base.eval(hit, wo, wi) * self.coat_transmission(hit, wo, wi);
+                if self.has_specular_part() {
+                    transmitted
+                } else {
+                    transmitted + self.coat_eval(hit, wo, wi)
+                }
+            },
+            Material::Mix { first, second, mask } => {
+                let weight = Material::mix_weight(mask, hit);
+                first.eval(hit, wo, wi) * (1.0 - weight) + second.eval(hit, wo, wi) * weight
+            },
+            Material::Emissive { base, .. } | Material::Cutout { base, .. } => base.eval(hit, wo, wi),
+            Material::Bump { base, height, strength } => base.eval(&Material::bumped(height, *strength, hit), wo, wi),
+            _ => Color::black()
+        }
+    }
+
+    // solid angle density with which scatter samples wi given wo, zero for specular materials
+    pub fn pdf(&self, hit: &HitInfo, wo: Vec3, wi: Vec3) -> f64 {
+        match self {
+            Material::Lambertian { .. } | Material::Subsurface { .. } | Material::OrenNayar { .. } | Material::Sheen { .. } => {
+                if !same_hemisphere(&hit.normal, &wo, &wi) {
+                    return 0.0;
+                }
+                Vec3::dot(&wi.normalized(), &hit.normal).abs() / PI
+            },
+            Material::Measured { brdf } => brdf.pdf(&hit.normal, wo, wi),
+            Material::Metal { fuzz, .. } if *fuzz > 0.0 => {
+                let (wo, wi) = (wo.normalized(), wi.normalized());
+                if Vec3::dot(&wo, &hit.normal) <= 0.0 || Vec3::dot(&wi, &hit.normal) <= 0.0 {
+                    return 0.0;
+                }
+                ggx_pdf(&hit.normal, wo, wi, *fuzz)
+            },
+            Material::Layered { base, roughness, .. } => {
+                let (wo, wi) = (wo.normalized(), wi.normalized());
+                let p_coat = self.coat_probability(hit, wo);
+                let mut pdf = (1.0 - p_coat) * base.pdf(hit, wo, wi);
+                if *roughness > 0.0 && same_hemisphere(&hit.normal, &wo, &wi) {
+                    pdf += p_coat * ggx_pdf(&hit.normal, wo, wi, roughness * roughness);
+                }
+                pdf
+            },
+            Material::Mix { first, second, mask } => {
+                let weight = Material::mix_weight(mask, hit);
+                (1.0 - weight) * first.pdf(hit, wo, wi) + weight * second.pdf(hit, wo, wi)
+            },
+            Material::Emissive { base, .. } | Material::Cutout { base, .. } => base.pdf(hit, wo, wi),
+            Material::Bump { base, height, strength } => base.pdf(&Material::bumped(height, *strength, hit), wo, wi),
+            _ => 0.0
+        }
+    }
+
+    // wether the material only scatters in discrete directions, which makes eval useless
+    pub fn is_specular(&self) -> bool {
+        match self {
+            Material::Metal { fuzz, .. } => *fuzz == 0.0,
+            Material::Dielectric { .. } | Material::Interface => true,
+            Material::Layered { base, roughness, .. } => *roughness == 0.0 && base.is_specular(),
+            Material::Mix { first, second, .. } => first.is_specular() && second.is_specular(),
+            Material::Emissive { base, .. } | Material::Cutout { base, .. } | Material::Bump { base, .. } => base.is_specular(),
+            _ => false
+        }
+    }
+
+    // wether a part of a layered or mix material is specular, so the parts cannot be combined
+    fn has_specular_part(&self) -> bool {
+        match self {
+            Material::Layered { base, roughness, .. } => *roughness == 0.0 || base.is_specular(),
+            Material::Mix { first, second, .. } => first.is_specular() || second.is_specular(),
+            _ => false
+        }
+    }
+
+    // color a metal reflects at the cosine between the light and the (microfacet) normal
+    fn metal_reflectance(&self, hit: &HitInfo, cosine: f64) -> Color {
+        let (albedo, film) = match self {
+            Material::Metal { albedo, film, .. } => (albedo.value(hit), film),
+            _ => return Color::black()
+        };
+        match film {
+            Some(film) => film.reflectance(hit, cosine, 1.0, |lambda| Substrate::Metal(rgb_to_spectrum(albedo, lambda))),
+            None => hit.spectrum(albedo)
+        }
+    }
+
+    // weight of the second material of a mix at the hit
+    fn mix_weight(mask: &Texture, hit: &HitInfo) -> f64 {
+        mask.value(hit).average().clamp(0.0, 1.0)
+    }
+
+    // Probability of sampling the coating of a layered material instead of the base, the amount
+    // of light each of them reflects. wo points away from the surface
+    fn coat_probability(&self, hit: &HitInfo, wo: Vec3) -> f64 {
+        let (base, ior, thickness, tint) = match self {
+            Material::Layered { base, ior, thickness, tint, .. } => (base, *ior, *thickness, *tint),
+            _ => return 0.0
+        };
+
+        let fresnel = fresnel_dielectric(Vec3::dot(&wo, &hit.normal).abs(), ior);
+        let base_reflectance = (1.0 - fresnel) * base.albedo(hit).average() * tint.average().powf(2.0 * thickness);
+        if fresnel + base_reflectance <= 0.0 {
+            return 0.5;
+        }
+        fresnel / (fresnel + base_reflectance)
+    }
+
+    // glossy reflection off the rough coating of a layered material, times the cosine
+    fn coat_eval(&self, hit: &HitInfo, wo: Vec3, wi: Vec3) -> Color {
+        let (ior, roughness) = match self {
+            Material::Layered { ior, roughness, .. } => (*ior, *roughness),
+            _ => return Color::black()
+        };
+
+        let cos_o = Vec3::dot(&wo, &hit.normal);
+        let cos_i = Vec3::dot(&wi, &hit.normal);
+        if cos_o <= 0.0 || cos_i <= 0.0 {
+            return Color::black();
+        }
+
+        let alpha = roughness * roughness;
+        let half = (wo + wi).normalized();
+        let fresnel = fresnel_dielectric(Vec3::dot(&wo, &half), ior);
+        let shadowing = ggx_g1(cos_o, alpha) * ggx_g1(cos_i, alpha);
+        Color::white() * (fresnel * ggx_d(Vec3::dot(&half, &hit.normal), alpha) * shadowing / (4.0 * cos_o))
+    }
+
+    // Fraction of light that enters the coating of a layered material from wi, reaches the base
+    // and leaves towards wo, or the other way around
+    fn coat_transmission(&self, hit: &HitInfo, wo: Vec3, wi: Vec3) -> Color {
+        let (ior, thickness, tint) = match self {
+            Material::Layered { ior, thickness, tint, .. } => (*ior, *thickness, *tint),
+            _ => return Color::white()
+        };
+
+        let cos_o = Vec3::dot(&wo, &hit.normal).abs();
+        let cos_i = Vec3::dot(&wi, &hit.normal).abs();
+        let fresnel = (1.0 - fresnel_dielectric(cos_o, ior)) * (1.0 - fresnel_dielectric(cos_i, ior));
+
+        // the tint is the color after passing straight through a coating of thickness 1, the
+        // path through the coating is longer for light refracted at an angle
+        let refracted = |cosine: f64| (1.0 - (1.0 - cosine * cosine) / (ior * ior)).max(0.0).sqrt();
+        let distance = thickness * (1.0 / refracted(cos_o) + 1.0 / refracted(cos_i));
+        let tint = hit.spectrum(tint);
+        Color::new(tint.r.powf(distance), tint.g.powf(distance), tint.b.powf(distance)) * fresnel
+    }
+
+    // The hit with the shading normal of the bump mapped surface, found from the slopes of the
+    // height texture. Without derivatives along the texture coordinates the hit is unchanged
+    fn bumped<'a>(height: &Texture, strength: f64, hit: &HitInfo<'a>) -> HitInfo<'a> {
+        if hit.dpdu.near_zero() || hit.dpdv.near_zero() {
+            return *hit;
+        }
+
+        let displacement = |u: f64, v: f64| strength * height.value(&HitInfo { u, v, ..*hit }).average();
+        let center = displacement(hit.u, hit.v);
+        let dpdu = hit.dpdu + (displacement(hit.u + BUMP_DELTA, hit.v) - center) / BUMP_DELTA * hit.normal;
+        let dpdv = hit.dpdv + (displacement(hit.u, hit.v + BUMP_DELTA) - center) / BUMP_DELTA * hit.normal;
+
+        let normal = Vec3::cross(&dpdu, &dpdv).normalized();
+        let normal = if Vec3::dot(&normal, &hit.normal) < 0.0 { -normal } else { normal };
+        HitInfo { normal, ..*hit }
+    }
+
+    // Enters the object at the hit and walks through its inside until the walk leaves it
+    // again. Returns the ray leaving the object, the attenuation includes the whole walk
+    pub fn random_walk(&self, hit: HitInfo, object: &dyn Hittable, sampler: &mut dyn Sampler) -> Option<ScatterRecord> {
+        let (albedo, mean_free_path, g) = match self {
+            Material::Subsurface { albedo, mean_free_path, g } => (albedo, mean_free_path, *g),
+            Material::Emissive { base, .. } | Material::Cutout { base, .. } | Material::Bump { base, .. } => return base.random_walk(hit, object, sampler),
+            _ => return None
+        };
+
+        // the albedo is the color after many scattering events
+        let color = hit.spectrum(albedo.value(&hit));
+        let mean_free_path = hit.spectrum(*mean_free_path);
+        let extinction = [1.0 / mean_free_path.r, 1.0 / mean_free_path.g, 1.0 / mean_free_path.b];
+        let single_albedo = [single_scattering_albedo(color.r), single_scattering_albedo(color.g), single_scattering_albedo(color.b)];
 
-                Some((attenuation, scattered))
+        // enter with a diffuse transmission, which does not change the weight
+        let mut ray = hit.spawn_ray(Vec3::random_cosine_direction(&(-hit.normal), sampler));
+        let mut weight = [1.0; 3];
+
+        for _ in 0..MAX_WALK_STEPS {
+            // numerical problems can let the walk escape, give up on it then
+            let boundary = object.intersect(&ray, 1e-6, f64::MAX)?;
+
+            // sample the distance with the extinction of one channel, picked according to the
+            // weights. The weights are then divided by the combined density of all channels
+            let total: f64 = weight.iter().sum();
+            if total <= 0.0 {
+                return None;
             }
-            Material::DiffuseLight { texture: _ } => None
+            let probabilities = [weight[0] / total, weight[1] / total, weight[2] / total];
+            let u = sampler.next_f64();
+            let channel = if u < probabilities[0] { 0 } else if u < probabilities[0] + probabilities[1] { 1 } else { 2 };
+            let distance = -(1.0 - sampler.next_f64()).ln() / extinction[channel];
+
+            let t = distance.min(boundary.t);
+            let transmittance = [(-extinction[0] * t).exp(), (-extinction[1] * t).exp(), (-extinction[2] * t).exp()];
+
+            if distance < boundary.t {
+                let pdf: f64 = (0..3).map(|c| probabilities[c] * extinction[c] * transmittance[c]).sum();
+                for c in 0..3 {
+                    weight[c] *= single_albedo[c] * extinction[c] * transmittance[c] / pdf;
+                }
+                ray = Ray { origin: ray.at(t), dir: sample_henyey_greenstein(g, ray.dir, sampler), ..ray };
+            } else {
+                let pdf: f64 = (0..3).map(|c| probabilities[c] * transmittance[c]).sum();
+                for c in 0..3 {
+                    weight[c] *= transmittance[c] / pdf;
+                }
+
+                // leave with a diffuse transmission, the hit normal points inside
+                let dir = Vec3::random_cosine_direction(&(-boundary.normal), sampler);
+                let pdf = Vec3::dot(&dir, &(-boundary.normal)) / PI;
+                let attenuation = Color::new(weight[0], weight[1], weight[2]);
+                return Some(ScatterRecord { attenuation, ray: boundary.spawn_ray(dir), pdf, specular: false });
+            }
+        }
+
+        None
+    }
+
+    // wether the material, or one of the materials of a mix, walks through the inside of the object
+    pub fn is_subsurface(&self) -> bool {
+        match self {
+            Material::Subsurface { .. } => true,
+            Material::Mix { first, second, .. } => first.is_subsurface() || second.is_subsurface(),
+            Material::Emissive { base, .. } | Material::Cutout { base, .. } | Material::Bump { base, .. } => base.is_subsurface(),
+            _ => false
+        }
+    }
+
+    // The material that scatters at the hit, with the hit it scatters at. Mixes with a subsurface
+    // part pick one of their materials by the mask, so only that one walks through the object
+    pub fn pick<'a>(&'a self, hit: HitInfo<'a>, sampler: &mut dyn Sampler) -> (&'a Material, HitInfo<'a>) {
+        if !self.is_subsurface() {
+            return (self, hit);
+        }
+        match self {
+            Material::Mix { first, second, mask } => {
+                let material = if sampler.next_f64() < Material::mix_weight(mask, &hit) { second } else { first };
+                material.pick(hit, sampler)
+            },
+            Material::Emissive { base, .. } | Material::Cutout { base, .. } => base.pick(hit, sampler),
+            Material::Bump { base, height, strength } => base.pick(Material::bumped(height, *strength, &hit), sampler),
+            _ => (self, hit)
+        }
+    }
+
+    // Wether the ray misses the surface at the hit because of a cutout. Fractional alpha lets rays
+    // through at random, hashing the ray and the hit gives the same answer when it is traced again
+    pub fn is_cut_out(&self, ray: &Ray, hit: &HitInfo) -> bool {
+        match self {
+            Material::Cutout { base, alpha, threshold } => {
+                let alpha = alpha.value(hit).average();
+                let cut = match threshold {
+                    Some(threshold) => alpha < *threshold,
+                    None => alpha < 1.0 && hash(&[ray.origin, ray.dir, hit.point]) >= alpha
+                };
+                cut || base.is_cut_out(ray, hit)
+            },
+            Material::Emissive { base, .. } | Material::Bump { base, .. } => base.is_cut_out(ray, hit),
+            // the same material as for the fractional alpha, picked with a differently ordered hash
+            Material::Mix { first, second, mask } => {
+                let material = if hash(&[hit.point, ray.dir, ray.origin]) < Material::mix_weight(mask, hit) { second } else { first };
+                material.is_cut_out(ray, hit)
+            },
+            _ => false
+        }
+    }
+
+    pub fn is_interface(&self) -> bool {
+        matches!(self, Material::Interface)
+    }
+
+    pub fn is_emissive(&self) -> bool {
+        match self {
+            Material::DiffuseLight { .. } | Material::Emissive { .. } => true,
+            Material::Mix { first, second, .. } => first.is_emissive() || second.is_emissive(),
+            Material::Cutout { base, .. } | Material::Bump { base, .. } => base.is_emissive(),
+            _ => false
         }
     }
-    pub fn emmit(self, _u: f64, _v: f64, p: Vec3) -> Color {
+
+    // base color of the material, used by the debug integrators
+    pub fn albedo(&self, hit: &HitInfo) -> Color {
         match self {
-            Material::DiffuseLight { texture }=> {
-                texture.value(p)
+            Material::Lambertian { albedo } => albedo.value(hit),
+            Material::OrenNayar { albedo, .. } | Material::Sheen { albedo, .. } => albedo.value(hit),
+            Material::Metal { albedo, .. } => albedo.value(hit),
+            Material::Dielectric { color, .. } => color.value(hit),
+            Material::Subsurface { albedo, .. } => albedo.value(hit),
+            // reflectance of a lambertian surface looking like it when seen from above
+            Material::Measured { brdf } => brdf.eval(&hit.normal, hit.normal, hit.normal) * PI,
+            Material::DiffuseLight { emission } => emission.texture.value(hit),
+            Material::Interface => Color::white(),
+            Material::Layered { base, .. } => base.albedo(hit),
+            Material::Mix { first, second, mask } => {
+                let weight = Material::mix_weight(mask, hit);
+                first.albedo(hit) * (1.0 - weight) + second.albedo(hit) * weight
+            },
+            Material::Emissive { base, .. } | Material::Cutout { base, .. } | Material::Bump { base, .. } => base.albedo(hit)
+        }
+    }
+
+    pub fn emmit(&self, ray_in: &Ray, hit: &HitInfo) -> Color {
+        match self {
+            Material::DiffuseLight { emission } => emission.radiance(ray_in, hit),
+            Material::Emissive { base, emission } => emission.radiance(ray_in, hit) + base.emmit(ray_in, hit),
+            Material::Cutout { base, .. } | Material::Bump { base, .. } => base.emmit(ray_in, hit),
+            Material::Mix { first, second, mask } => {
+                let weight = Material::mix_weight(mask, hit);
+                first.emmit(ray_in, hit) * (1.0 - weight) + second.emmit(ray_in, hit) * weight
             },
             _ => Color::black()
         }
@@ -84,18 +667,292 @@ impl Material {
 
 // private helper functions
 
+// The single scattering albedo of a medium whose albedo after many scattering events is the
+// given one, inverting the approximation of van de Hulst (as fitted by Cycles)
+fn single_scattering_albedo(albedo: f64) -> f64 {
+    let a = albedo.clamp(0.0, 0.999);
+    1.0 - (4.09712 + 4.20863 * a - (9.59217 + 41.6808 * a + 17.7126 * a * a).sqrt()).powi(2)
+}
+
 fn reflect(vec: &Vec3, normal: &Vec3) -> Vec3{
     *vec - 2.0 * Vec3::dot(vec, normal) * *normal
 }
 
+fn same_hemisphere(normal: &Vec3, wo: &Vec3, wi: &Vec3) -> bool {
+    Vec3::dot(normal, wo) * Vec3::dot(normal, wi) > 0.0
+}
+
 fn reflectance(cosine: f64, ref_idx: f64) -> f64 {
     let r0 = ((1.0-ref_idx) / (1.0+ref_idx)).powi(2);
     r0 + (1.0-r0)*(1.0 - cosine).powi(5)
 }
 
+// deterministic pseudo random number in [0, 1) for the given vectors
+fn hash(vectors: &[Vec3]) -> f64 {
+    let mut hasher = DefaultHasher::new();
+    for v in vectors {
+        v.x.to_bits().hash(&mut hasher);
+        v.y.to_bits().hash(&mut hasher);
+        v.z.to_bits().hash(&mut hasher);
+    }
+    (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+// fresnel reflectance of unpolarized light arriving from the outside of a dielectric
+fn fresnel_dielectric(cos_i: f64, ior: f64) -> f64 {
+    let cos_i = cos_i.clamp(0.0, 1.0);
+    let sin_t = (1.0 - cos_i * cos_i).sqrt() / ior;
+    let cos_t = (1.0 - sin_t * sin_t).sqrt();
+
+    let r_s = (cos_i - ior * cos_t) / (cos_i + ior * cos_t);
+    let r_p = (ior * cos_i - cos_t) / (ior * cos_i + cos_t);
+    0.5 * (r_s * r_s + r_p * r_p)
+}
+
+// GGX (Trowbridge-Reitz) distribution of microfacet normals
+fn ggx_d(cos_h: f64, alpha: f64) -> f64 {
+    if cos_h <= 0.0 {
+        return 0.0;
+    }
+    let a2 = alpha * alpha;
+    let d = cos_h * cos_h * (a2 - 1.0) + 1.0;
+    a2 / (PI * d * d)
+}
+
+// Smith masking of the GGX distribution for one direction
+fn ggx_g1(cosine: f64, alpha: f64) -> f64 {
+    let a2 = alpha * alpha;
+    2.0 * cosine / (cosine + (a2 + (1.0 - a2) * cosine * cosine).sqrt())
+}
+
+// reflects wo at a microfacet normal sampled proportional to its distribution times its cosine
+fn sample_ggx(normal: &Vec3, wo: Vec3, alpha: f64, sampler: &mut dyn Sampler) -> Vec3 {
+    let u = sampler.next_f64();
+    let phi = 2.0 * PI * sampler.next_f64();
+    let cos_theta = ((1.0 - u) / (1.0 + (alpha * alpha - 1.0) * u)).sqrt();
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+
+    let (tangent, bitangent) = normal.orthonormal_basis();
+    let half = sin_theta * phi.cos() * tangent + sin_theta * phi.sin() * bitangent + cos_theta * *normal;
+    reflect(&(-wo), &half)
+}
+
+// solid angle density of sample_ggx returning wi
+fn ggx_pdf(normal: &Vec3, wo: Vec3, wi: Vec3, alpha: f64) -> f64 {
+    let half = (wo + wi).normalized();
+    let cos_o_h = Vec3::dot(&wo, &half);
+    if cos_o_h <= 0.0 {
+        return 0.0;
+    }
+    ggx_d(Vec3::dot(&half, normal), alpha) * Vec3::dot(&half, normal) / (4.0 * cos_o_h)
+}
+
 fn refract(vec: &Vec3, normal: &Vec3, etai_over_etat: f64) -> Vec3 {
     let costheta = f64::min(1.0, Vec3::dot(&(-(*vec)), normal));
     let r_out_perp = etai_over_etat * (*vec + costheta * *normal);
     let r_out_parallel = -(1.0 - r_out_perp.norm_sqared()).sqrt() * *normal;
     r_out_perp + r_out_parallel
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::intersection::sphere::Sphere;
+    use crate::sampler::RandomSampler;
+    use crate::texture::ImageTexture;
+
+    #[test]
+    fn test_bumped_normal() {
+        // the height grows along u, from black to white
+        let image = image::RgbaImage::from_fn(4, 1, |x, _| image::Rgba([(x * 85) as u8, (x * 85) as u8, (x * 85) as u8, 255]));
+        let height = Texture::Image { image: Arc::new(ImageTexture::from_image(&image, false)) };
+        let material = Material::Lambertian { albedo: Texture::Constant { color: Color::white() } };
+
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let mut hit = HitInfo::new(1.0, &ray, Vec3::new(0.0, 0.0, 1.0), &material, 0.5, 0.5);
+
+        // without derivatives the surface can not be bumped
+        assert!(Material::bumped(&height, 1.0, &hit).normal.is_close(&hit.normal));
+
+        // the normal leans away from the slope
+        hit.dpdu = Vec3::new(1.0, 0.0, 0.0);
+        hit.dpdv = Vec3::new(0.0, 1.0, 0.0);
+        let bumped = Material::bumped(&height, 0.1, &hit);
+        assert!(bumped.normal.x < -0.01 && bumped.normal.z > 0.0);
+        assert!(bumped.normal.y.abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_mix_forwarding() {
+        let white = || Texture::Constant { color: Color::white() };
+        let lambertian = Material::Lambertian { albedo: white() };
+        let cutout = Material::Cutout { base: Box::new(lambertian.clone()), alpha: Texture::Constant { color: Color::black() }, threshold: Some(0.5) };
+        let subsurface = Material::Subsurface { albedo: white(), mean_free_path: Color::white(), g: 0.0 };
+        let mix = |first: &Material, second: &Material, weight: f64| Material::Mix {
+            first: Box::new(first.clone()),
+            second: Box::new(second.clone()),
+            mask: Texture::Constant { color: Color::new(weight, weight, weight) }
+        };
+
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit = HitInfo::new(1.0, &ray, Vec3::new(0.0, 0.0, 1.0), &lambertian, 0.5, 0.5);
+        let mut sampler = RandomSampler::new();
+
+        // the mask picks the cut out material
+        assert!(mix(&lambertian, &cutout, 1.0).is_cut_out(&ray, &hit));
+        assert!(!mix(&lambertian, &cutout, 0.0).is_cut_out(&ray, &hit));
+
+        // and the material that walks through the object
+        let mixed = mix(&lambertian, &subsurface, 1.0);
+        assert!(mixed.is_subsurface());
+        assert!(matches!(mixed.pick(hit, &mut sampler).0, Material::Subsurface { .. }));
+        let mixed = mix(&lambertian, &subsurface, 0.0);
+        assert!(matches!(mixed.pick(hit, &mut sampler).0, Material::Lambertian { .. }));
+    }
+
+    // Average throughput of the scattered rays for light leaving towards wo, checking that every
+    // sampled direction that is not specular has the weight and density that eval and pdf give it
+    fn directional_albedo(material: &Material, wo: Vec3) -> f64 {
+        let wo = wo.normalized();
+        let ray = Ray::new(wo, -wo);
+        let hit = HitInfo::new(1.0, &ray, Vec3::new(0.0, 0.0, 1.0), material, 0.5, 0.5);
+        let mut sampler = RandomSampler::new();
+
+        let samples = 20000;
+        let mut total = 0.0;
+        for _ in 0..samples {
+            let record = match material.scatter(&ray, hit, &mut sampler) {
+                Some(record) => record,
+                None => continue
+            };
+            total += record.attenuation.average();
+            if record.specular {
+                continue;
+            }
+            let wi = record.ray.dir.normalized();
+            let pdf = material.pdf(&hit, wo, wi);
+            let expected = material.eval(&hit, wo, wi) / pdf;
+            assert!((record.pdf - pdf).abs() <= 1e-6 * pdf, "pdf {} instead of {}", record.pdf, pdf);
+            assert!((record.attenuation.r - expected.r).abs() <= 1e-6 * expected.r, "weight {:?} instead of {:?}", record.attenuation, expected);
+        }
+        total / samples as f64
+    }
+
+    // the same albedo from eval alone, integrated over uniformly distributed directions. It only
+    // matches the one of scatter if that samples directions with the density pdf gives
+    fn integrated_albedo(material: &Material, wo: Vec3) -> f64 {
+        let normal = Vec3::new(0.0, 0.0, 1.0);
+        let wo = wo.normalized();
+        let ray = Ray::new(wo, -wo);
+        let hit = HitInfo::new(1.0, &ray, normal, material, 0.5, 0.5);
+        let mut sampler = RandomSampler::new();
+
+        let samples = 200000;
+        let total: f64 = (0..samples)
+            .map(|_| material.eval(&hit, wo, Vec3::random_cone_direction(&normal, 0.0, &mut sampler)).average() * 2.0 * PI)
+            .sum();
+        total / samples as f64
+    }
+
+    #[test]
+    fn test_rough_metal() {
+        let metal = |fuzz: f64| Material::Metal { albedo: Texture::Constant { color: Color::white() }, fuzz, film: None };
+        assert!(metal(0.0).is_specular());
+        assert!(!metal(0.3).is_specular());
+
+        // a white rough metal reflects most of the light, a bit is lost to microfacets that
+        // shadow each other, more so at grazing angles
+        let head_on = directional_albedo(&metal(0.3), Vec3::new(0.0, 0.0, 1.0));
+        let grazing = directional_albedo(&metal(0.3), Vec3::new(1.0, 0.0, 0.1));
+        assert!(head_on > 0.8 && head_on <= 1.0, "albedo {}", head_on);
+        assert!(grazing < head_on, "albedo {} at a grazing angle", grazing);
+    }
+
+    #[test]
+    fn test_single_scattering_albedo() {
+        // van de Hulst's albedo after many scattering events, for a single scattering albedo
+        let multiple = |single: f64| {
+            let s = (1.0 - single).sqrt();
+            (1.0 - s) * (1.0 - 0.139 * s) / (1.0 + 1.17 * s)
+        };
+        for i in 0..=99 {
+            let albedo = i as f64 / 100.0;
+            let single = single_scattering_albedo(albedo);
+            assert!((0.0..=1.0).contains(&single) && single >= albedo);
+            assert!((multiple(single) - albedo).abs() < 1e-4, "albedo {} became {}", albedo, multiple(single));
+        }
+    }
+
+    #[test]
+    fn test_random_walk_exits() {
+        // a white sphere many mean free paths across, so the walks scatter a lot inside of it
+        let material = Material::Subsurface { albedo: Texture::Constant { color: Color::white() }, mean_free_path: Color::new(0.2, 0.2, 0.2), g: 0.0 };
+        let sphere = Sphere { center: Vec3::zero(), radius: 1.0, material: material.clone(), medium: None };
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit = sphere.intersect(&ray, 1e-6, f64::MAX).unwrap();
+        let mut sampler = RandomSampler::new();
+
+        // light is (nearly) never absorbed, so every walk leaves the sphere again, outwards and
+        // with most of its weight
+        for _ in 0..200 {
+            let record = material.random_walk(hit, &sphere, &mut sampler).expect("the walk did not leave the sphere");
+            assert!((record.ray.origin.norm() - 1.0).abs() < 1e-3);
+            assert!(Vec3::dot(&record.ray.dir, &record.ray.origin) > 0.0);
+            assert!(record.attenuation.r > 0.7 && record.attenuation.r < 1.0 + 1e-9, "weight {:?}", record.attenuation);
+        }
+    }
+
+    #[test]
+    fn test_layered_energy() {
+        let layered = |roughness: f64| Material::Layered {
+            base: Box::new(Material::Lambertian { albedo: Texture::Constant { color: Color::white() } }),
+            ior: 1.5,
+            roughness,
+            thickness: 1.0,
+            tint: Color::white()
+        };
+
+        for wo in [Vec3::new(0.0, 0.0, 1.0), Vec3::new(1.0, 0.0, 1.0), Vec3::new(1.0, 0.0, 0.2)] {
+            // a coating can not add light to what the base reflects
+            let albedo = directional_albedo(&layered(0.5), wo);
+            assert!(albedo > 0.5 && albedo < 1.01, "albedo {}", albedo);
+            let smooth = directional_albedo(&layered(0.0), wo);
+            assert!(smooth > 0.5 && smooth < 1.01, "albedo {} with a smooth coating", smooth);
+
+            // and both layers are sampled with the density pdf gives them
+            let integrated = integrated_albedo(&layered(0.5), wo);
+            assert!((albedo - integrated).abs() < 0.03, "albedo {} from sampling, {} from eval", albedo, integrated);
+        }
+    }
+
+    #[test]
+    fn test_rough_diffuse_energy() {
+        let white = || Texture::Constant { color: Color::white() };
+        let gray = |value: f64| Texture::Constant { color: Color::new(value, value, value) };
+        let oren_nayar = |sigma: f64| Material::OrenNayar { albedo: white(), sigma: gray(sigma) };
+        let sheen = |albedo: f64, roughness: f64| Material::Sheen { albedo: gray(albedo), sheen: white(), roughness: gray(roughness) };
+
+        // neither reflects more light than arrives, and both are sampled with the density pdf gives them
+        for wo in [Vec3::new(0.0, 0.0, 1.0), Vec3::new(1.0, 0.0, 1.0), Vec3::new(1.0, 0.0, 0.2)] {
+            for material in [oren_nayar(30.0), sheen(0.0, 0.3), sheen(0.0, 1.0), sheen(0.5, 0.5)] {
+                let albedo = directional_albedo(&material, wo);
+                let integrated = integrated_albedo(&material, wo);
+                assert!(albedo < 1.01, "albedo {} of {:?}", albedo, material);
+                assert!((albedo - integrated).abs() < 0.03, "albedo {} from sampling, {} from eval of {:?}", albedo, integrated, material);
+            }
+        }
+
+        // a perfectly smooth Oren-Nayar surface is Lambertian
+        let lambertian = Material::Lambertian { albedo: white() };
+        let smooth = oren_nayar(0.0);
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit = HitInfo::new(1.0, &ray, Vec3::new(0.0, 0.0, 1.0), &lambertian, 0.5, 0.5);
+        let mut sampler = RandomSampler::new();
+        for _ in 0..100 {
+            let wo = Vec3::random_cosine_direction(&hit.normal, &mut sampler);
+            let wi = Vec3::random_cosine_direction(&hit.normal, &mut sampler);
+            assert!(smooth.eval(&hit, wo, wi).is_close(&lambertian.eval(&hit, wo, wi)));
+            assert!((smooth.pdf(&hit, wo, wi) - lambertian.pdf(&hit, wo, wi)).abs() < 1e-12);
+        }
+    }
+}
@@ -0,0 +1,190 @@
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+use crate::color::Color;
+use crate::grid::DensityGrid;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::vec::Vec3;
+
+// Participating medium filling the inside of an object or the whole world. Light travelling
+// through it is absorbed and scattered according to the coefficients (per unit of distance)
+// times the density, the direction of scattered light follows a Henyey-Greenstein phase function.
+#[derive(Debug, Clone)]
+pub struct Medium {
+    pub absorption: Color,
+    pub scattering: Color,
+    // density of the medium, constant 1 without a grid
+    pub grid: Option<Arc<DensityGrid>>,
+    // anisotropy of the phase function, negative values scatter backwards and positive forwards
+    pub g: f64,
+}
+
+// What happens to a ray travelling through a medium before it reaches the next surface
+pub enum MediumEvent {
+    Scatter { point: Vec3, weight: Color },
+    Absorb,
+    Pass { weight: Color },
+}
+
+impl Medium {
+
+    // coefficients as seen by the ray, which in spectral mode are their spectra at its wavelengths
+    fn coefficients(&self, ray: &Ray) -> (Color, Color) {
+        match ray.wavelengths {
+            Some(wavelengths) => (wavelengths.upsample(self.absorption), wavelengths.upsample(self.scattering)),
+            None => (self.absorption, self.scattering)
+        }
+    }
+
+    fn density(&self, point: Vec3) -> f64 {
+        match &self.grid {
+            Some(grid) => grid.density(point),
+            None => 1.0
+        }
+    }
+
+    // Splits the first t_max of the ray into segments (t_start, t_end, majorant), where the
+    // majorant bounds the extinction inside the segment and is the density of tentative collisions
+    fn majorant_segments(&self, ray: &Ray, t_max: f64) -> Vec<(f64, f64, f64)> {
+        let (absorption, scattering) = self.coefficients(ray);
+        let extinction = (absorption + scattering).max_component();
+        match &self.grid {
+            Some(grid) => grid
+                .majorant_segments(ray, t_max)
+                .into_iter()
+                .map(|(t_start, t_end, density)| (t_start, t_end, density * extinction))
+                .collect(),
+            None => vec![(0.0, t_max, extinction)]
+        }
+    }
+
+    // Finds the first real collision along the (normalized) ray before t_max using delta
+    // tracking. The collision type is chosen with the average of the color channels, the
+    // weights correct for the channels that are more or less likely than that.
+    pub fn sample_interaction(&self, ray: &Ray, t_max: f64, sampler: &mut dyn Sampler) -> MediumEvent {
+        let (absorption, scattering) = self.coefficients(ray);
+        let mut weight = Color::white();
+
+        for (t_start, t_end, majorant) in self.majorant_segments(ray, t_max) {
+            if majorant <= 0.0 {
+                continue;
+            }
+
+            let mut t = t_start;
+            loop {
+                t -= (1.0 - sampler.next_f64()).ln() / majorant;
+                if t >= t_end {
+                    break;
+                }
+
+                let point = ray.at(t);
+                let density = self.density(point);
+                let absorption = absorption * density;
+                let scattering = scattering * density;
+                let null = Color::new(majorant, majorant, majorant) - absorption - scattering;
+
+                let p_absorb = absorption.average() / majorant;
+                let p_scatter = scattering.average() / majorant;
+                let p_null = null.average() / majorant;
+
+                let u = sampler.next_f64();
+                if u < p_absorb {
+                    return MediumEvent::Absorb;
+                } else if u < p_absorb + p_scatter {
+                    return MediumEvent::Scatter { point, weight: weight * scattering / (majorant * p_scatter) };
+                } else if p_null > 0.0 {
+                    weight = weight * null / (majorant * p_null);
+                }
+            }
+        }
+
+        MediumEvent::Pass { weight }
+    }
+
+    // fraction of light that makes it through the first t_max of the (normalized) ray, estimated with ratio tracking
+    pub fn transmittance(&self, ray: &Ray, t_max: f64, sampler: &mut dyn Sampler) -> Color {
+        let (absorption, scattering) = self.coefficients(ray);
+        let mut transmittance = Color::white();
+
+        for (t_start, t_end, majorant) in self.majorant_segments(ray, t_max) {
+            if majorant <= 0.0 {
+                continue;
+            }
+
+            let mut t = t_start;
+            loop {
+                t -= (1.0 - sampler.next_f64()).ln() / majorant;
+                if t >= t_end {
+                    break;
+                }
+
+                let density = self.density(ray.at(t));
+                let null = Color::new(majorant, majorant, majorant) - (absorption + scattering) * density;
+                transmittance = transmittance * null / majorant;
+                if transmittance.max_component() <= 0.0 {
+                    return Color::black();
+                }
+            }
+        }
+
+        transmittance
+    }
+
+    pub fn phase(&self, dir_in: Vec3, dir_out: Vec3) -> f64 {
+        henyey_greenstein(self.g, dir_in, dir_out)
+    }
+
+    pub fn sample_phase(&self, dir_in: Vec3, sampler: &mut dyn Sampler) -> Vec3 {
+        sample_henyey_greenstein(self.g, dir_in, sampler)
+    }
+}
+
+// density of scattering light travelling along dir_in into dir_out, both normalized
+pub fn henyey_greenstein(g: f64, dir_in: Vec3, dir_out: Vec3) -> f64 {
+    let denominator = 1.0 + g * g - 2.0 * g * Vec3::dot(&dir_in, &dir_out);
+    (1.0 - g * g) / (4.0 * PI * denominator * denominator.sqrt())
+}
+
+// samples a scattered direction proportional to the phase function
+pub fn sample_henyey_greenstein(g: f64, dir_in: Vec3, sampler: &mut dyn Sampler) -> Vec3 {
+    let u = sampler.next_f64();
+
+    let cos_theta = if g.abs() < 1e-3 {
+        1.0 - 2.0 * u
+    } else {
+        let s = (1.0 - g * g) / (1.0 - g + 2.0 * g * u);
+        ((1.0 + g * g - s * s) / (2.0 * g)).clamp(-1.0, 1.0)
+    };
+
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * PI * sampler.next_f64();
+    let (tangent, bitangent) = dir_in.orthonormal_basis();
+
+    sin_theta * phi.cos() * tangent + sin_theta * phi.sin() * bitangent + cos_theta * dir_in
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::sampler::RandomSampler;
+
+    #[test]
+    fn test_transmittance() {
+        let medium = Medium { absorption: Color::new(0.5, 1.0, 0.0), scattering: Color::new(0.5, 0.0, 0.0), grid: None, g: 0.0 };
+        let ray = Ray::new(Vec3::zero(), Vec3::new(1.0, 0.0, 0.0));
+        let mut sampler = RandomSampler::new();
+
+        let n = 10000;
+        let mut sum = Color::black();
+        for _ in 0..n {
+            sum += medium.transmittance(&ray, 1.0, &mut sampler);
+        }
+        let estimate = sum / n as f64;
+
+        let expected = (-1.0f64).exp();
+        assert!((estimate.r - expected).abs() < 0.02);
+        assert!((estimate.g - expected).abs() < 0.02);
+        assert!((estimate.b - 1.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,202 @@
+use std::f64::consts::PI;
+use std::fs;
+
+use crate::color::Color;
+use crate::sampler::Sampler;
+use crate::vec::Vec3;
+
+// resolution of the tables, in half angle, difference angle and difference azimuth
+const THETA_HALF_RES: usize = 90;
+const THETA_DIFF_RES: usize = 90;
+const PHI_DIFF_RES: usize = 180;
+
+// the color channels are stored with different scales
+const SCALE: [f64; 3] = [1.0 / 1500.0, 1.15 / 1500.0, 1.66 / 1500.0];
+
+// Isotropic BRDF measured by Matusik et al. ("A Data-Driven Reflectance Model"), tabulated over
+// the half and difference angles of the directions (Rusinkiewicz coordinates). Directions are
+// sampled either cosine weighted or from a table of how much light each half angle reflects.
+#[derive(Debug)]
+pub struct MerlBrdf {
+    // red, green and blue tables one after another
+    values: Vec<f64>,
+    // cumulative probabilities of sampling the half vector in uniformly spaced bins of its angle
+    half_cdf: Vec<f64>,
+}
+
+impl MerlBrdf {
+
+    // reads a file of the MERL database: three int32 resolutions followed by the float64 tables
+    pub fn load(path: &str) -> MerlBrdf {
+        let bytes = fs::read(path).unwrap_or_else(|e| panic!("Could not read BRDF file {}: {}", path, e));
+        let n = THETA_HALF_RES * THETA_DIFF_RES * PHI_DIFF_RES;
+
+        let dims: Vec<usize> = bytes
+            .chunks_exact(4)
+            .take(3)
+            .map(|b| i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize)
+            .collect();
+        if dims.len() != 3 || dims[0] * dims[1] * dims[2] != n || bytes.len() != 12 + 8 * 3 * n {
+            panic!("BRDF file {} is not in the MERL format", path);
+        }
+
+        let values = bytes[12..]
+            .chunks_exact(8)
+            .enumerate()
+            .map(|(i, b)| {
+                let value = f64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]);
+                // missing measurements are negative
+                value.max(0.0) * SCALE[i / n]
+            })
+            .collect();
+
+        MerlBrdf::new(values)
+    }
+
+    pub fn new(values: Vec<f64>) -> MerlBrdf {
+        let mut brdf = MerlBrdf { values, half_cdf: vec![] };
+
+        // the light reflected around each half angle, averaged over the difference angles
+        let mut cdf = vec![0.0];
+        for bin in 0..THETA_HALF_RES {
+            let theta_half = (bin as f64 + 0.5) / THETA_HALF_RES as f64 * PI / 2.0;
+            let mut sum = 0.0;
+            for theta_diff_index in 0..THETA_DIFF_RES {
+                for phi_diff_index in (0..PHI_DIFF_RES).step_by(4) {
+                    sum += brdf.lookup(theta_half, theta_diff_index, phi_diff_index).luminance();
+                }
+            }
+            cdf.push(cdf[bin] + sum * theta_half.sin());
+        }
+
+        let total = cdf[THETA_HALF_RES];
+        brdf.half_cdf = if total > 0.0 {
+            cdf.iter().map(|c| c / total).collect()
+        } else {
+            (0..=THETA_HALF_RES).map(|bin| bin as f64 / THETA_HALF_RES as f64).collect()
+        };
+
+        brdf
+    }
+
+    fn lookup(&self, theta_half: f64, theta_diff_index: usize, phi_diff_index: usize) -> Color {
+        // the half angle is stored with a square root mapping, more densely near the normal
+        let theta_half_index = if theta_half <= 0.0 {
+            0
+        } else {
+            ((theta_half / (PI / 2.0)).sqrt() * THETA_HALF_RES as f64) as usize
+        };
+        let index = phi_diff_index
+            + theta_diff_index * PHI_DIFF_RES
+            + theta_half_index.min(THETA_HALF_RES - 1) * PHI_DIFF_RES * THETA_DIFF_RES;
+
+        let n = THETA_HALF_RES * THETA_DIFF_RES * PHI_DIFF_RES;
+        Color::new(self.values[index], self.values[index + n], self.values[index + 2 * n])
+    }
+
+    // brdf for light arriving from wi and leaving towards wo, both pointing away from the surface
+    pub fn eval(&self, normal: &Vec3, wo: Vec3, wi: Vec3) -> Color {
+        let (tangent, bitangent) = normal.orthonormal_basis();
+        let local = |v: Vec3| Vec3::new(Vec3::dot(&v, &tangent), Vec3::dot(&v, &bitangent), Vec3::dot(&v, normal));
+        let (wo, wi) = (local(wo.normalized()), local(wi.normalized()));
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return Color::black();
+        }
+
+        let half = (wo + wi).normalized();
+        let theta_half = half.z.clamp(-1.0, 1.0).acos();
+        let phi_half = half.y.atan2(half.x);
+
+        // the incoming direction in the frame where the half vector is the normal
+        let z = Vec3::new(0.0, 0.0, 1.0);
+        let y = Vec3::new(0.0, 1.0, 0.0);
+        let diff = rotate(rotate(wi, z, -phi_half), y, -theta_half);
+        let theta_diff = diff.z.clamp(-1.0, 1.0).acos();
+        let mut phi_diff = diff.y.atan2(diff.x);
+
+        // reciprocity makes the table symmetric in the difference azimuth
+        if phi_diff < 0.0 {
+            phi_diff += PI;
+        }
+
+        let theta_diff_index = ((theta_diff / (PI / 2.0) * THETA_DIFF_RES as f64) as usize).min(THETA_DIFF_RES - 1);
+        let phi_diff_index = ((phi_diff / PI * PHI_DIFF_RES as f64) as usize).min(PHI_DIFF_RES - 1);
+        self.lookup(theta_half, theta_diff_index, phi_diff_index)
+    }
+
+    // samples wi given wo, half of the time cosine weighted and otherwise by the half angle table
+    pub fn sample(&self, normal: &Vec3, wo: Vec3, sampler: &mut dyn Sampler) -> Vec3 {
+        if sampler.next_f64() < 0.5 {
+            return Vec3::random_cosine_direction(normal, sampler);
+        }
+
+        let u = sampler.next_f64();
+        let bin = self.half_cdf.partition_point(|&c| c <= u).clamp(1, THETA_HALF_RES) - 1;
+        let theta = (bin as f64 + sampler.next_f64()) / THETA_HALF_RES as f64 * PI / 2.0;
+        let phi = 2.0 * PI * sampler.next_f64();
+
+        let (tangent, bitangent) = normal.orthonormal_basis();
+        let half = theta.sin() * phi.cos() * tangent + theta.sin() * phi.sin() * bitangent + theta.cos() * *normal;
+        let wo = wo.normalized();
+        2.0 * Vec3::dot(&wo, &half) * half - wo
+    }
+
+    // solid angle density of sample returning wi
+    pub fn pdf(&self, normal: &Vec3, wo: Vec3, wi: Vec3) -> f64 {
+        let (wo, wi) = (wo.normalized(), wi.normalized());
+        let cosine = Vec3::dot(&wi, normal);
+        if cosine <= 0.0 || Vec3::dot(&wo, normal) <= 0.0 {
+            return 0.0;
+        }
+
+        let half = (wo + wi).normalized();
+        let theta = Vec3::dot(&half, normal).clamp(-1.0, 1.0).acos();
+        let bin = ((theta / (PI / 2.0) * THETA_HALF_RES as f64) as usize).min(THETA_HALF_RES - 1);
+        let bin_width = PI / 2.0 / THETA_HALF_RES as f64;
+
+        // density of the half vector per solid angle, turned into one of the reflected direction
+        let sin_theta = theta.sin().max(1e-6);
+        let half_pdf = (self.half_cdf[bin + 1] - self.half_cdf[bin]) / (bin_width * 2.0 * PI * sin_theta);
+        let reflected_pdf = half_pdf / (4.0 * Vec3::dot(&wo, &half).max(1e-6));
+
+        0.5 * cosine / PI + 0.5 * reflected_pdf
+    }
+}
+
+// rotates v around the (normalized) axis by angle
+fn rotate(v: Vec3, axis: Vec3, angle: f64) -> Vec3 {
+    let (sin, cos) = angle.sin_cos();
+    v * cos + axis * (Vec3::dot(&axis, &v) * (1.0 - cos)) + Vec3::cross(&axis, &v) * sin
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::sampler::RandomSampler;
+
+    #[test]
+    fn test_sampling() {
+        // a measured lambertian with albedo 0.5, which sampling should reproduce if the
+        // density matches the sampled directions
+        let n = THETA_HALF_RES * THETA_DIFF_RES * PHI_DIFF_RES;
+        let brdf = MerlBrdf::new(vec![0.5 / PI; 3 * n]);
+        let normal = Vec3::new(0.0, 0.0, 1.0);
+        let wo = Vec3::new(0.6, 0.0, 0.8);
+
+        assert!((brdf.eval(&normal, wo, Vec3::new(-0.3, 0.4, 0.866)).r - 0.5 / PI).abs() < 1e-9);
+
+        let mut sampler = RandomSampler::new();
+        let samples = 100000;
+        let mut albedo = 0.0;
+        for _ in 0..samples {
+            let wi = brdf.sample(&normal, wo, &mut sampler);
+            let pdf = brdf.pdf(&normal, wo, wi);
+            if pdf > 0.0 {
+                albedo += brdf.eval(&normal, wo, wi).r * Vec3::dot(&wi, &normal) / pdf;
+            }
+        }
+        albedo /= samples as f64;
+
+        assert!((albedo - 0.5).abs() < 0.02);
+    }
+}
@@ -0,0 +1,871 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use json::JsonValue;
+
+use crate::camera::Camera;
+use crate::cli::CliOptions;
+use crate::color::Color;
+use crate::import::{self, Warnings};
+use crate::integrator::Integrator;
+use crate::intersection::{bvh::Aabb, mesh::{Mesh, MeshData}, object::Object, scene::Scene, sphere::Sphere};
+use crate::ior::{self, Ior};
+use crate::material::{Emission, Material};
+use crate::obj;
+use crate::parsing::parse_integrator;
+use crate::ply;
+use crate::spectrum;
+use crate::texture::{ImageTexture, Texture};
+use crate::transform::Transform;
+use crate::vec::Vec3;
+
+// Imports a Mitsuba 3 XML scene. Plugins and parameters rayo does not have are reported as
+// warnings and left out. Like for pbrt files, the resolution of the film is used unless the
+// command line gives one
+pub fn load(path: &str, opts: &mut CliOptions) -> (Scene, Camera, Box<dyn Integrator>) {
+    let scene = read_scene(Path::new(path));
+    let directory = Path::new(path).parent().unwrap_or_else(|| Path::new("")).to_path_buf();
+    let importer = Importer::new(&scene, directory);
+
+    import::apply_resolution(opts, importer.resolution());
+    let integrator = parse_integrator(&importer.integrator(), opts);
+    let (objects, camera) = importer.import(opts.aspect_ratio);
+    (Scene::new(objects), camera, integrator)
+}
+
+#[derive(Debug, Clone, Default)]
+struct Element {
+    name: String,
+    attributes: Vec<(String, String)>,
+    children: Vec<Element>,
+}
+
+// reads the elements of an XML document, skipping its text, comments and declarations
+fn parse_xml(source: &str, path: &str) -> Element {
+    fn unescape(text: &str) -> String {
+        text.replace("&lt;", "<").replace("&gt;", ">").replace("&quot;", "\"").replace("&apos;", "'").replace("&amp;", "&")
+    }
+
+    // the document is the parent of the root element
+    let mut stack = vec![Element::default()];
+    let mut rest = source;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start..];
+        let skip_to = |rest: &str, end: &str| -> usize {
+            rest.find(end).map(|i| i + end.len()).unwrap_or_else(|| panic!("{}: missing {}", path, end))
+        };
+
+        if rest.starts_with("<!--") {
+            rest = &rest[skip_to(rest, "-->")..];
+        } else if rest.starts_with("<?") || rest.starts_with("<!") {
+            rest = &rest[skip_to(rest, ">")..];
+        } else if let Some(closing) = rest.strip_prefix("</") {
+            let end = skip_to(rest, ">");
+            let name = closing[..end - 3].trim();
+            let element = stack.pop().filter(|e| e.name == name && !stack.is_empty());
+            let element = element.unwrap_or_else(|| panic!("{}: unexpected closing tag {}", path, name));
+            stack.last_mut().unwrap().children.push(element);
+            rest = &rest[end..];
+        } else {
+            // the name, the attributes with their quoted values and the end of the tag
+            let mut chars = rest[1..].char_indices().peekable();
+            let mut element = Element::default();
+            while let Some((_, c)) = chars.next_if(|&(_, c)| !c.is_whitespace() && c != '/' && c != '>') {
+                element.name.push(c);
+            }
+
+            let (end, self_closing) = loop {
+                match chars.next() {
+                    Some((_, c)) if c.is_whitespace() => {},
+                    Some((i, '/')) => break (i + 3, true),
+                    Some((i, '>')) => break (i + 2, false),
+                    Some((_, c)) => {
+                        let mut name = c.to_string();
+                        while let Some((_, c)) = chars.next_if(|&(_, c)| c != '=' && !c.is_whitespace()) {
+                            name.push(c);
+                        }
+                        let quote = loop {
+                            match chars.next() {
+                                Some((_, q @ ('"' | '\''))) => break q,
+                                Some((_, c)) if c == '=' || c.is_whitespace() => {},
+                                _ => panic!("{}: attribute {} of {} has no value", path, name, element.name)
+                            }
+                        };
+                        let value: String = chars.by_ref().map(|(_, c)| c).take_while(|&c| c != quote).collect();
+                        element.attributes.push((name, unescape(&value)));
+                    },
+                    None => panic!("{}: unclosed tag {}", path, element.name)
+                }
+            };
+
+            if self_closing {
+                stack.last_mut().unwrap().children.push(element);
+            } else {
+                stack.push(element);
+            }
+            rest = &rest[end.min(rest.len())..];
+        }
+    }
+
+    if stack.len() != 1 {
+        panic!("{}: unclosed tag {}", path, stack.last().unwrap().name);
+    }
+    stack.pop().unwrap().children.into_iter().next().unwrap_or_else(|| panic!("{}: no scene", path))
+}
+
+fn read_scene(path: &Path) -> Element {
+    let source = fs::read_to_string(path).unwrap_or_else(|e| panic!("Could not read Mitsuba file {}: {}", path.display(), e));
+    let scene = parse_xml(&source, &path.to_string_lossy());
+    if scene.name != "scene" {
+        panic!("{}: expected a scene, got {}", path.display(), scene.name);
+    }
+    resolve(scene, path.parent().unwrap_or_else(|| Path::new("")))
+}
+
+// Reads the included files, relative to the directory of the including one, and merges their
+// scenes into this one. Then parameters like $spp are replaced by their defaults
+fn resolve(scene: Element, directory: &Path) -> Element {
+    let mut children = vec![];
+    for child in scene.children {
+        match child.attribute("filename").filter(|_| child.name == "include") {
+            Some(file) => children.extend(read_scene(&directory.join(file)).children),
+            None => children.push(child),
+        }
+    }
+
+    // longer names first, so $spp does not replace the start of $spp_max
+    let mut defaults: Vec<(String, String)> = children
+        .iter()
+        .filter(|c| c.name == "default")
+        .filter_map(|c| Some((format!("${}", c.attribute("name")?), c.attribute("value")?.to_string())))
+        .collect();
+    defaults.sort_by_key(|(name, _)| std::cmp::Reverse(name.len()));
+
+    fn substitute(element: &mut Element, defaults: &[(String, String)]) {
+        for (_, value) in &mut element.attributes {
+            for (name, default) in defaults {
+                *value = value.replace(name.as_str(), default);
+            }
+        }
+        for child in &mut element.children {
+            substitute(child, defaults);
+        }
+    }
+
+    let mut scene = Element { children, ..scene };
+    substitute(&mut scene, &defaults);
+    scene
+}
+
+// numbers separated by commas or spaces
+fn numbers(text: &str) -> Vec<f64> {
+    text.split(|c: char| c == ',' || c.is_whitespace()).filter(|n| !n.is_empty()).filter_map(|n| n.parse().ok()).collect()
+}
+
+impl Element {
+
+    fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+    }
+
+    fn kind(&self) -> &str {
+        self.attribute("type").unwrap_or("")
+    }
+
+    fn children_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Element> {
+        self.children.iter().filter(move |c| c.name == name)
+    }
+
+    // the child holding the parameter with the given name
+    fn property(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|c| c.attribute("name") == Some(name))
+    }
+
+    fn float(&self, name: &str, default: f64) -> f64 {
+        self.property(name).and_then(|p| p.attribute("value")).and_then(|v| v.trim().parse().ok()).unwrap_or(default)
+    }
+
+    fn string(&self, name: &str) -> Option<&str> {
+        self.property(name).and_then(|p| p.attribute("value"))
+    }
+
+    fn bool(&self, name: &str, default: bool) -> bool {
+        self.string(name).map_or(default, |v| v == "true")
+    }
+
+    // points and vectors, either as x, y and z or as one value
+    fn vector(&self) -> Vec3 {
+        match self.attribute("value").map(numbers).as_deref() {
+            Some([x, y, z]) => Vec3::new(*x, *y, *z),
+            Some([v]) => Vec3::new(*v, *v, *v),
+            _ => {
+                let coordinate = |name: &str| self.attribute(name).and_then(|v| v.trim().parse().ok()).unwrap_or(0.0);
+                Vec3::new(coordinate("x"), coordinate("y"), coordinate("z"))
+            }
+        }
+    }
+}
+
+// index of refraction of the materials Mitsuba knows by name
+fn named_ior(name: &str) -> Option<f64> {
+    match name {
+        "vacuum" => Some(1.0),
+        "air" => Some(1.000277),
+        "water" => Some(1.333),
+        "water ice" => Some(1.31),
+        "acetone" => Some(1.36),
+        "ethanol" => Some(1.361),
+        "glycerol" => Some(1.4729),
+        "fused quartz" => Some(1.458),
+        "pyrex" => Some(1.47),
+        "acrylic glass" | "polypropylene" => Some(1.49),
+        "bk7" => Some(1.5046),
+        "sodium chloride" => Some(1.544),
+        "amber" => Some(1.55),
+        "pet" => Some(1.575),
+        "diamond" => Some(2.419),
+        _ => None
+    }
+}
+
+struct Importer<'a> {
+    scene: &'a Element,
+    directory: PathBuf,
+    // elements with an id, which can be referenced like <ref id="..."/>
+    ids: HashMap<&'a str, &'a Element>,
+    warnings: Warnings,
+}
+
+impl<'a> Importer<'a> {
+
+    fn new(scene: &'a Element, directory: PathBuf) -> Importer<'a> {
+        fn collect<'a>(element: &'a Element, ids: &mut HashMap<&'a str, &'a Element>) {
+            if let Some(id) = element.attribute("id").filter(|_| element.name != "ref") {
+                ids.insert(id, element);
+            }
+            for child in &element.children {
+                collect(child, ids);
+            }
+        }
+
+        let mut ids = HashMap::new();
+        collect(scene, &mut ids);
+        Importer { scene, directory, ids, warnings: Warnings::default() }
+    }
+
+    fn sensor(&self) -> Option<&'a Element> {
+        let sensor = self.scene.children_named("sensor").next();
+        if sensor.is_none() {
+            self.warnings.warn("the scene has no sensor, looking along the z axis from the origin");
+        }
+        sensor
+    }
+
+    // the size of the film, with the defaults of Mitsuba
+    fn resolution(&self) -> (u32, u32) {
+        let film = self.scene.children_named("sensor").next().and_then(|s| s.children_named("film").next());
+        film.map_or((768, 576), |film| (film.float("width", 768.0).max(1.0) as u32, film.float("height", 576.0).max(1.0) as u32))
+    }
+
+    fn integrator(&self) -> JsonValue {
+        let integrator = match self.scene.children_named("integrator").next() {
+            Some(integrator) => integrator,
+            None => return json::object! {"type": "path"}
+        };
+
+        let kind = match integrator.kind() {
+            "path" | "volpath" | "volpathmis" => "path",
+            // light tracing is closest to the bidirectional path tracer
+            "ptracer" => "bdpt",
+            other => {
+                self.warnings.warn(&format!("the {} integrator is not supported, using path", other));
+                "path"
+            }
+        };
+
+        // a maximum depth of -1 is unlimited
+        let mut settings = json::object! {"type": kind};
+        let max_depth = integrator.float("max_depth", -1.0);
+        if max_depth >= 0.0 {
+            settings["max-depth"] = (max_depth as u32).into();
+        }
+        settings
+    }
+
+    // the to_world transformation of an element, the identity if it has none
+    fn transform(&self, element: &Element) -> Transform {
+        let mut transform = Transform::identity();
+        for operation in element.property("to_world").map_or(&[][..], |t| &t.children[..]) {
+            let values = operation.attribute("value").map(numbers).unwrap_or_default();
+            let next = match operation.name.as_str() {
+                "translate" => Transform::translation(operation.vector()),
+                "scale" => match values[..] {
+                    [s] => Transform::scale(Vec3::new(s, s, s)),
+                    [x, y, z] => Transform::scale(Vec3::new(x, y, z)),
+                    _ => {
+                        let factor = |name: &str| operation.attribute(name).and_then(|v| v.trim().parse().ok()).unwrap_or(1.0);
+                        Transform::scale(Vec3::new(factor("x"), factor("y"), factor("z")))
+                    }
+                },
+                "rotate" => {
+                    let angle = operation.attribute("angle").and_then(|a| a.trim().parse().ok()).unwrap_or(0.0);
+                    Transform::axis_rotation(angle, operation.vector())
+                },
+                // matrices are given row by row
+                "matrix" if values.len() == 16 => {
+                    Transform::from_rows([0, 1, 2].map(|i| [values[4 * i], values[4 * i + 1], values[4 * i + 2], values[4 * i + 3]]))
+                },
+                "lookat" => {
+                    let vector = |name: &str| match operation.attribute(name).map(numbers).as_deref() {
+                        Some([x, y, z]) => Some(Vec3::new(*x, *y, *z)),
+                        _ => None
+                    };
+                    let origin = vector("origin").unwrap_or_else(Vec3::zero);
+                    let target = vector("target").unwrap_or_else(|| Vec3::new(0.0, 0.0, 1.0));
+                    Transform::look_at(origin, target, vector("up").unwrap_or_else(|| Vec3::new(0.0, 1.0, 0.0)))
+                },
+                other => {
+                    self.warnings.warn(&format!("the {} transformation is not supported", other));
+                    Transform::identity()
+                }
+            };
+            transform = transform.then(&next);
+        }
+        transform
+    }
+
+    // spectrum elements as colors, None if they are not supported
+    fn color(&self, element: &Element) -> Option<Color> {
+        let value = element.attribute("value").unwrap_or("");
+        match (element.name.as_str(), &numbers(value)[..]) {
+            ("rgb", &[r, g, b]) => Some(Color::new(r, g, b)),
+            ("rgb" | "spectrum" | "float", &[v]) => Some(Color::new(v, v, v)),
+            ("blackbody", _) => element.attribute("temperature").and_then(|t| t.trim().parse().ok()).filter(|&t| t > 0.0).map(Color::blackbody),
+            // sampled spectra as wavelength:value pairs
+            ("spectrum", _) if value.contains(':') => {
+                let pairs: Option<Vec<(f64, f64)>> = value
+                    .split(',')
+                    .map(|pair| {
+                        let (lambda, v) = pair.split_once(':')?;
+                        Some((lambda.trim().parse().ok()?, v.trim().parse().ok()?))
+                    })
+                    .collect();
+                pairs.map(|pairs| spectrum::sampled_to_rgb(&pairs))
+            },
+            _ => {
+                self.warnings.warn(&format!("{} values like \"{}\" are not supported", element.name, value));
+                None
+            }
+        }
+    }
+
+    // a texture, a reference to one or a constant color
+    fn texture(&self, parent: &Element, name: &str, default: Color) -> Texture {
+        let element = match parent.property(name) {
+            Some(element) if element.name == "ref" => self.ids.get(element.attribute("id").unwrap_or("")).copied(),
+            other => other,
+        };
+
+        match element {
+            Some(element) if element.name == "texture" => match element.kind() {
+                "bitmap" => {
+                    let file = self.directory.join(element.string("filename").unwrap_or(""));
+                    // raw images hold data that is not converted from sRGB
+                    let srgb = !element.bool("raw", false);
+                    let image = fs::read(&file).ok().and_then(|bytes| ImageTexture::decode(&bytes, srgb));
+                    match image {
+                        Some(image) => Texture::Image { image: Arc::new(image) },
+                        None => {
+                            self.warnings.warn(&format!("could not read image {}", file.display()));
+                            Texture::Constant { color: default }
+                        }
+                    }
+                },
+                other => {
+                    self.warnings.warn(&format!("{} textures are not supported", other));
+                    Texture::Constant { color: default }
+                }
+            },
+            Some(element) => Texture::Constant { color: self.color(element).unwrap_or(default) },
+            None => Texture::Constant { color: default },
+        }
+    }
+
+    fn material(&self, bsdf: &'a Element) -> Material {
+        match bsdf.kind() {
+            "diffuse" => Material::Lambertian { albedo: self.texture(bsdf, "reflectance", Color::new(0.5, 0.5, 0.5)) },
+            kind @ ("conductor" | "roughconductor") => {
+                // named metals, or a perfect mirror
+                let material = bsdf.string("material").unwrap_or("none");
+                let reflectance = match (material, bsdf.property("eta"), bsdf.property("k")) {
+                    (_, Some(eta), Some(k)) => match (self.color(eta), self.color(k)) {
+                        (Some(eta), Some(k)) => ior::conductor_reflectance(eta, k),
+                        _ => Color::white()
+                    },
+                    ("none", _, _) => Color::white(),
+                    (name, _, _) => match ior::conductor_preset(&name.to_lowercase()) {
+                        Some((eta, k)) => ior::conductor_reflectance(eta, k),
+                        None => {
+                            self.warnings.warn(&format!("the conductor {} is not supported, using a mirror", name));
+                            Color::white()
+                        }
+                    }
+                };
+
+                let albedo = match self.texture(bsdf, "specular_reflectance", Color::white()) {
+                    Texture::Constant { color } => Texture::Constant { color: color * reflectance },
+                    texture => texture,
+                };
+
+                let fuzz = if kind == "conductor" {
+                    0.0
+                } else {
+                    let alpha = bsdf.float("alpha", 0.1);
+                    0.5 * (bsdf.float("alpha_u", alpha) + bsdf.float("alpha_v", alpha))
+                };
+                Material::Metal { albedo, fuzz, film: None }
+            },
+            kind @ ("dielectric" | "roughdielectric") => {
+                if kind == "roughdielectric" {
+                    self.warnings.warn("rough dielectrics are rendered smooth");
+                }
+                let ior = |name: &str, default: f64| match bsdf.string(name) {
+                    Some(value) => value.trim().parse().ok().or_else(|| named_ior(value)).unwrap_or_else(|| {
+                        self.warnings.warn(&format!("unknown index of refraction {}", value));
+                        default
+                    }),
+                    None => default
+                };
+                let ior = Ior::Constant(ior("int_ior", 1.5046) / ior("ext_ior", 1.000277));
+                let color = self.texture(bsdf, "specular_transmittance", Color::white());
+                Material::Dielectric { ior, color, film: None }
+            },
+            // rayo renders both sides of surfaces anyway
+            "twosided" => match self.bsdf(bsdf) {
+                Some(inner) => self.material(inner),
+                None => self.default_material()
+            },
+            other => {
+                self.warnings.warn(&format!("the {} BSDF is not supported, using diffuse", other));
+                self.default_material()
+            }
+        }
+    }
+
+    fn default_material(&self) -> Material {
+        Material::Lambertian { albedo: Texture::Constant { color: Color::new(0.5, 0.5, 0.5) } }
+    }
+
+    // the BSDF inside an element, or the one it references
+    fn bsdf(&self, element: &'a Element) -> Option<&'a Element> {
+        element.children.iter().find_map(|child| match child.name.as_str() {
+            "bsdf" => Some(child),
+            "ref" => self.ids.get(child.attribute("id").unwrap_or("")).copied().filter(|e| e.name == "bsdf"),
+            _ => None
+        })
+    }
+
+    // Sensors look along their z axis with x to the left of the image, like cameras of rayo do.
+    // If their transformation mirrors that, the whole scene is mirrored to get the same image
+    fn camera(&self, aspect_ratio: f64) -> (Camera, Transform) {
+        let sensor = self.sensor();
+        let to_world = sensor.map_or_else(Transform::identity, |s| self.transform(s));
+        let mirror = if to_world.determinant() < 0.0 { Transform::scale(Vec3::new(-1.0, 1.0, 1.0)) } else { Transform::identity() };
+        let to_world = to_world.then(&mirror);
+
+        let kind = sensor.map_or("perspective", |s| s.kind());
+        if kind != "perspective" && kind != "thinlens" {
+            self.warnings.warn(&format!("{} sensors are not supported, using a perspective camera", kind));
+        }
+
+        // the field of view is given for one axis of the image, or by the focal length of a 35mm camera
+        let sensor = sensor.cloned().unwrap_or_default();
+        let fov = match sensor.string("fov") {
+            Some(fov) => fov.trim().parse().unwrap_or(45.0),
+            None => {
+                let focal_length: f64 = sensor.string("focal_length").unwrap_or("50mm").trim_end_matches("mm").trim().parse().unwrap_or(50.0);
+                2.0 * (18.0 / focal_length).atan().to_degrees()
+            }
+        };
+        let fov_axis = sensor.string("fov_axis").unwrap_or("x");
+        let tangent = (fov / 2.0f64).to_radians().tan();
+        let vertical_tangent = match fov_axis {
+            "y" => tangent,
+            "diagonal" => tangent / (aspect_ratio * aspect_ratio + 1.0).sqrt(),
+            "smaller" if aspect_ratio >= 1.0 => tangent,
+            "larger" if aspect_ratio < 1.0 => tangent,
+            _ => tangent / aspect_ratio
+        };
+        let vfov = 2.0 * vertical_tangent.atan().to_degrees();
+
+        let aperture = 2.0 * sensor.float("aperture_radius", 0.0);
+        let focal_length = if aperture > 0.0 { sensor.float("focus_distance", 1.0) } else { 1.0 };
+
+        let lookfrom = to_world.point(Vec3::zero());
+        let lookat = lookfrom + to_world.vector(Vec3::new(0.0, 0.0, 1.0));
+        let vup = to_world.vector(Vec3::new(0.0, 1.0, 0.0));
+        (Camera::new(lookfrom, lookat, vup, vfov, focal_length, aperture, aspect_ratio), mirror)
+    }
+
+    fn import(&self, aspect_ratio: f64) -> (Vec<Object>, Camera) {
+        let (camera, mirror) = self.camera(aspect_ratio);
+        let mut objects = vec![];
+        let mut bounds = Aabb::empty();
+
+        for shape in self.scene.children_named("shape") {
+            let mut material = self.bsdf(shape).map_or_else(|| self.default_material(), |bsdf| self.material(bsdf));
+            if let Some(emitter) = shape.children_named("emitter").next() {
+                if emitter.kind() != "area" {
+                    self.warnings.warn(&format!("{} emitters on shapes are not supported", emitter.kind()));
+                }
+                let emission = Emission::new(self.texture(emitter, "radiance", Color::white()));
+                material = Material::Emissive { base: Box::new(material), emission };
+            }
+
+            let to_world = self.transform(shape).then(&mirror);
+            for object in self.shape(shape, &to_world, material) {
+                bounds = bounds.union(&match &object {
+                    Shape::Sphere(sphere) => {
+                        let extent = Vec3::new(sphere.radius, sphere.radius, sphere.radius);
+                        Aabb::around(&[sphere.center - extent, sphere.center + extent])
+                    },
+                    Shape::Mesh(data, _) => Aabb::around(&data.positions),
+                });
+                objects.push(match object {
+                    Shape::Sphere(sphere) => Object::Sphere(sphere),
+                    Shape::Mesh(mut data, material) => {
+                        // mirroring turns the triangles around, which would change the side area lights shine on
+                        if mirror.determinant() < 0.0 {
+                            data.reverse_winding();
+                        }
+                        Object::Mesh(Mesh::new(data, material, None))
+                    }
+                });
+            }
+        }
+
+        // lights are sized by the scene
+        let (center, size) = import::extent(&bounds, Vec3::zero());
+        for emitter in self.scene.children_named("emitter") {
+            let to_world = self.transform(emitter).then(&mirror);
+            objects.extend(self.emitter(emitter, &to_world, center, size + (camera.origin - center).norm()));
+        }
+
+        (objects, camera)
+    }
+
+    fn shape(&self, shape: &Element, to_world: &Transform, material: Material) -> Vec<Shape> {
+        let file = || self.directory.join(shape.string("filename").unwrap_or("")).to_string_lossy().to_string();
+        let meshes = match shape.kind() {
+            "sphere" => {
+                let center = shape.property("center").map_or_else(Vec3::zero, |c| c.vector());
+                let radius = shape.float("radius", 1.0) * to_world.vector(Vec3::new(1.0, 0.0, 0.0)).norm();
+                return vec![Shape::Sphere(Sphere { center: to_world.point(center), radius, material, medium: None })];
+            },
+            // the square from -1 to 1 in the xy plane, facing along z
+            "rectangle" => vec![MeshData {
+                positions: vec![Vec3::new(-1.0, -1.0, 0.0), Vec3::new(1.0, -1.0, 0.0), Vec3::new(1.0, 1.0, 0.0), Vec3::new(-1.0, 1.0, 0.0)],
+                uvs: vec![(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)],
+                triangles: vec![[0, 1, 2], [0, 2, 3]],
+                ..MeshData::default()
+            }],
+            // the cube from -1 to 1, with faces made of the axes u and v around their normals u x v
+            "cube" => {
+                let (x, y, z) = (Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 0.0, 1.0));
+                let mut data = MeshData::default();
+                for (u, v) in [(y, z), (z, y), (z, x), (x, z), (x, y), (y, x)] {
+                    let normal = Vec3::cross(&u, &v);
+                    let first = data.positions.len();
+                    for (a, b) in [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)] {
+                        data.positions.push(normal + a * u + b * v);
+                        data.uvs.push(((a + 1.0) / 2.0, (b + 1.0) / 2.0));
+                    }
+                    data.triangles.extend([[first, first + 1, first + 2], [first, first + 2, first + 3]]);
+                }
+                vec![data]
+            },
+            "obj" => obj::load(&file()).meshes.into_iter().map(|(_, data)| data).collect(),
+            "ply" => vec![ply::load(&file())],
+            other => {
+                self.warnings.warn(&format!("{} shapes are not supported", other));
+                return vec![];
+            }
+        };
+
+        meshes
+            .into_iter()
+            .map(|mut data| {
+                if shape.bool("face_normals", false) {
+                    data.normals.clear();
+                }
+                if shape.bool("flip_normals", false) {
+                    data.reverse_winding();
+                    data.normals.iter_mut().for_each(|n| *n = -*n);
+                }
+                data.transform(to_world);
+                Shape::Mesh(data, material.clone())
+            })
+            .collect()
+    }
+
+    // Lights become glowing spheres. Point lights are small with the same intensity, directional
+    // ones far away and constant ones enclose the scene, glowing inwards. size is that of the scene and the camera
+    fn emitter(&self, emitter: &Element, to_world: &Transform, center: Vec3, size: f64) -> Option<Object> {
+        match emitter.kind() {
+            kind @ ("point" | "spot") => {
+                if kind == "spot" {
+                    self.warnings.warn("spot lights are rendered as point lights");
+                }
+                let position = emitter.property("position").map_or_else(|| to_world.point(Vec3::zero()), |p| to_world.point(p.vector()));
+                Some(import::point_light(position, self.color_value(emitter, "intensity"), size))
+            },
+            "directional" => {
+                let direction = emitter.property("direction").map_or(Vec3::new(0.0, 0.0, 1.0), |d| d.vector());
+                Some(import::directional_light(center, to_world.vector(direction), self.color_value(emitter, "irradiance"), size))
+            },
+            kind @ ("constant" | "envmap") => {
+                let radiance = if kind == "envmap" {
+                    self.warnings.warn("environment maps are not supported, the environment is white");
+                    emitter.float("scale", 1.0) * Color::white()
+                } else {
+                    self.color_value(emitter, "radiance")
+                };
+                Some(import::environment_light(center, radiance, size))
+            },
+            other => {
+                self.warnings.warn(&format!("{} emitters are not supported", other));
+                None
+            }
+        }
+    }
+
+    // a constant color parameter, white by default
+    fn color_value(&self, element: &Element, name: &str) -> Color {
+        element.property(name).and_then(|p| self.color(p)).unwrap_or_else(Color::white)
+    }
+}
+
+// shapes before they are added to the scene, meshes with their material
+enum Shape {
+    Sphere(Sphere),
+    Mesh(MeshData, Material),
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::intersection::Hittable;
+    use crate::ray::Ray;
+    use crate::sampler::RandomSampler;
+
+    // a resolved scene with the given elements
+    fn parse_scene(elements: &str) -> Element {
+        let source = format!(r#"<?xml version="1.0" encoding="utf-8"?><scene version="3.0.0">{}</scene>"#, elements);
+        resolve(parse_xml(&source, "test.xml"), Path::new(""))
+    }
+
+    #[test]
+    fn test_parse_xml() {
+        let scene = parse_scene(r#"
+            <!-- <shape type="sphere"/> is commented out -->
+            <default name="spp" value="16"/>
+            <default name="spp_max" value="64"/>
+            <integer name="a" value="$spp"/>
+            <integer name="b" value="$spp_max"/>
+            <string name="c" value='say &quot;hi&quot; &amp; bye'/>
+            <shape type="rectangle" ><bsdf type="diffuse" /></shape>
+        "#);
+        assert_eq!(scene.children.len(), 6);
+        assert_eq!(scene.float("a", 0.0), 16.0);
+        assert_eq!(scene.float("b", 0.0), 64.0);
+        assert_eq!(scene.string("c"), Some("say \"hi\" & bye"));
+
+        let shape = scene.children_named("shape").next().unwrap();
+        assert_eq!(shape.kind(), "rectangle");
+        assert_eq!(shape.children[0].kind(), "diffuse");
+    }
+
+    #[test]
+    #[should_panic(expected = "test.xml: unexpected closing tag sensor")]
+    fn test_mismatched_tag() {
+        parse_scene("<shape></sensor>");
+    }
+
+    #[test]
+    fn test_colors() {
+        let scene = parse_scene(r#"
+            <rgb value="0.1, 0.2, 0.3"/>
+            <spectrum value="0.5"/>
+            <blackbody temperature="6500"/>
+            <spectrum value="400:1, 500:1, 600:1, 700:1"/>
+            <spectrum filename="d65.spd"/>
+        "#);
+        let importer = Importer::new(&scene, PathBuf::new());
+        let colors: Vec<Option<Color>> = scene.children.iter().map(|c| importer.color(c)).collect();
+        assert!(colors[0].unwrap().is_close(&Color::new(0.1, 0.2, 0.3)));
+        assert!(colors[1].unwrap().is_close(&Color::new(0.5, 0.5, 0.5)));
+        assert!(colors[2].unwrap().is_close(&Color::blackbody(6500.0)));
+        assert!(colors[3].unwrap().g > 0.5);
+
+        // spectra from files are left out with a warning
+        assert!(colors[4].is_none());
+        assert!(importer.warnings.contains("spectrum values"));
+    }
+
+    #[test]
+    fn test_transforms() {
+        let scene = parse_scene(r#"
+            <shape type="sphere">
+                <transform name="to_world">
+                    <scale value="2"/>
+                    <rotate y="1" angle="90"/>
+                    <translate x="1" y="2" z="3"/>
+                    <skew value="1"/>
+                </transform>
+            </shape>
+            <shape type="sphere">
+                <transform name="to_world">
+                    <matrix value="1 0 0 4  0 1 0 5  0 0 1 6  0 0 0 1"/>
+                </transform>
+            </shape>
+        "#);
+        let importer = Importer::new(&scene, PathBuf::new());
+        let shapes: Vec<&Element> = scene.children_named("shape").collect();
+
+        // the operations apply in order
+        let transform = importer.transform(shapes[0]);
+        assert!(transform.point(Vec3::new(1.0, 0.0, 0.0)).is_close(&Vec3::new(1.0, 2.0, 1.0)));
+        assert!(importer.warnings.contains("the skew transformation is not supported"));
+
+        // matrices are given row by row
+        assert!(importer.transform(shapes[1]).point(Vec3::zero()).is_close(&Vec3::new(4.0, 5.0, 6.0)));
+    }
+
+    #[test]
+    fn test_materials() {
+        let scene = parse_scene(r#"
+            <bsdf type="twosided" id="gold">
+                <bsdf type="roughconductor">
+                    <string name="material" value="Au"/>
+                    <float name="alpha" value="0.2"/>
+                </bsdf>
+            </bsdf>
+            <shape type="sphere"><ref id="gold"/></shape>
+            <shape type="sphere">
+                <bsdf type="dielectric">
+                    <string name="int_ior" value="water"/>
+                    <string name="ext_ior" value="vacuum"/>
+                </bsdf>
+            </shape>
+            <shape type="sphere"><bsdf type="plastic"/></shape>
+            <shape type="sphere"><bsdf type="conductor"><string name="material" value="unobtainium"/></bsdf></shape>
+        "#);
+        let importer = Importer::new(&scene, PathBuf::new());
+        let materials: Vec<Material> = scene.children_named("shape").map(|s| importer.material(importer.bsdf(s).unwrap())).collect();
+
+        assert!(matches!(&materials[0], Material::Metal { albedo: Texture::Constant { color }, fuzz, .. } if color.r > color.b && (fuzz - 0.2).abs() < 1e-9));
+        assert!(matches!(&materials[1], Material::Dielectric { ior: Ior::Constant(ior), .. } if (ior - 1.333).abs() < 1e-9));
+
+        assert!(matches!(&materials[2], Material::Lambertian { .. }));
+        assert!(importer.warnings.contains("the plastic BSDF is not supported"));
+        assert!(matches!(&materials[3], Material::Metal { albedo: Texture::Constant { color }, fuzz, .. } if color.is_close(&Color::white()) && *fuzz == 0.0));
+        assert!(importer.warnings.contains("the conductor unobtainium is not supported"));
+    }
+
+    #[test]
+    fn test_shapes() {
+        let scene = parse_scene(r#"
+            <shape type="cube">
+                <transform name="to_world"><scale value="0.5"/></transform>
+            </shape>
+            <shape type="rectangle">
+                <boolean name="flip_normals" value="true"/>
+                <transform name="to_world"><translate z="-2"/></transform>
+            </shape>
+            <shape type="disk"/>
+        "#);
+        let importer = Importer::new(&scene, PathBuf::new());
+        let (objects, _) = importer.import(1.0);
+        assert_eq!(objects.len(), 2);
+        assert!(importer.warnings.contains("disk shapes are not supported"));
+
+        // the cube from -0.5 to 0.5 and the rectangle facing away from the sensor at the origin
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit = objects[0].intersect(&ray, 1e-6, f64::MAX).unwrap();
+        assert!((objects[0].area() - 6.0).abs() < 1e-9 && (hit.t - 0.5).abs() < 1e-9 && hit.front_face);
+        let hit = objects[1].intersect(&ray, 1e-6, f64::MAX).unwrap();
+        assert!((hit.t - 3.0).abs() < 1e-9 && !hit.front_face);
+    }
+
+    #[test]
+    fn test_sensor() {
+        let scene = parse_scene(r#"
+            <integrator type="ptracer"><integer name="max_depth" value="6"/></integrator>
+            <sensor type="perspective">
+                <float name="fov" value="40"/>
+                <transform name="to_world">
+                    <lookat origin="0, 0, 5" target="0, 0, 0" up="0, 1, 0"/>
+                </transform>
+                <film type="hdrfilm">
+                    <integer name="width" value="320"/>
+                    <integer name="height" value="240"/>
+                </film>
+            </sensor>
+            <shape type="sphere">
+                <float name="radius" value="0.5"/>
+                <point name="center" x="1.5" y="0" z="0"/>
+            </shape>
+        "#);
+        let importer = Importer::new(&scene, PathBuf::new());
+        assert_eq!(importer.resolution(), (320, 240));
+        assert_eq!(importer.integrator()["type"].as_str(), Some("bdpt"));
+        assert_eq!(importer.integrator()["max-depth"].as_u32(), Some(6));
+
+        // the sphere is on the right, where Mitsuba shows +x for this sensor
+        let (objects, camera) = importer.import(4.0 / 3.0);
+        let mut sampler = RandomSampler::new();
+        assert!(objects[0].intersect(&camera.generate_ray(0.1, 0.5, &mut sampler), 1e-6, f64::MAX).is_none());
+        let hit = objects[0].intersect(&camera.generate_ray(0.9, 0.5, &mut sampler), 1e-6, f64::MAX).unwrap();
+        assert!(hit.point.x > 1.0);
+
+        // without a sensor the importer warns and looks along z
+        let empty = parse_scene("");
+        let importer = Importer::new(&empty, PathBuf::new());
+        assert!(importer.camera(1.0).0.origin.is_close(&Vec3::zero()));
+        assert!(importer.warnings.contains("the scene has no sensor"));
+        assert_eq!(importer.integrator()["type"].as_str(), Some("path"));
+    }
+
+    #[test]
+    fn test_emitters() {
+        let scene = parse_scene(r#"
+            <shape type="rectangle">
+                <emitter type="area"><rgb name="radiance" value="4, 4, 4"/></emitter>
+            </shape>
+            <emitter type="point"><point name="position" x="0" y="3" z="0"/></emitter>
+            <emitter type="directional"><vector name="direction" x="0" y="-1" z="0"/></emitter>
+            <emitter type="constant"><rgb name="radiance" value="0.1"/></emitter>
+            <emitter type="envmap"><string name="filename" value="sky.exr"/></emitter>
+            <emitter type="projector"/>
+        "#);
+        let importer = Importer::new(&scene, PathBuf::new());
+        let (objects, _) = importer.import(1.0);
+        assert_eq!(objects.len(), 5);
+        assert!(importer.warnings.contains("environment maps are not supported"));
+        assert!(importer.warnings.contains("projector emitters are not supported"));
+
+        assert!(objects[0].material().is_emissive());
+        let spheres: Vec<&Sphere> = objects[1..].iter().map(|o| match o {
+            Object::Sphere(sphere) | Object::Environment(sphere) => sphere,
+            other => panic!("Expected a sphere, got {:?}", other)
+        }).collect();
+        assert!(spheres[0].center.is_close(&Vec3::new(0.0, 3.0, 0.0)));
+        assert!(spheres[1].center.y > 10.0);
+
+        // constant and environment emitters enclose the scene, shining inwards
+        assert!(matches!(objects[3..], [Object::Environment(_), Object::Environment(_)]));
+        assert!(spheres[2].radius > 10.0 && spheres[3].radius > 10.0);
+    }
+}
@@ -0,0 +1,373 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::color::Color;
+use crate::intersection::mesh::MeshData;
+use crate::ior::Ior;
+use crate::material::{Emission, Material};
+use crate::texture::{ImageTexture, Texture};
+use crate::vec::Vec3;
+
+// A model read from a Wavefront OBJ file, split into one mesh per material
+pub struct ObjModel {
+    // the meshes with the name of their material, None for faces before any usemtl
+    pub meshes: Vec<(Option<String>, MeshData)>,
+    // materials of the MTL files the model refers to
+    pub materials: HashMap<String, Material>,
+}
+
+// indices of the position, texture coordinates and normal of a face corner
+type Corner = (usize, Option<usize>, Option<usize>);
+
+// reads an OBJ file and its MTL files, which are looked up next to it
+pub fn load(path: &str) -> ObjModel {
+    let source = fs::read_to_string(path).unwrap_or_else(|e| panic!("Could not read OBJ file {}: {}", path, e));
+    let directory = Path::new(path).parent().unwrap_or_else(|| Path::new(""));
+
+    let (meshes, libraries) = parse_obj(&source, path);
+
+    let mut materials = HashMap::new();
+    for library in libraries {
+        let mtl_path = directory.join(&library);
+        let mtl_source = fs::read_to_string(&mtl_path)
+            .unwrap_or_else(|e| panic!("Could not read MTL file {}: {}", mtl_path.display(), e));
+        materials.extend(parse_mtl(&mtl_source, mtl_path.parent().unwrap_or(directory)));
+    }
+
+    ObjModel { meshes, materials }
+}
+
+// Reads the faces of an OBJ file, polygons are split into triangles. Returns the meshes and
+// the MTL files named by mtllib
+fn parse_obj(source: &str, path: &str) -> (Vec<(Option<String>, MeshData)>, Vec<String>) {
+    let mut positions: Vec<Vec3> = vec![];
+    let mut colors: Vec<Color> = vec![];
+    let mut uvs: Vec<(f64, f64)> = vec![];
+    let mut normals: Vec<Vec3> = vec![];
+    let mut libraries = vec![];
+
+    // one mesh per material, with its vertices for each combination of position, uv and normal
+    let mut meshes: Vec<(Option<String>, MeshData)> = vec![];
+    let mut vertex_maps: Vec<HashMap<Corner, usize>> = vec![];
+    let mut current: Option<usize> = None;
+    let mut material: Option<String> = None;
+
+    for (line_number, line) in source.lines().enumerate() {
+        let error = |message: &str| -> ! { panic!("{}:{}: {}", path, line_number + 1, message) };
+        let mut tokens = line.split_whitespace();
+        let number = |token: Option<&str>| -> f64 {
+            token.and_then(|t| t.parse().ok()).unwrap_or_else(|| error("expected a number"))
+        };
+
+        match tokens.next() {
+            Some("v") => {
+                positions.push(Vec3::new(number(tokens.next()), number(tokens.next()), number(tokens.next())));
+                // some programs write the vertex color after the position
+                if let Some(r) = tokens.next() {
+                    colors.resize(positions.len() - 1, Color::white());
+                    colors.push(Color::new(number(Some(r)), number(tokens.next()), number(tokens.next())));
+                }
+            },
+            Some("vt") => uvs.push((number(tokens.next()), tokens.next().map_or(0.0, |t| number(Some(t))))),
+            Some("vn") => normals.push(Vec3::new(number(tokens.next()), number(tokens.next()), number(tokens.next()))),
+            Some("mtllib") => libraries.extend(tokens.map(|t| t.to_string())),
+            Some("usemtl") => {
+                material = tokens.next().map(|t| t.to_string());
+                current = None;
+            },
+            Some("f") => {
+                let mesh = *current.get_or_insert_with(|| {
+                    match meshes.iter().position(|(name, _)| *name == material) {
+                        Some(mesh) => mesh,
+                        None => {
+                            meshes.push((material.clone(), MeshData::default()));
+                            vertex_maps.push(HashMap::new());
+                            meshes.len() - 1
+                        }
+                    }
+                });
+
+                // indices start at 1, negative ones count back from the last vertex
+                let index = |token: &str, count: usize| -> usize {
+                    let index: i64 = token.parse().unwrap_or_else(|_| error("invalid vertex index"));
+                    let index = if index < 0 { count as i64 + index } else { index - 1 };
+                    if index < 0 || index >= count as i64 {
+                        error("vertex index out of range");
+                    }
+                    index as usize
+                };
+
+                let mut corners = vec![];
+                for token in tokens {
+                    // v, v/vt, v//vn or v/vt/vn
+                    let mut parts = token.split('/');
+                    let position = index(parts.next().unwrap(), positions.len());
+                    let uv = parts.next().filter(|t| !t.is_empty()).map(|t| index(t, uvs.len()));
+                    let normal = parts.next().filter(|t| !t.is_empty()).map(|t| index(t, normals.len()));
+
+                    let data = &mut meshes[mesh].1;
+                    let vertex = *vertex_maps[mesh].entry((position, uv, normal)).or_insert_with(|| {
+                        data.positions.push(positions[position]);
+                        data.uvs.push(uv.map_or((0.0, 0.0), |uv| uvs[uv]));
+                        data.normals.push(normal.map_or(Vec3::zero(), |normal| normals[normal]));
+                        data.colors.push(colors.get(position).copied().unwrap_or_else(Color::white));
+                        data.positions.len() - 1
+                    });
+                    corners.push(vertex);
+                }
+                if corners.len() < 3 {
+                    error("faces need at least three vertices");
+                }
+
+                for i in 1..corners.len() - 1 {
+                    meshes[mesh].1.triangles.push([corners[0], corners[i], corners[i + 1]]);
+                }
+            },
+            _ => {}
+        }
+    }
+
+    // leave out the attributes the file does not have, meshes without texture coordinates fall
+    // back to barycentric ones. Normals are only used if all vertices have one
+    for (_, data) in &mut meshes {
+        if uvs.is_empty() {
+            data.uvs.clear();
+        }
+        if data.normals.iter().any(|n| n.near_zero()) {
+            data.normals.clear();
+        }
+        if colors.is_empty() {
+            data.colors.clear();
+        }
+    }
+
+    (meshes, libraries)
+}
+
+// Material as described in an MTL file, with the Phong like parameters of the format
+struct MtlMaterial {
+    diffuse: Color,
+    specular: Color,
+    // specular exponent
+    shininess: f64,
+    ior: Option<f64>,
+    opacity: f64,
+    emission: Color,
+    illumination: u32,
+    diffuse_map: Option<String>,
+    // file and strength
+    bump_map: Option<(String, f64)>,
+    alpha_map: Option<String>,
+}
+
+// reads the materials of an MTL file, texture maps are looked up relative to the directory
+fn parse_mtl(source: &str, directory: &Path) -> HashMap<String, Material> {
+    let mut definitions: Vec<(String, MtlMaterial)> = vec![];
+
+    for line in source.lines() {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let (keyword, arguments) = match tokens.split_first() {
+            Some((keyword, arguments)) => (*keyword, arguments),
+            None => continue
+        };
+
+        if keyword == "newmtl" {
+            definitions.push((arguments.join(" "), MtlMaterial {
+                diffuse: Color::new(0.8, 0.8, 0.8),
+                specular: Color::black(),
+                shininess: 0.0,
+                ior: None,
+                opacity: 1.0,
+                emission: Color::black(),
+                illumination: 2,
+                diffuse_map: None,
+                bump_map: None,
+                alpha_map: None,
+            }));
+            continue;
+        }
+
+        let material = match definitions.last_mut() {
+            Some((_, material)) => material,
+            None => continue
+        };
+        let numbers: Vec<f64> = arguments.iter().filter_map(|a| a.parse().ok()).collect();
+        let color = || match numbers[..] {
+            [r, g, b, ..] => Color::new(r, g, b),
+            [value] => Color::new(value, value, value),
+            _ => panic!("Invalid color in MTL file: {}", line)
+        };
+        let number = || *numbers.first().unwrap_or_else(|| panic!("Invalid number in MTL file: {}", line));
+
+        match keyword {
+            "Kd" => material.diffuse = color(),
+            "Ks" => material.specular = color(),
+            "Ke" => material.emission = color(),
+            "Ns" => material.shininess = number(),
+            "Ni" => material.ior = Some(number()),
+            "d" => material.opacity = number(),
+            "Tr" => material.opacity = 1.0 - number(),
+            "illum" => material.illumination = number() as u32,
+            "map_Kd" => material.diffuse_map = arguments.last().map(|file| file.to_string()),
+            "map_d" => material.alpha_map = arguments.last().map(|file| file.to_string()),
+            "map_bump" | "map_Bump" | "bump" => {
+                // the strength is given by the -bm option
+                let strength = arguments
+                    .iter()
+                    .position(|a| *a == "-bm")
+                    .and_then(|i| arguments.get(i + 1))
+                    .and_then(|a| a.parse().ok())
+                    .unwrap_or(1.0);
+                material.bump_map = arguments.last().map(|file| (file.to_string(), strength));
+            },
+            _ => {}
+        }
+    }
+
+    // texture files used by several materials are only loaded once, bump and alpha maps are not sRGB
+    let mut images: HashMap<(String, bool), Arc<ImageTexture>> = HashMap::new();
+    let mut image = |file: &str, srgb: bool| images
+        .entry((file.to_string(), srgb))
+        .or_insert_with(|| Arc::new(ImageTexture::load(directory.join(file).to_str().unwrap(), srgb)))
+        .clone();
+
+    definitions
+        .into_iter()
+        .map(|(name, mtl)| {
+            let diffuse_map = mtl.diffuse_map.as_ref().map(|file| Texture::Image { image: image(file, true) });
+            let bump_map = mtl.bump_map.as_ref().map(|(file, strength)| (Texture::Image { image: image(file, false) }, *strength));
+            // the alpha channel of the image if it has one, its brightness otherwise
+            let alpha_map = mtl.alpha_map.as_ref().map(|file| match image(file, false) {
+                image if image.has_alpha() => Texture::Alpha { image, factor: 1.0 },
+                image => Texture::Image { image }
+            });
+            (name, mtl.to_material(diffuse_map, bump_map, alpha_map))
+        })
+        .collect()
+}
+
+impl MtlMaterial {
+
+    // Maps the parameters onto the closest material: glass for the refracting illumination
+    // models, metal for mirrors or specular without diffuse, and otherwise diffuse with a glossy
+    // coating if there is a specular color
+    fn to_material(&self, diffuse_map: Option<Texture>, bump_map: Option<(Texture, f64)>, alpha_map: Option<Texture>) -> Material {
+        let textured = diffuse_map.is_some();
+        let albedo = diffuse_map.unwrap_or(Texture::Constant { color: self.diffuse });
+        let ior = self.ior.filter(|ior| *ior > 1.0).unwrap_or(1.5);
+        // width of the highlight of a Phong lobe with the exponent, as a microfacet roughness
+        let alpha = (2.0 / (self.shininess + 2.0)).sqrt();
+
+        let mut material = match self.illumination {
+            4 | 6 | 7 | 9 => Material::Dielectric { ior: Ior::Constant(ior), color: Texture::Constant { color: Color::white() }, film: None },
+            3 | 5 => Material::Metal { albedo: Texture::Constant { color: self.specular }, fuzz: alpha, film: None },
+            0 | 1 => Material::Lambertian { albedo },
+            _ if self.specular.near_zero() => Material::Lambertian { albedo },
+            _ if self.diffuse.near_zero() && !textured => {
+                Material::Metal { albedo: Texture::Constant { color: self.specular }, fuzz: alpha, film: None }
+            },
+            _ => Material::Layered {
+                base: Box::new(Material::Lambertian { albedo }),
+                ior,
+                roughness: alpha.sqrt(),
+                thickness: 1.0,
+                tint: Color::white()
+            }
+        };
+
+        if let Some((height, strength)) = bump_map {
+            material = Material::Bump { base: Box::new(material), height, strength };
+        }
+        if !self.emission.near_zero() {
+            material = Material::Emissive { base: Box::new(material), emission: Emission::new(Texture::Constant { color: self.emission }) };
+        }
+        // the alpha map and the opacity both cut out the surface
+        if let Some(alpha) = alpha_map {
+            material = Material::Cutout { base: Box::new(material), alpha, threshold: None };
+        }
+        if self.opacity < 1.0 {
+            material = Material::Cutout { base: Box::new(material), alpha: Texture::Constant { color: Color::new(self.opacity, self.opacity, self.opacity) }, threshold: None };
+        }
+
+        material
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_obj() {
+        let source = "
+            mtllib model.mtl
+            v 0 0 0
+            v 1 0 0
+            v 1 1 0
+            v 0 1 0
+            vt 0 0
+            vt 1 0
+            vt 1 1
+            vt 0 1
+            vn 0 0 1
+            usemtl red
+            f 1/1 2/2 3/3 4/4
+            usemtl green
+            f -4//1 -2//1 -1//1
+            usemtl red
+            f 1/1 3/3 4/4
+        ";
+        let (meshes, libraries) = parse_obj(source, "test.obj");
+        assert_eq!(libraries, vec!["model.mtl"]);
+        assert_eq!(meshes.len(), 2);
+
+        // the quad is split into two triangles and the vertices are shared
+        let (name, red) = &meshes[0];
+        assert_eq!(name.as_deref(), Some("red"));
+        assert_eq!(red.triangles, vec![[0, 1, 2], [0, 2, 3], [0, 2, 3]]);
+        assert_eq!(red.positions.len(), 4);
+        assert_eq!(red.uvs[2], (1.0, 1.0));
+
+        let (name, green) = &meshes[1];
+        assert_eq!(name.as_deref(), Some("green"));
+        assert!(green.positions[2].is_close(&Vec3::new(0.0, 1.0, 0.0)));
+        assert_eq!(green.uvs[2], (0.0, 0.0));
+        assert!(green.normals[0].is_close(&Vec3::new(0.0, 0.0, 1.0)));
+        assert!(red.normals.is_empty());
+
+        let materials = parse_mtl("
+            newmtl glass
+            Kd 0 0 0
+            Ni 1.33
+            illum 7
+            newmtl plastic
+            Kd 0.8 0.1 0.1
+            Ks 0.5 0.5 0.5
+            Ns 100
+            d 0.5
+        ", Path::new(""));
+        assert!(matches!(materials["glass"], Material::Dielectric { ior: Ior::Constant(ior), .. } if ior == 1.33));
+        match &materials["plastic"] {
+            Material::Cutout { base, .. } => assert!(matches!(**base, Material::Layered { .. })),
+            other => panic!("Expected a cutout, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn test_alpha_map() {
+        // the alpha channel of the image cuts out the surface
+        let directory = std::env::temp_dir();
+        image::RgbaImage::from_pixel(1, 1, image::Rgba([255, 255, 255, 51])).save(directory.join("rayo-alpha-map.png")).unwrap();
+        let materials = parse_mtl("
+            newmtl leaf
+            Kd 0.1 0.5 0.1
+            map_d rayo-alpha-map.png
+        ", &directory);
+        match &materials["leaf"] {
+            Material::Cutout { alpha: Texture::Alpha { image, .. }, threshold: None, .. } => assert!((image.alpha(0.5, 0.5) - 0.2).abs() < 1e-9),
+            other => panic!("Expected a cutout, got {:?}", other)
+        }
+    }
+}
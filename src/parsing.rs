@@ -1,21 +1,42 @@
+use std::collections::HashMap;
+use std::fs;
 use std::io::Read;
 use std::panic;
+use std::path::Path;
+use std::sync::Arc;
 use json_comments::StripComments;
 
 use json::JsonValue;
 use crate::camera::Camera;
+use crate::cli::CliOptions;
 use crate::color::Color;
+use crate::grid::DensityGrid;
+use crate::ies::IesProfile;
+use crate::integrator::{bdpt::BidirectionalPathTracer, debug::{DebugIntegrator, DebugMode}, mlt::Pssmlt, path::PathTracer, ppm::ProgressivePhotonMapper, Integrator};
+use crate::intersection::mesh::Mesh;
+use crate::intersection::object::Object;
 use crate::intersection::sphere::Sphere;
 use crate::intersection::scene::Scene;
-use crate::material::Material;
-use crate::texture::Texture;
+use crate::ior::Ior;
+use crate::material::{Emission, Material};
+use crate::medium::Medium;
+use crate::merl::MerlBrdf;
+use crate::obj;
+use crate::ply;
+use crate::stl;
+use crate::texture::{ImageTexture, Texture};
+use crate::thin_film::ThinFilm;
+use crate::transform::Transform;
 use crate::vec::Vec3;
 
 pub trait ParseJson<T> {
     fn parse_json(json_value: &JsonValue) -> T;
 }
 
-pub fn parse_scene(scene_json: String, aspect_ratio: f64) -> (Scene, Camera) {
+// materials defined once and referenced by name
+type MaterialLibrary = HashMap<String, Material>;
+
+pub fn parse_scene(scene_json: String, opts: &CliOptions) -> (Scene, Camera, Box<dyn Integrator>) {
 
     // strip comments
     let mut stripped = String::new();
@@ -24,22 +45,15 @@ pub fn parse_scene(scene_json: String, aspect_ratio: f64) -> (Scene, Camera) {
     // parse the JSON
     let parsed = json::parse(&stripped).unwrap();
 
-    let mut scene = Scene::empty();
-
     match parsed {
         JsonValue::Object(obj) => {
-            let spheres = &obj["spheres"];
-
-            match spheres {
-                JsonValue::Array(spheres_vec) => {
+            let materials = parse_material_library(&obj["material-libraries"], &obj["materials"]);
+            let spheres = obj["spheres"].members().map(|sphere| Object::Sphere(parse_sphere(sphere, &materials)));
+            let meshes = obj["meshes"].members().flat_map(|mesh| parse_mesh(mesh, &materials)).map(Object::Mesh);
+            let mut scene = Scene::new(spheres.chain(meshes).collect());
 
-                    for sphere_json in spheres_vec {
-                        let sphere = Sphere::parse_json(sphere_json);
-                        scene.spheres.push(sphere);
-                    }
-
-                }
-                _ => panic!()
+            if !obj["world-medium"].is_null() {
+                scene.medium = Some(Medium::parse_json(&obj["world-medium"]));
             }
 
             let cam_json = &obj["camera"];
@@ -54,30 +68,207 @@ pub fn parse_scene(scene_json: String, aspect_ratio: f64) -> (Scene, Camera) {
                     let focal_length = &obj["focal-length"].as_f64().unwrap();
                     let aperture = &obj["aperture"].as_f64().unwrap();
                     
-                    Camera::new(lookfrom, lookat, vup, *vfov, *focal_length, *aperture, aspect_ratio)
+                    Camera::new(lookfrom, lookat, vup, *vfov, *focal_length, *aperture, opts.aspect_ratio)
                 },
                 _ => panic!()
             };
 
-            (scene, camera) 
+            let integrator = parse_integrator(&obj["integrator"], opts);
+
+            (scene, camera, integrator)
         },
         _ => panic!("Scene should be an object"),
     }
 }
 
+// Reads the named materials, first from the library files and then from the scene. Later
+// definitions replace earlier ones of the same name and can refer to them
+fn parse_material_library(files: &JsonValue, materials: &JsonValue) -> MaterialLibrary {
+    let mut library = MaterialLibrary::new();
+
+    let mut add = |definitions: &JsonValue| match definitions {
+        JsonValue::Object(obj) => {
+            for (name, material) in obj.iter() {
+                let material = parse_material(material, &library);
+                library.insert(name.to_string(), material);
+            }
+        },
+        JsonValue::Null => {},
+        _ => panic!("Materials should be an object mapping names to materials")
+    };
+
+    for file in files.members() {
+        let path = file.as_str().unwrap_or_else(|| panic!("Material libraries should be file paths"));
+        add(&parse_json_file(path));
+    }
+    add(materials);
+
+    library
+}
+
+// parses a JSON file that can contain comments
+fn parse_json_file(path: &str) -> JsonValue {
+    let contents = fs::read_to_string(path).unwrap_or_else(|e| panic!("Could not read {}: {}", path, e));
+    let mut stripped = String::new();
+    StripComments::new(contents.as_bytes()).read_to_string(&mut stripped).unwrap();
+    json::parse(&stripped).unwrap_or_else(|e| panic!("Invalid JSON in {}: {}", path, e))
+}
+
+pub fn parse_integrator(json_value: &JsonValue, opts: &CliOptions) -> Box<dyn Integrator> {
+    let settings = integrator_settings(json_value, opts);
+
+    let max_depth = settings["max-depth"].as_u32().unwrap_or(30);
+
+    let rr_depth = settings["rr-depth"].as_u32().unwrap_or(5);
+
+    let spectral = opts.spectral || settings["spectral"].as_bool().unwrap_or(false);
+
+    match settings["type"].as_str().unwrap_or("path") {
+        "path" => Box::new(PathTracer { max_depth, rr_depth, spectral }),
+        "mlt" => {
+            let bootstrap_samples = settings["bootstrap-samples"].as_u32().unwrap_or(100000);
+            let chains = settings["chains"].as_u32().unwrap_or(1000);
+            let large_step_probability = settings["large-step-probability"].as_f64().unwrap_or(0.3);
+            let path_tracer = PathTracer { max_depth, rr_depth, spectral };
+            Box::new(Pssmlt { path_tracer, bootstrap_samples, chains, large_step_probability })
+        },
+        "bdpt" => Box::new(BidirectionalPathTracer { max_depth, spectral }),
+        "ppm" => {
+            if spectral {
+                panic!("Spectral mode is not supported by the ppm integrator");
+            }
+            let photons_per_iteration = settings["photons"].as_u32().unwrap_or(100000);
+            let initial_radius = settings["radius"].as_f64().unwrap_or(0.1);
+            let alpha = settings["alpha"].as_f64().unwrap_or(2.0 / 3.0);
+            Box::new(ProgressivePhotonMapper { max_depth, rr_depth, photons_per_iteration, initial_radius, alpha })
+        },
+        "normals" => Box::new(DebugIntegrator { mode: DebugMode::Normals }),
+        "albedo" => Box::new(DebugIntegrator { mode: DebugMode::Albedo }),
+        "depth" => {
+            let far = settings["far"].as_f64().unwrap_or(10.0);
+            Box::new(DebugIntegrator { mode: DebugMode::Depth { far } })
+        },
+        "uv" => Box::new(DebugIntegrator { mode: DebugMode::Uv }),
+        "object-id" => Box::new(DebugIntegrator { mode: DebugMode::ObjectId }),
+        "ao" => {
+            let radius = settings["radius"].as_f64().unwrap_or(1.0);
+            Box::new(DebugIntegrator { mode: DebugMode::AmbientOcclusion { radius } })
+        },
+        other => panic!("Unknown integrator type: {}", other)
+    }
+}
+
+// the settings of the integrator the scene asks for, with the command line options applied
+fn integrator_settings(json_value: &JsonValue, opts: &CliOptions) -> JsonValue {
+
+    // the scene can either give the name of the integrator or an object with its settings
+    let mut settings = match json_value {
+        JsonValue::Null => json::object! {"type": "path"},
+        JsonValue::Short(_) | JsonValue::String(_) => json::object! {"type": json_value.as_str()},
+        JsonValue::Object(_) => json_value.clone(),
+        _ => panic!("Integrator should be a name or an object")
+    };
+
+    // command line options take precedence over the scene file
+    if let Some(name) = &opts.integrator {
+        if settings["type"].as_str() != Some(name) {
+            settings = json::object! {"type": name.as_str()};
+        }
+    }
+    if let Some(max_depth) = opts.max_depth {
+        settings["max-depth"] = max_depth.into();
+    }
+    settings
+}
+
 impl ParseJson<Sphere> for Sphere {
     
     fn parse_json(json_value: &JsonValue) -> Sphere {
+        parse_sphere(json_value, &MaterialLibrary::new())
+    }
+}
 
-        match json_value {
-            JsonValue::Object(obj) => {
-                let center = Vec3::parse_json(&obj["center"]);
-                let radius = obj["radius"].as_f64().unwrap_or_else(|| {panic!()});
-                let material = Material::parse_json(&obj["material"]);
-                Sphere { center, radius, material }
-            },
-            _ => panic!("Sphere should be an object")
-        }
+fn parse_sphere(json_value: &JsonValue, library: &MaterialLibrary) -> Sphere {
+    match json_value {
+        JsonValue::Object(obj) => {
+            let center = Vec3::parse_json(&obj["center"]);
+            let radius = obj["radius"].as_f64().unwrap_or_else(|| {panic!()});
+            let material = parse_material(&obj["material"], library);
+            let medium = if obj["medium"].is_null() { None } else { Some(Medium::parse_json(&obj["medium"])) };
+            Sphere { center, radius, material, medium }
+        },
+        _ => panic!("Sphere should be an object")
+    }
+}
+
+// Loads the meshes of an OBJ, PLY or STL file, picked by the extension. OBJ files have one mesh
+// for each of their materials, which come from the MTL files unless they are overridden by the
+// scene, all at once or by name
+fn parse_mesh(json_value: &JsonValue, library: &MaterialLibrary) -> Vec<Mesh> {
+    match json_value {
+        JsonValue::Object(obj) => {
+            let file = obj["file"].as_str().unwrap_or_else(|| panic!("Mesh should have a file"));
+            let extension = Path::new(file).extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase());
+            let (meshes, materials) = match extension.as_deref() {
+                Some("obj") => {
+                    let model = obj::load(file);
+                    (model.meshes, model.materials)
+                },
+                Some("ply") => (vec![(None, ply::load(file))], HashMap::new()),
+                Some("stl") => (vec![(None, stl::load(file))], HashMap::new()),
+                _ => panic!("Unknown mesh format of {}, expected .obj, .ply or .stl", file)
+            };
+            let medium = if obj["medium"].is_null() { None } else { Some(Medium::parse_json(&obj["medium"])) };
+
+            // the model is scaled, rotated and then moved
+            let scale = match &obj["scale"] {
+                JsonValue::Null => Vec3::new(1.0, 1.0, 1.0),
+                JsonValue::Number(_) => {
+                    let scale = obj["scale"].as_f64().unwrap();
+                    Vec3::new(scale, scale, scale)
+                },
+                scale => Vec3::parse_json(scale)
+            };
+            let mut transform = Transform::scale(scale);
+            if !obj["rotation"].is_null() {
+                transform = transform.then(&Transform::rotation(Vec3::parse_json(&obj["rotation"])));
+            }
+            if !obj["translation"].is_null() {
+                transform = transform.then(&Transform::translation(Vec3::parse_json(&obj["translation"])));
+            }
+
+            // normals are generated for files without them, or when the ones of the file are replaced
+            let recompute_normals = obj["recompute-normals"].as_bool().unwrap_or(false);
+            let crease_angle = obj["crease-angle"].as_f64();
+
+            let overrides = &obj["material-overrides"];
+            meshes.into_iter().map(|(name, mut data)| {
+                let overridden = name.as_ref().map_or(&JsonValue::Null, |name| &overrides[name.as_str()]);
+                let material = if !obj["material"].is_null() {
+                    parse_material(&obj["material"], library)
+                } else if !overridden.is_null() {
+                    parse_material(overridden, library)
+                } else {
+                    // faces without a known material have their vertex colors, or are light gray
+                    let albedo = if data.colors.is_empty() {
+                        Texture::Constant { color: Color::new(0.8, 0.8, 0.8) }
+                    } else {
+                        Texture::VertexColor
+                    };
+                    name.and_then(|name| materials.get(&name).cloned()).unwrap_or(Material::Lambertian { albedo })
+                };
+
+                if recompute_normals {
+                    data.normals.clear();
+                }
+                if let (true, Some(crease_angle)) = (data.normals.is_empty(), crease_angle) {
+                    data.smooth_normals(crease_angle);
+                }
+                data.transform(&transform);
+                Mesh::new(data, material, medium.clone())
+            }).collect()
+        },
+        _ => panic!("Mesh should be an object")
     }
 }
 
@@ -123,62 +314,281 @@ impl ParseJson<Color> for Color {
     }
 }
 
+// a number, the name of a preset or the coefficients of a dispersion formula
+impl ParseJson<Ior> for Ior {
+    fn parse_json(json_value: &JsonValue) -> Ior {
+        if let Some(ior) = json_value.as_f64() {
+            return Ior::Constant(ior);
+        }
+        if let Some(name) = json_value.as_str() {
+            return Ior::preset(name).unwrap_or_else(|| panic!("Unknown IOR preset {}", name));
+        }
+
+        // all three coefficients are needed, a missing one would silently change the index
+        let coefficients = |key: &str| {
+            let mut values = [0.0; 3];
+            for (i, value) in values.iter_mut().enumerate() {
+                *value = json_value[key][i].as_f64()
+                    .unwrap_or_else(|| panic!("Sellmeier IOR needs {} as three numbers", key));
+            }
+            values
+        };
+
+        match json_value["type"].as_str() {
+            Some("cauchy") => Ior::Cauchy {
+                a: json_value["a"].as_f64().unwrap_or_else(|| panic!("Cauchy IOR needs a")),
+                b: json_value["b"].as_f64().unwrap_or_else(|| panic!("Cauchy IOR needs b")),
+            },
+            Some("sellmeier") => Ior::Sellmeier { b: coefficients("b"), c: coefficients("c") },
+            _ => panic!("IOR should be a number, a preset or a cauchy or sellmeier object")
+        }
+    }
+}
+
 impl ParseJson<Material> for Material {
     fn parse_json(json_value: &JsonValue) -> Material {
+        parse_material(json_value, &MaterialLibrary::new())
+    }
+}
+
+// a material object, or the name of one in the library
+fn parse_material(json_value: &JsonValue, library: &MaterialLibrary) -> Material {
+    match json_value {
+        JsonValue::Short(_) | JsonValue::String(_) => {
+            let name = json_value.as_str().unwrap();
+            library.get(name).cloned().unwrap_or_else(|| {
+                let mut known: Vec<&str> = library.keys().map(|k| k.as_str()).collect();
+                known.sort_unstable();
+                panic!("Unknown material \"{}\", the known materials are: {}", name, known.join(", "))
+            })
+        },
+        JsonValue::Object(obj) => {
+
+            let material_type = obj["type"].as_str().unwrap();
+
+            let material = match material_type {
+                "lambertian" => {
+                    let albedo = Texture::parse_json(&obj["albedo"]);
+                    Material::Lambertian {albedo}
+                },
+                "orenNayar" => {
+                    let albedo = Texture::parse_json(&obj["albedo"]);
+                    let sigma = Texture::parse_json(&obj["sigma"]);
+                    Material::OrenNayar {albedo, sigma}
+                },
+                "sheen" => {
+                    let albedo = Texture::parse_json(&obj["albedo"]);
+                    let sheen = Texture::parse_json(&obj["sheen"]);
+                    let roughness = if obj["roughness"].is_null() { Texture::Constant {color: Color::new(0.5, 0.5, 0.5)} } else { Texture::parse_json(&obj["roughness"]) };
+                    Material::Sheen {albedo, sheen, roughness}
+                },
+                "measured" => {
+                    let path = obj["file"].as_str().unwrap_or_else(|| panic!("Measured material should name its BRDF file"));
+                    Material::Measured {brdf: Arc::new(MerlBrdf::load(path))}
+                },
+                "metal" => {
+                    let albedo = Texture::parse_json(&obj["albedo"]);
+                    let fuzz = obj["fuzz"].as_f64().unwrap_or_else(|| { panic!("Fuzz should be a float") });
+                    let film = parse_film(&obj["film"]);
+                    Material::Metal {albedo, fuzz, film}
+                },
+                "dielectric" => {
+                    let ior = Ior::parse_json(&obj["ior"]);
+                    let color = Texture::parse_json(&obj["color"]);
+                    let film = parse_film(&obj["film"]);
+                    Material::Dielectric {ior, color, film}
+                }
+                "diffuseLight" => {
+                    let emission = Emission::parse_json(json_value);
+                    Material::DiffuseLight {emission}
+                }
+                "interface" => Material::Interface,
+                "subsurface" => {
+                    let albedo = Texture::parse_json(&obj["albedo"]);
+                    let mean_free_path = Color::parse_json(&obj["mean-free-path"]);
+                    if mean_free_path.r <= 0.0 || mean_free_path.g <= 0.0 || mean_free_path.b <= 0.0 {
+                        panic!("Mean free path should be positive");
+                    }
+                    let g = obj["anisotropy"].as_f64().unwrap_or(0.0);
+                    Material::Subsurface {albedo, mean_free_path, g}
+                },
+                "mix" => {
+                    let first = Box::new(parse_material(&obj["first"], library));
+                    let second = Box::new(parse_material(&obj["second"], library));
+                    let mask = parse_data_texture(&obj["mask"]);
+                    Material::Mix {first, second, mask}
+                },
+                "layered" => {
+                    let base = Box::new(parse_material(&obj["base"], library));
+                    let ior = obj["ior"].as_f64().unwrap_or(1.5);
+                    let roughness = obj["roughness"].as_f64().unwrap_or(0.0).clamp(0.0, 1.0);
+                    let thickness = obj["thickness"].as_f64().unwrap_or(1.0);
+                    let tint = if obj["tint"].is_null() { Color::white() } else { Color::parse_json(&obj["tint"]) };
+                    Material::Layered {base, ior, roughness, thickness, tint}
+                },
+                _ => panic!("Unknown material type")
+            };
+
+            // any material can additionally emit light
+            let material = if obj["emission"].is_null() {
+                material
+            } else {
+                let emission = Emission::parse_json(&obj["emission"]);
+                Material::Emissive { base: Box::new(material), emission }
+            };
+
+            // small bumps on its surface
+            let material = if obj["bump"].is_null() {
+                material
+            } else {
+                let height = parse_data_texture(&obj["bump"]);
+                let strength = obj["bump-strength"].as_f64().unwrap_or(1.0);
+                Material::Bump { base: Box::new(material), height, strength }
+            };
+
+            // and have holes cut into it
+            if obj["alpha"].is_null() {
+                material
+            } else {
+                let alpha = parse_data_texture(&obj["alpha"]);
+                let threshold = obj["alpha-threshold"].as_f64();
+                Material::Cutout { base: Box::new(material), alpha, threshold }
+            }
+        },
+        json => panic!("Material should be an object or the name of a library material, got {}", json)
+    }
+}
+
+// optional thin film coating of a material
+fn parse_film(json_value: &JsonValue) -> Option<ThinFilm> {
+    if json_value.is_null() {
+        return None;
+    }
+    let thickness = Texture::parse_json(&json_value["thickness"]);
+    let ior = json_value["ior"].as_f64().unwrap_or_else(|| panic!("Film IOR should be a float"));
+    Some(ThinFilm {thickness, ior})
+}
+
+impl ParseJson<Emission> for Emission {
+    fn parse_json(json_value: &JsonValue) -> Emission {
         match json_value {
             JsonValue::Object(obj) => {
-    
-                let material_type = obj["type"].as_str().unwrap();
-
-                match material_type {
-                    "lambertian" => {
-                        let albedo = Texture::parse_json(&obj["albedo"]);
-                        Material::Lambertian {albedo}
-                    },
-                    "metal" => {
-                        let albedo = Texture::parse_json(&obj["albedo"]);
-                        let fuzz = obj["fuzz"].as_f64().unwrap_or_else(|| { panic!("Fuzz should be a float") });
-                        Material::Metal {albedo, fuzz}
-                    },
-                    "dielectric" => {
-                        let ior = obj["ior"].as_f64().unwrap();
-                        let color = Texture::parse_json(&obj["color"]);
-                        Material::Dielectric {ior, color}
-                    }
-                    "diffuseLight" => {
-                        let texture = Texture::parse_json(&obj["color"]);
-                        Material::DiffuseLight {texture}
-                    }
-                    _ => panic!("Unknown material type")
+                let mut emission = Emission::new(Texture::parse_json(&obj["color"]));
+
+                if let Some(strength) = obj["strength"].as_f64() {
+                    emission.strength = strength;
                 }
+                if let Some(path) = obj["profile"].as_str() {
+                    let profile = IesProfile::load(path).unwrap_or_else(|e| panic!("Could not read IES profile {}: {}", path, e));
+                    emission.profile = Some(Arc::new(profile));
+                }
+                if !obj["orientation"].is_null() {
+                    emission.orientation = Vec3::parse_json(&obj["orientation"]);
+                }
+
+                emission
             },
-            _ => todo!()
+            _ => panic!("Emission should be an object")
         }
     }
 }
 
-impl ParseJson<Texture> for Texture {
-    fn parse_json(json_value: &JsonValue) -> Texture {
+impl ParseJson<Medium> for Medium {
+    fn parse_json(json_value: &JsonValue) -> Medium {
         match json_value {
             JsonValue::Object(obj) => {
-                let texture_type = obj["type"].as_str().unwrap();
-                match texture_type {
-                    "checker" => {
-                        let odd = Color::parse_json(&obj["odd"]);
-                        let even = Color::parse_json(&obj["even"]);
-                        let size = obj["size"].as_f64().unwrap();
-                        let round = obj["round"].as_f64().unwrap();
-                        Texture::Checkered {odd, even, size, round}
-                    }
-                    _ => todo!()
+                let absorption = Color::parse_json(&obj["absorption"]);
+                let scattering = Color::parse_json(&obj["scattering"]);
+                let g = obj["anisotropy"].as_f64().unwrap_or(0.0);
+                if g.abs() >= 1.0 {
+                    panic!("Anisotropy should be between -1 and 1");
                 }
+                let grid = if obj["grid"].is_null() { None } else { Some(Arc::new(DensityGrid::parse_json(&obj["grid"]))) };
+                Medium { absorption, scattering, grid, g }
+            },
+            _ => panic!("Medium should be an object")
+        }
+    }
+}
+
+impl ParseJson<DensityGrid> for DensityGrid {
+    fn parse_json(json_value: &JsonValue) -> DensityGrid {
+        match json_value {
+            JsonValue::Object(obj) => {
+                let file = obj["file"].as_str().unwrap_or_else(|| panic!("Grid should have a file"));
+                let min = Vec3::parse_json(&obj["min"]);
+                let max = Vec3::parse_json(&obj["max"]);
+
+                // the grid fills the box from min to max, which is then rotated and moved
+                let mut grid_to_world = Transform::scale(max - min).then(&Transform::translation(min));
+                if !obj["rotation"].is_null() {
+                    grid_to_world = grid_to_world.then(&Transform::rotation(Vec3::parse_json(&obj["rotation"])));
+                }
+                if !obj["translation"].is_null() {
+                    grid_to_world = grid_to_world.then(&Transform::translation(Vec3::parse_json(&obj["translation"])));
+                }
+
+                DensityGrid::load(file, grid_to_world)
             },
-            JsonValue::Array(_) => Texture::Constant {color: Color::parse_json(json_value)},
-            _ => panic!()
+            _ => panic!("Grid should be an object")
         }
     }
 }
 
+impl ParseJson<Texture> for Texture {
+    fn parse_json(json_value: &JsonValue) -> Texture {
+        parse_texture(json_value, true)
+    }
+}
+
+// a texture holding data like heights or masks, whose images are not converted from sRGB
+fn parse_data_texture(json_value: &JsonValue) -> Texture {
+    parse_texture(json_value, false)
+}
+
+fn parse_texture(json_value: &JsonValue, srgb: bool) -> Texture {
+    match json_value {
+        JsonValue::Object(obj) => {
+            let texture_type = obj["type"].as_str().unwrap();
+            match texture_type {
+                "checker" => {
+                    let odd = Color::parse_json(&obj["odd"]);
+                    let even = Color::parse_json(&obj["even"]);
+                    let size = obj["size"].as_f64().unwrap();
+                    let round = obj["round"].as_f64().unwrap();
+                    Texture::Checkered {odd, even, size, round}
+                }
+                "image" => {
+                    let file = obj["file"].as_str().unwrap_or_else(|| panic!("Image texture should have a file"));
+                    let image = Arc::new(ImageTexture::load(file, srgb));
+                    match obj["channel"].as_str() {
+                        None | Some("color") => Texture::Image {image},
+                        Some("alpha") => Texture::Alpha {image, factor: 1.0},
+                        Some(channel) => panic!("Image channel should be color or alpha, got {}", channel)
+                    }
+                }
+                "vertex-color" => Texture::VertexColor,
+                "blackbody" => {
+                    let temperature = obj["temperature"].as_f64().unwrap_or_else(|| { panic!("Temperature should be a float") });
+                    if temperature <= 0.0 {
+                        panic!("Blackbody temperature should be positive (in Kelvin), got {}", temperature);
+                    }
+                    Texture::Constant {color: Color::blackbody(temperature)}
+                }
+                _ => todo!()
+            }
+        },
+        JsonValue::Array(_) => Texture::Constant {color: Color::parse_json(json_value)},
+        // a single number is a gray texture, for scalar values like the thickness of a film
+        JsonValue::Number(_) => {
+            let value = json_value.as_f64().unwrap();
+            Texture::Constant {color: Color::new(value, value, value)}
+        },
+        _ => panic!()
+    }
+}
+
+#[cfg(test)]
 mod test {
     use super::*;
 
@@ -188,5 +598,71 @@ mod test {
         let vec = Vec3::parse_json(&parsed);
         assert!(vec.is_close(&Vec3::new(1.0, 2.0, 3.5)));
     }
-    
-}
\ No newline at end of file
+
+    #[test]
+    fn test_material_references() {
+        let materials = json::parse(r#"{
+            "red": {"type": "lambertian", "albedo": [0.8, 0.1, 0.1]},
+            "coated": {"type": "layered", "base": "red"}
+        }"#).unwrap();
+        let library = parse_material_library(&JsonValue::Null, &materials);
+
+        let sphere = json::parse(r#"{"center": [0, 0, 0], "radius": 1, "material": "coated"}"#).unwrap();
+        match parse_sphere(&sphere, &library).material {
+            Material::Layered { base, .. } => assert!(matches!(*base, Material::Lambertian { .. })),
+            _ => panic!("Expected the referenced material")
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Blackbody temperature should be positive")]
+    fn test_blackbody_temperature() {
+        Texture::parse_json(&json::parse(r#"{"type": "blackbody", "temperature": 0}"#).unwrap());
+    }
+
+    // command line options choosing the integrator and the maximum depth
+    fn options(integrator: Option<&str>, max_depth: Option<u32>) -> CliOptions {
+        CliOptions {
+            img_x: 1,
+            img_y: 1,
+            custom_resolution: false,
+            max_depth,
+            integrator: integrator.map(|name| name.to_string()),
+            num_samples: 1,
+            output_file: String::new(),
+            scene_file: String::new(),
+            silent: true,
+            spectral: false,
+            aspect_ratio: 1.0,
+        }
+    }
+
+    #[test]
+    fn test_integrator_choice() {
+        let scene = json::parse(r#"{"type": "ppm", "photons": 1000, "max-depth": 4}"#).unwrap();
+
+        // the path tracer without settings, otherwise the scene's integrator with its settings
+        assert_eq!(integrator_settings(&JsonValue::Null, &options(None, None))["type"], "path");
+        assert_eq!(integrator_settings(&"bdpt".into(), &options(None, None))["type"], "bdpt");
+        let settings = integrator_settings(&scene, &options(None, None));
+        assert_eq!((settings["type"].as_str(), settings["photons"].as_u32()), (Some("ppm"), Some(1000)));
+
+        // the command line replaces a different integrator and its settings, but keeps the ones
+        // of the same integrator
+        let settings = integrator_settings(&scene, &options(Some("mlt"), None));
+        assert_eq!((settings["type"].as_str(), settings["photons"].as_u32()), (Some("mlt"), None));
+        let settings = integrator_settings(&scene, &options(Some("ppm"), Some(8)));
+        assert_eq!((settings["photons"].as_u32(), settings["max-depth"].as_u32()), (Some(1000), Some(8)));
+
+        // every integrator can be chosen
+        for name in ["path", "mlt", "bdpt", "ppm", "normals", "albedo", "depth", "uv", "object-id", "ao"] {
+            parse_integrator(&JsonValue::Null, &options(Some(name), None));
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Unknown integrator type: whitted")]
+    fn test_unknown_integrator() {
+        parse_integrator(&"whitted".into(), &options(None, None));
+    }
+}
@@ -0,0 +1,1001 @@
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use json::JsonValue;
+
+use crate::camera::Camera;
+use crate::cli::CliOptions;
+use crate::color::Color;
+use crate::import::{self, Warnings};
+use crate::integrator::Integrator;
+use crate::intersection::{bvh::Aabb, mesh::{Mesh, MeshData}, object::Object, scene::Scene, sphere::Sphere};
+use crate::ior::{self, Ior};
+use crate::material::{Emission, Material};
+use crate::parsing::parse_integrator;
+use crate::ply;
+use crate::spectrum;
+use crate::texture::{ImageTexture, Texture};
+use crate::transform::Transform;
+use crate::vec::Vec3;
+
+// Imports a scene in the pbrt-v4 format. Directives, shapes, materials and lights rayo does not
+// have are reported as warnings and left out. The Film resolution is used unless the command
+// line gives one, which then keeps the aspect ratio of the Film
+pub fn load(path: &str, opts: &mut CliOptions) -> (Scene, Camera, Box<dyn Integrator>) {
+    let source = fs::read_to_string(path).unwrap_or_else(|e| panic!("Could not read pbrt file {}: {}", path, e));
+    let directory = Path::new(path).parent().unwrap_or_else(|| Path::new("")).to_path_buf();
+
+    let mut importer = Importer::new(directory);
+    importer.run(&source, path);
+
+    import::apply_resolution(opts, importer.resolution);
+    let integrator = parse_integrator(&importer.integrator, opts);
+    let (objects, camera) = importer.finish(opts.aspect_ratio);
+    (Scene::new(objects), camera, integrator)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    // quoted strings
+    Text(String),
+    Open,
+    Close,
+}
+
+fn tokenize(source: &str) -> Vec<Token> {
+    let mut tokens = vec![];
+    let mut chars = source.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '#' => while chars.next_if(|&c| c != '\n').is_some() {},
+            '"' => {
+                let text: String = chars.by_ref().take_while(|&c| c != '"').collect();
+                tokens.push(Token::Text(text));
+            },
+            '[' => tokens.push(Token::Open),
+            ']' => tokens.push(Token::Close),
+            c if c.is_whitespace() => {},
+            c => {
+                let mut word = c.to_string();
+                while let Some(c) = chars.next_if(|&c| !c.is_whitespace() && !"[]\"#".contains(c)) {
+                    word.push(c);
+                }
+                tokens.push(Token::Word(word));
+            }
+        }
+    }
+    tokens
+}
+
+// The parameters of a directive, like "float radius" [2]. The values are kept as text
+struct Param {
+    kind: String,
+    name: String,
+    values: Vec<String>,
+}
+
+struct Params(Vec<Param>);
+
+impl Params {
+
+    fn get(&self, name: &str) -> Option<&Param> {
+        self.0.iter().find(|p| p.name == name)
+    }
+
+    fn floats(&self, name: &str) -> Vec<f64> {
+        self.get(name).map_or(vec![], |p| p.values.iter().filter_map(|v| v.parse().ok()).collect())
+    }
+
+    fn float(&self, name: &str, default: f64) -> f64 {
+        self.floats(name).first().copied().unwrap_or(default)
+    }
+
+    fn integers(&self, name: &str) -> Vec<usize> {
+        self.floats(name).iter().map(|&i| i as usize).collect()
+    }
+
+    fn vectors(&self, name: &str) -> Vec<Vec3> {
+        self.floats(name).chunks_exact(3).map(|c| Vec3::new(c[0], c[1], c[2])).collect()
+    }
+
+    fn string(&self, name: &str) -> Option<&str> {
+        self.get(name).and_then(|p| p.values.first()).map(|v| v.as_str())
+    }
+
+    fn bool(&self, name: &str, default: bool) -> bool {
+        self.string(name).map_or(default, |v| v == "true")
+    }
+}
+
+// reads the arguments of directives from the tokens of a file
+struct Reader<'a> {
+    tokens: &'a [Token],
+    position: usize,
+    path: &'a str,
+}
+
+impl Reader<'_> {
+
+    fn next(&mut self) -> Option<&Token> {
+        self.position += 1;
+        self.tokens.get(self.position - 1)
+    }
+
+    fn text(&mut self) -> String {
+        let path = self.path;
+        match self.next() {
+            Some(Token::Text(text)) => text.clone(),
+            other => panic!("{}: expected a quoted string, got {:?}", path, other)
+        }
+    }
+
+    // numbers, which may be in brackets
+    fn numbers(&mut self, count: usize) -> Vec<f64> {
+        let bracketed = self.tokens.get(self.position) == Some(&Token::Open);
+        if bracketed {
+            self.position += 1;
+        }
+
+        let path = self.path;
+        let numbers: Vec<f64> = (0..count)
+            .map(|_| match self.next() {
+                Some(Token::Word(word)) => word.parse().ok(),
+                _ => None
+            }.unwrap_or_else(|| panic!("{}: expected {} numbers", path, count)))
+            .collect();
+
+        if bracketed && self.next() != Some(&Token::Close) {
+            panic!("{}: expected {} numbers in brackets", path, count);
+        }
+        numbers
+    }
+
+    // skips the arguments of a directive, up to the next word starting with a capital letter
+    fn skip(&mut self) {
+        while let Some(token) = self.tokens.get(self.position) {
+            match token {
+                Token::Word(word) if word.starts_with(|c: char| c.is_ascii_uppercase()) => break,
+                _ => self.position += 1
+            }
+        }
+    }
+
+    fn params(&mut self) -> Params {
+        let mut params = vec![];
+        while let Some(Token::Text(declaration)) = self.tokens.get(self.position) {
+            let (kind, name) = match declaration.split_whitespace().collect::<Vec<&str>>()[..] {
+                [kind, name] => (kind.to_string(), name.to_string()),
+                _ => panic!("{}: invalid parameter \"{}\"", self.path, declaration)
+            };
+            self.position += 1;
+
+            let mut values = vec![];
+            let path = self.path;
+            match self.next() {
+                Some(Token::Open) => loop {
+                    match self.next() {
+                        Some(Token::Word(value)) | Some(Token::Text(value)) => values.push(value.clone()),
+                        Some(Token::Close) => break,
+                        _ => panic!("{}: unclosed bracket in parameter {}", path, name)
+                    }
+                },
+                Some(Token::Word(value)) | Some(Token::Text(value)) => values.push(value.clone()),
+                _ => panic!("{}: parameter {} has no value", path, name)
+            }
+            params.push(Param { kind, name, values });
+        }
+        Params(params)
+    }
+}
+
+#[derive(Clone)]
+enum Shape {
+    Sphere { center: Vec3, radius: f64 },
+    Mesh(MeshData),
+}
+
+#[derive(Clone)]
+struct Primitive {
+    shape: Shape,
+    material: Material,
+}
+
+impl Primitive {
+
+    fn transform(&mut self, transform: &Transform) {
+        match &mut self.shape {
+            Shape::Sphere { center, radius } => {
+                *center = transform.point(*center);
+                *radius *= transform.vector(Vec3::new(1.0, 0.0, 0.0)).norm();
+            },
+            Shape::Mesh(data) => {
+                data.transform(transform);
+                // keep the side the triangles face, which is the side area lights shine on
+                if transform.determinant() < 0.0 {
+                    data.reverse_winding();
+                }
+            },
+        }
+    }
+
+    fn bounds(&self) -> Aabb {
+        match &self.shape {
+            Shape::Sphere { center, radius } => {
+                let extent = Vec3::new(*radius, *radius, *radius);
+                Aabb::around(&[*center - extent, *center + extent])
+            },
+            Shape::Mesh(data) => Aabb::around(&data.positions),
+        }
+    }
+}
+
+// the attributes that AttributeBegin and AttributeEnd save and restore
+#[derive(Clone)]
+struct State {
+    transform: Transform,
+    material: Material,
+    area_light: Option<Emission>,
+    reverse_orientation: bool,
+}
+
+struct Importer {
+    directory: PathBuf,
+    resolution: (u32, u32),
+    integrator: JsonValue,
+    // the transformation from the world to the camera and the parameters of the camera
+    camera: Option<(Transform, Params)>,
+    state: State,
+    stack: Vec<State>,
+    coordinate_systems: HashMap<String, Transform>,
+    named_materials: HashMap<String, Material>,
+    textures: HashMap<String, Texture>,
+    // object instances, and the one being defined between ObjectBegin and ObjectEnd
+    instances: HashMap<String, Vec<Primitive>>,
+    instance: Option<(String, Vec<Primitive>)>,
+    primitives: Vec<Primitive>,
+    // radiance of the infinite lights
+    environment: Option<Color>,
+    warnings: Warnings,
+}
+
+impl Importer {
+
+    fn new(directory: PathBuf) -> Importer {
+        Importer {
+            directory,
+            // the defaults of pbrt
+            resolution: (1280, 720),
+            integrator: json::object! {"type": "path", "max-depth": 5},
+            camera: None,
+            state: State {
+                transform: Transform::identity(),
+                material: Material::Lambertian { albedo: Texture::Constant { color: Color::new(0.5, 0.5, 0.5) } },
+                area_light: None,
+                reverse_orientation: false,
+            },
+            stack: vec![],
+            coordinate_systems: HashMap::new(),
+            named_materials: HashMap::new(),
+            textures: HashMap::new(),
+            instances: HashMap::new(),
+            instance: None,
+            primitives: vec![],
+            environment: None,
+            warnings: Warnings::default(),
+        }
+    }
+
+    fn file(&self, name: &str) -> PathBuf {
+        self.directory.join(name)
+    }
+
+    // applies a transformation before the current one, like pbrt does
+    fn apply(&mut self, transform: Transform) {
+        self.state.transform = transform.then(&self.state.transform);
+    }
+
+    fn run(&mut self, source: &str, path: &str) {
+        let tokens = tokenize(source);
+        let mut reader = Reader { tokens: &tokens, position: 0, path };
+
+        while let Some(token) = reader.next() {
+            let directive = match token {
+                Token::Word(word) => word.clone(),
+                other => panic!("{}: expected a directive, got {:?}", path, other)
+            };
+
+            match directive.as_str() {
+                "Identity" => self.state.transform = Transform::identity(),
+                "Translate" => {
+                    let v = reader.numbers(3);
+                    self.apply(Transform::translation(Vec3::new(v[0], v[1], v[2])));
+                },
+                "Scale" => {
+                    let v = reader.numbers(3);
+                    self.apply(Transform::scale(Vec3::new(v[0], v[1], v[2])));
+                },
+                "Rotate" => {
+                    let v = reader.numbers(4);
+                    self.apply(Transform::axis_rotation(v[0], Vec3::new(v[1], v[2], v[3])));
+                },
+                "LookAt" => {
+                    let v = reader.numbers(9);
+                    let camera_to_world = Transform::look_at(Vec3::new(v[0], v[1], v[2]), Vec3::new(v[3], v[4], v[5]), Vec3::new(v[6], v[7], v[8]));
+                    self.apply(camera_to_world.inverse());
+                },
+                // matrices are given column by column
+                "Transform" | "ConcatTransform" => {
+                    let m = reader.numbers(16);
+                    let transform = Transform::from_rows([0, 1, 2].map(|i| [m[i], m[4 + i], m[8 + i], m[12 + i]]));
+                    if directive == "Transform" {
+                        self.state.transform = transform;
+                    } else {
+                        self.apply(transform);
+                    }
+                },
+                "CoordinateSystem" => {
+                    let name = reader.text();
+                    self.coordinate_systems.insert(name, self.state.transform);
+                },
+                "CoordSysTransform" => {
+                    let name = reader.text();
+                    match self.coordinate_systems.get(&name) {
+                        Some(transform) => self.state.transform = *transform,
+                        None => self.warnings.warn(&format!("unknown coordinate system {}", name))
+                    }
+                },
+                "TransformTimes" => {
+                    reader.numbers(2);
+                    self.warnings.warn("animated transformations are not supported");
+                },
+                "ActiveTransform" => {
+                    reader.next();
+                    self.warnings.warn("animated transformations are not supported");
+                },
+                "ReverseOrientation" => self.state.reverse_orientation = !self.state.reverse_orientation,
+                "WorldEnd" => {},
+
+                "Camera" => {
+                    let kind = reader.text();
+                    let params = reader.params();
+                    if kind != "perspective" {
+                        self.warnings.warn(&format!("{} cameras are not supported, using a perspective camera", kind));
+                    }
+                    self.coordinate_systems.insert("camera".to_string(), self.state.transform.inverse());
+                    self.camera = Some((self.state.transform, params));
+                },
+                "Film" => {
+                    reader.text();
+                    let params = reader.params();
+                    let x = params.float("xresolution", 1280.0) as u32;
+                    let y = params.float("yresolution", 720.0) as u32;
+                    self.resolution = (x.max(1), y.max(1));
+                },
+                "Integrator" => {
+                    let kind = reader.text();
+                    let params = reader.params();
+                    let kind = match kind.as_str() {
+                        "path" | "volpath" => "path",
+                        "bdpt" => "bdpt",
+                        "mlt" => "mlt",
+                        "sppm" => "ppm",
+                        "ambientocclusion" => "ao",
+                        other => {
+                            self.warnings.warn(&format!("the {} integrator is not supported, using path", other));
+                            "path"
+                        }
+                    };
+                    self.integrator = json::object! {"type": kind, "max-depth": params.float("maxdepth", 5.0) as u32};
+                },
+                "Sampler" | "PixelFilter" | "ColorSpace" | "Accelerator" | "Option" => {
+                    if directive != "Option" {
+                        reader.text();
+                    }
+                    reader.params();
+                },
+
+                "WorldBegin" => {
+                    self.state.transform = Transform::identity();
+                    self.coordinate_systems.insert("world".to_string(), Transform::identity());
+                },
+                "AttributeBegin" | "TransformBegin" => self.stack.push(self.state.clone()),
+                "AttributeEnd" | "TransformEnd" => {
+                    let state = self.stack.pop().unwrap_or_else(|| panic!("{}: unmatched {}", path, directive));
+                    if directive == "TransformEnd" {
+                        self.state.transform = state.transform;
+                    } else {
+                        self.state = state;
+                    }
+                },
+                "Attribute" => {
+                    reader.text();
+                    reader.params();
+                    self.warnings.warn("the Attribute directive is not supported");
+                },
+
+                "Texture" => {
+                    let name = reader.text();
+                    reader.text();
+                    let class = reader.text();
+                    let params = reader.params();
+                    if let Some(texture) = self.texture(&class, &params) {
+                        self.textures.insert(name, texture);
+                    }
+                },
+                "Material" => {
+                    let kind = reader.text();
+                    let params = reader.params();
+                    self.state.material = self.material(&kind, &params);
+                },
+                "MakeNamedMaterial" => {
+                    let name = reader.text();
+                    let params = reader.params();
+                    let material = self.material(params.string("type").unwrap_or(""), &params);
+                    self.named_materials.insert(name, material);
+                },
+                "NamedMaterial" => {
+                    let name = reader.text();
+                    match self.named_materials.get(&name) {
+                        Some(material) => self.state.material = material.clone(),
+                        None => self.warnings.warn(&format!("unknown material {}", name))
+                    }
+                },
+                "MakeNamedMedium" | "MediumInterface" => {
+                    reader.text();
+                    if directive == "MakeNamedMedium" {
+                        reader.params();
+                    } else if let Some(Token::Text(_)) = reader.tokens.get(reader.position) {
+                        reader.next();
+                    }
+                    self.warnings.warn("participating media are not supported");
+                },
+
+                "AreaLightSource" => {
+                    let kind = reader.text();
+                    let params = reader.params();
+                    if kind != "diffuse" {
+                        self.warnings.warn(&format!("{} area lights are not supported", kind));
+                    } else if params.get("filename").is_some() {
+                        self.warnings.warn("area lights with images are not supported");
+                    }
+                    // pbrt's area lights only glow on the side the surface faces unless they are two sided
+                    let radiance = self.color(&params, "L").unwrap_or_else(Color::white);
+                    let two_sided = params.bool("twosided", false);
+                    if two_sided {
+                        self.warnings.warn("two sided area lights only glow on their front with bdpt and ppm");
+                    }
+                    let emission = Emission::new(Texture::Constant { color: params.float("scale", 1.0) * radiance });
+                    self.state.area_light = Some(Emission { two_sided, ..emission });
+                },
+                "LightSource" => {
+                    let kind = reader.text();
+                    let params = reader.params();
+                    self.light(&kind, &params);
+                },
+                "Shape" => {
+                    let kind = reader.text();
+                    let params = reader.params();
+                    self.shape(&kind, &params);
+                },
+
+                "ObjectBegin" => {
+                    let name = reader.text();
+                    self.stack.push(self.state.clone());
+                    self.instance = Some((name, vec![]));
+                },
+                "ObjectEnd" => {
+                    if let Some((name, primitives)) = self.instance.take() {
+                        self.instances.insert(name, primitives);
+                    }
+                    self.state = self.stack.pop().unwrap_or_else(|| panic!("{}: unmatched ObjectEnd", path));
+                },
+                "ObjectInstance" => {
+                    let name = reader.text();
+                    match self.instances.get(&name) {
+                        Some(primitives) => {
+                            let mut primitives = primitives.clone();
+                            for primitive in &mut primitives {
+                                primitive.transform(&self.state.transform);
+                            }
+                            self.primitives.extend(primitives);
+                        },
+                        None => self.warnings.warn(&format!("unknown object {}", name))
+                    }
+                },
+
+                "Include" | "Import" => {
+                    let file = self.file(&reader.text());
+                    let source = fs::read_to_string(&file).unwrap_or_else(|e| panic!("Could not read pbrt file {}: {}", file.display(), e));
+                    self.run(&source, &file.to_string_lossy());
+                },
+                other => {
+                    self.warnings.warn(&format!("the {} directive is not supported", other));
+                    reader.skip();
+                }
+            }
+        }
+    }
+
+    // spectrum parameters as colors, None if they are missing or not supported
+    fn color(&self, params: &Params, name: &str) -> Option<Color> {
+        let param = params.get(name)?;
+        let values = params.floats(name);
+        match (param.kind.as_str(), &values[..]) {
+            ("rgb", &[r, g, b]) => Some(Color::new(r, g, b)),
+            ("blackbody", &[temperature, ..]) if temperature > 0.0 => Some(Color::blackbody(temperature)),
+            ("float", &[value]) => Some(Color::new(value, value, value)),
+            ("spectrum", values) if !values.is_empty() => {
+                let pairs: Vec<(f64, f64)> = values.chunks_exact(2).map(|p| (p[0], p[1])).collect();
+                Some(spectrum::sampled_to_rgb(&pairs))
+            },
+            // standard illuminants are white in sRGB
+            ("spectrum", _) if param.values[0].starts_with("stdillum") => Some(Color::white()),
+            _ => {
+                self.warnings.warn(&format!("the {} value of {} is not supported", param.kind, name));
+                None
+            }
+        }
+    }
+
+    // texture or constant parameter
+    fn color_texture(&self, params: &Params, name: &str, default: Color) -> Texture {
+        if let Some(param) = params.get(name).filter(|p| p.kind == "texture") {
+            return match self.textures.get(&param.values[0]) {
+                Some(texture) => texture.clone(),
+                None => {
+                    self.warnings.warn(&format!("unknown texture {}", param.values[0]));
+                    Texture::Constant { color: default }
+                }
+            };
+        }
+        Texture::Constant { color: self.color(params, name).unwrap_or(default) }
+    }
+
+    fn texture(&self, class: &str, params: &Params) -> Option<Texture> {
+        match class {
+            "imagemap" => {
+                let file = self.file(params.string("filename").unwrap_or(""));
+                let srgb = params.string("encoding") != Some("linear");
+                let image = fs::read(&file).ok().and_then(|bytes| ImageTexture::decode(&bytes, srgb));
+                if image.is_none() {
+                    self.warnings.warn(&format!("could not read image {}", file.display()));
+                }
+                image.map(|image| Texture::Image { image: Arc::new(image) })
+            },
+            "constant" => Some(self.color_texture(params, "value", Color::white())),
+            other => {
+                self.warnings.warn(&format!("{} textures are not supported", other));
+                None
+            }
+        }
+    }
+
+    fn material(&self, kind: &str, params: &Params) -> Material {
+        if params.get("normalmap").is_some() || params.get("displacement").is_some() {
+            self.warnings.warn("normal and displacement maps are not supported");
+        }
+
+        // pbrt maps the roughness to the width of the microfacet distribution (alpha) by default
+        let roughness = {
+            let roughness = params.float("roughness", 0.0);
+            let roughness = 0.5 * (params.float("uroughness", roughness) + params.float("vroughness", roughness));
+            if params.bool("remaproughness", true) { roughness.sqrt() } else { roughness }
+        };
+
+        match kind {
+            "diffuse" => Material::Lambertian { albedo: self.color_texture(params, "reflectance", Color::new(0.5, 0.5, 0.5)) },
+            "conductor" => {
+                let albedo = if params.get("reflectance").is_some() {
+                    self.color_texture(params, "reflectance", Color::white())
+                } else {
+                    // named metals like "metal-Au-eta", copper by default
+                    let named = |param: &str| {
+                        let name = params.string(param).unwrap_or("metal-Cu-eta");
+                        name.strip_prefix("metal-").and_then(|n| n.split('-').next()).and_then(|n| ior::conductor_preset(&n.to_lowercase()))
+                    };
+                    let (eta, k) = match (named("eta"), named("k")) {
+                        (Some((eta, _)), Some((_, k))) => (eta, k),
+                        _ => {
+                            let (eta, k) = ior::conductor_preset("cu").unwrap();
+                            (self.color(params, "eta").unwrap_or(eta), self.color(params, "k").unwrap_or(k))
+                        }
+                    };
+                    Texture::Constant { color: ior::conductor_reflectance(eta, k) }
+                };
+                Material::Metal { albedo, fuzz: roughness, film: None }
+            },
+            "dielectric" => {
+                if roughness > 0.0 {
+                    self.warnings.warn("rough dielectrics are rendered smooth");
+                }
+                let ior = match params.get("eta") {
+                    Some(param) if param.kind == "spectrum" => {
+                        let preset = param.values[0].strip_prefix("glass-").and_then(|name| Ior::preset(&name.to_lowercase()));
+                        preset.unwrap_or_else(|| {
+                            self.warnings.warn(&format!("the index of refraction {} is not supported, using 1.5", param.values[0]));
+                            Ior::Constant(1.5)
+                        })
+                    },
+                    _ => Ior::Constant(params.float("eta", 1.5))
+                };
+                Material::Dielectric { ior, color: Texture::Constant { color: Color::white() }, film: None }
+            },
+            "interface" => Material::Interface,
+            other => {
+                self.warnings.warn(&format!("the {} material is not supported, using diffuse", other));
+                Material::Lambertian { albedo: Texture::Constant { color: Color::new(0.5, 0.5, 0.5) } }
+            }
+        }
+    }
+
+    fn light(&mut self, kind: &str, params: &Params) {
+        if kind != "infinite" {
+            self.warnings.warn(&format!("{} lights are not supported, only area and infinite lights", kind));
+            return;
+        }
+
+        let mut radiance = match params.get("filename") {
+            Some(_) => {
+                self.warnings.warn("environment maps are not supported, the infinite light is white");
+                Color::white()
+            },
+            None => self.color(params, "L").unwrap_or_else(Color::white),
+        };
+        radiance = params.float("scale", 1.0) * radiance;
+        // the illuminance of a constant environment on an upward facing surface is pi times its luminance
+        if let Some(illuminance) = params.floats("illuminance").first() {
+            radiance = radiance * (illuminance / (PI * radiance.luminance().max(1e-9)));
+        }
+
+        self.environment = Some(self.environment.map_or(radiance, |color| color + radiance));
+    }
+
+    fn shape(&mut self, kind: &str, params: &Params) {
+        let shape = match kind {
+            "sphere" => {
+                if params.get("zmin").is_some() || params.get("zmax").is_some() || params.get("phimax").is_some() {
+                    self.warnings.warn("partial spheres are rendered whole");
+                }
+                Shape::Sphere { center: Vec3::zero(), radius: params.float("radius", 1.0) }
+            },
+            "trianglemesh" | "bilinearmesh" => {
+                let mut data = MeshData {
+                    positions: params.vectors("P"),
+                    normals: params.vectors("N"),
+                    uvs: params.floats("uv").chunks_exact(2).map(|uv| (uv[0], uv[1])).collect(),
+                    ..MeshData::default()
+                };
+                let corners = if kind == "trianglemesh" { 3 } else { 4 };
+                let mut indices = params.integers("indices");
+                if indices.is_empty() && data.positions.len() == corners {
+                    indices = (0..corners).collect();
+                }
+
+                // bilinear patches have their corners in the order (0, 0), (1, 0), (0, 1), (1, 1)
+                data.triangles = match corners {
+                    3 => indices.chunks_exact(3).map(|t| [t[0], t[1], t[2]]).collect(),
+                    _ => indices.chunks_exact(4).flat_map(|q| [[q[0], q[1], q[3]], [q[0], q[3], q[2]]]).collect(),
+                };
+                if data.normals.len() != data.positions.len() {
+                    data.normals.clear();
+                }
+                if data.uvs.len() != data.positions.len() {
+                    data.uvs.clear();
+                }
+                if data.triangles.iter().flatten().any(|&i| i >= data.positions.len()) {
+                    self.warnings.warn("skipping a mesh with vertex indices out of range");
+                    return;
+                }
+                self.orient(&mut data);
+                Shape::Mesh(data)
+            },
+            "plymesh" => {
+                let file = self.file(params.string("filename").unwrap_or(""));
+                if file.extension().is_some_and(|e| e == "gz") {
+                    self.warnings.warn("compressed PLY files are not supported");
+                    return;
+                }
+                if params.get("displacement").is_some() {
+                    self.warnings.warn("displacement is not supported");
+                }
+                let mut data = ply::load(&file.to_string_lossy());
+                self.orient(&mut data);
+                Shape::Mesh(data)
+            },
+            other => {
+                self.warnings.warn(&format!("{} shapes are not supported", other));
+                return;
+            }
+        };
+
+        let mut material = self.state.material.clone();
+        if let Some(emission) = &self.state.area_light {
+            material = Material::Emissive { base: Box::new(material), emission: emission.clone() };
+        }
+        if params.get("alpha").is_some() {
+            let alpha = self.color_texture(params, "alpha", Color::white());
+            material = Material::Cutout { base: Box::new(material), alpha, threshold: None };
+        }
+
+        let mut primitive = Primitive { shape, material };
+        primitive.transform(&self.state.transform);
+        match &mut self.instance {
+            Some((_, primitives)) => primitives.push(primitive),
+            None => self.primitives.push(primitive),
+        }
+    }
+
+    // Triangles face the side of their vertex normals, without normals ReverseOrientation turns them around
+    fn orient(&self, data: &mut MeshData) {
+        if data.normals.is_empty() {
+            if self.state.reverse_orientation {
+                data.reverse_winding();
+            }
+            return;
+        }
+
+        let (positions, normals) = (&data.positions, &data.normals);
+        for triangle in &mut data.triangles {
+            let [a, b, c] = triangle.map(|i| positions[i]);
+            let normal = triangle.iter().fold(Vec3::zero(), |sum, &i| sum + normals[i]);
+            if Vec3::dot(&Vec3::cross(&(b - a), &(c - a)), &normal) < 0.0 {
+                triangle.swap(1, 2);
+            }
+        }
+    }
+
+    // The objects and the camera. Cameras of pbrt see the world mirrored compared to the ones of
+    // rayo unless their transformation mirrors it, so the scene is mirrored to get the same image
+    fn finish(self, aspect_ratio: f64) -> (Vec<Object>, Camera) {
+        let (world_to_camera, params) = self.camera.unwrap_or_else(|| (Transform::identity(), Params(vec![])));
+        let camera_to_world = world_to_camera.inverse();
+
+        let axes = [Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 0.0, 1.0)].map(|a| camera_to_world.vector(a));
+        let determinant = Vec3::dot(&Vec3::cross(&axes[0], &axes[1]), &axes[2]);
+        let mirror = if determinant > 0.0 { Transform::scale(Vec3::new(-1.0, 1.0, 1.0)) } else { Transform::identity() };
+        let camera_to_world = camera_to_world.then(&mirror);
+
+        // the field of view is the one of the shorter side of the image
+        let fov = params.float("fov", 90.0);
+        let vfov = if aspect_ratio >= 1.0 { fov } else { 2.0 * ((fov / 2.0).to_radians().tan() / aspect_ratio).atan().to_degrees() };
+        let lens_radius = params.float("lensradius", 0.0);
+        let focal_length = if lens_radius > 0.0 { params.float("focaldistance", 1e6) } else { 1.0 };
+
+        let lookfrom = camera_to_world.point(Vec3::zero());
+        let lookat = lookfrom + camera_to_world.vector(Vec3::new(0.0, 0.0, 1.0));
+        let vup = camera_to_world.vector(Vec3::new(0.0, 1.0, 0.0));
+        let camera = Camera::new(lookfrom, lookat, vup, vfov, focal_length, 2.0 * lens_radius, aspect_ratio);
+
+        let mut bounds = Aabb::empty();
+        let mut objects = vec![];
+        for mut primitive in self.primitives {
+            primitive.transform(&mirror);
+            bounds = bounds.union(&primitive.bounds());
+            objects.push(match primitive.shape {
+                Shape::Sphere { center, radius } => Object::Sphere(Sphere { center, radius, material: primitive.material, medium: None }),
+                Shape::Mesh(data) => Object::Mesh(Mesh::new(data, primitive.material, None)),
+            });
+        }
+
+        // infinite lights become a large sphere around the scene and the camera, glowing inwards
+        if let Some(radiance) = self.environment {
+            let (center, size) = import::extent(&bounds, lookfrom);
+            objects.push(import::environment_light(center, radiance, size + (lookfrom - center).norm()));
+        }
+
+        (objects, camera)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::intersection::Hittable;
+    use crate::ray::Ray;
+    use crate::sampler::RandomSampler;
+
+    // an importer that has read the source
+    fn import(source: &str) -> Importer {
+        let mut importer = Importer::new(PathBuf::new());
+        importer.run(source, "test.pbrt");
+        importer
+    }
+
+    fn params(source: &str) -> Params {
+        let tokens = tokenize(source);
+        Reader { tokens: &tokens, position: 0, path: "test.pbrt" }.params()
+    }
+
+    #[test]
+    fn test_params() {
+        let params = params(r#"
+            "float radius" 2 # a comment
+            "point3 P" [0 1 2  3 4 5]
+            "string filename" ["mesh.ply"]
+            "bool remaproughness" false
+        "#);
+        assert_eq!(params.float("radius", 1.0), 2.0);
+        assert_eq!(params.float("missing", 1.0), 1.0);
+        assert_eq!(params.vectors("P").len(), 2);
+        assert!(params.vectors("P")[1].is_close(&Vec3::new(3.0, 4.0, 5.0)));
+        assert_eq!(params.string("filename"), Some("mesh.ply"));
+        assert!(!params.bool("remaproughness", true));
+    }
+
+    #[test]
+    fn test_colors() {
+        let importer = Importer::new(PathBuf::new());
+        let params = params(r#"
+            "rgb a" [0.1 0.2 0.3]
+            "float b" 0.5
+            "blackbody c" 6500
+            "spectrum d" "stdillum-D65"
+            "spectrum e" "canon_eos_100d_r"
+        "#);
+        assert!(importer.color(&params, "a").unwrap().is_close(&Color::new(0.1, 0.2, 0.3)));
+        assert!(importer.color(&params, "b").unwrap().is_close(&Color::new(0.5, 0.5, 0.5)));
+        assert!(importer.color(&params, "c").unwrap().is_close(&Color::blackbody(6500.0)));
+        assert!(importer.color(&params, "d").unwrap().is_close(&Color::white()));
+
+        // unknown named spectra are left out with a warning
+        assert!(importer.color(&params, "e").is_none());
+        assert!(importer.warnings.contains("the spectrum value of e is not supported"));
+    }
+
+    #[test]
+    fn test_materials() {
+        let importer = Importer::new(PathBuf::new());
+        let material = |source: &str| {
+            let params = params(source);
+            importer.material(params.string("type").unwrap(), &params)
+        };
+
+        // named metals and the roughness, which is remapped to the width of the distribution
+        let gold = material(r#""string type" "conductor" "spectrum eta" "metal-Au-eta" "spectrum k" "metal-Au-k" "float roughness" 0.04"#);
+        assert!(matches!(gold, Material::Metal { albedo: Texture::Constant { color }, fuzz, .. } if color.r > color.b && (fuzz - 0.2).abs() < 1e-9));
+        let rough = material(r#""string type" "conductor" "float roughness" 0.04 "bool remaproughness" false"#);
+        assert!(matches!(rough, Material::Metal { fuzz, .. } if (fuzz - 0.04).abs() < 1e-9));
+
+        assert!(matches!(material(r#""string type" "dielectric" "spectrum eta" "glass-BK7""#), Material::Dielectric { ior: Ior::Sellmeier { .. }, .. }));
+        assert!(matches!(material(r#""string type" "dielectric" "float eta" 1.33"#), Material::Dielectric { ior: Ior::Constant(ior), .. } if ior == 1.33));
+        assert!(matches!(material(r#""string type" "diffuse" "rgb reflectance" [1 0 0]"#), Material::Lambertian { .. }));
+
+        assert!(matches!(material(r#""string type" "coateddiffuse""#), Material::Lambertian { .. }));
+        assert!(importer.warnings.contains("the coateddiffuse material is not supported"));
+    }
+
+    #[test]
+    fn test_attributes_and_instances() {
+        // without a camera the scene is mirrored along x, like for any camera that does not mirror it
+        let importer = import(r#"
+            WorldBegin
+            ObjectBegin "ball"
+                Material "dielectric"
+                Shape "sphere" "float radius" 0.5
+            ObjectEnd
+            AttributeBegin
+                Material "conductor"
+                Translate 5 0 0
+                ObjectInstance "ball"
+                Shape "sphere"
+            AttributeEnd
+            ObjectInstance "ball"
+            ObjectInstance "cube"
+            Shape "sphere" "float radius" 2
+            Shape "trianglemesh" "point3 P" [0 0 0  1 0 0  0 1 0] "integer indices" [0 1 3]
+            Shape "curve"
+        "#);
+        assert!(importer.warnings.contains("unknown object cube"));
+        assert!(importer.warnings.contains("skipping a mesh with vertex indices out of range"));
+        assert!(importer.warnings.contains("curve shapes are not supported"));
+
+        let (objects, _) = importer.finish(1.0);
+        let spheres: Vec<&Sphere> = objects.iter().map(|o| match o {
+            Object::Sphere(sphere) => sphere,
+            other => panic!("Expected a sphere, got {:?}", other)
+        }).collect();
+        assert_eq!(spheres.len(), 4);
+
+        // instances keep their own material and are moved by the transformation where they are placed
+        assert!(spheres[0].center.is_close(&Vec3::new(-5.0, 0.0, 0.0)));
+        assert!(matches!(spheres[0].material, Material::Dielectric { .. }));
+        assert!(matches!(spheres[1].material, Material::Metal { .. }));
+        assert!(spheres[2].center.is_close(&Vec3::zero()) && spheres[2].radius == 0.5);
+
+        // the material and transformation are restored after AttributeEnd
+        assert!(spheres[3].center.is_close(&Vec3::zero()) && spheres[3].radius == 2.0);
+        assert!(matches!(spheres[3].material, Material::Lambertian { .. }));
+    }
+
+    #[test]
+    fn test_unknown_directive() {
+        // the directive and its arguments are skipped, the rest of the file is still imported
+        let importer = import(r#"WorldBegin Frobnicate "widget" 1 [2 3] "float size" 4 Shape "sphere""#);
+        assert!(importer.warnings.contains("the Frobnicate directive is not supported"));
+        assert_eq!(importer.finish(1.0).0.len(), 1);
+    }
+
+    #[test]
+    fn test_area_light_sides() {
+        // pbrt's area lights are one sided by default, they glow on the outside of spheres
+        let radiance = |source: &str, origin: Vec3| {
+            let (objects, _) = import(source).finish(1.0);
+            let ray = Ray::new(origin, Vec3::new(0.0, 0.0, 1.0));
+            let hit = objects[0].intersect(&ray, 1e-6, f64::INFINITY).unwrap();
+            hit.material.emmit(&ray, &hit).r
+        };
+        let one_sided = r#"WorldBegin AreaLightSource "diffuse" Shape "sphere""#;
+        let two_sided = r#"WorldBegin AreaLightSource "diffuse" "bool twosided" true Shape "sphere""#;
+        assert!(radiance(one_sided, Vec3::new(0.0, 0.0, -5.0)) > 0.0);
+        assert_eq!(radiance(one_sided, Vec3::zero()), 0.0);
+        assert!(radiance(two_sided, Vec3::zero()) > 0.0);
+    }
+
+
+    #[test]
+    fn test_camera() {
+        let importer = import(r#"
+            # a camera looking along +z, which pbrt shows with +x on the right
+            LookAt 0 0 -5  0 0 0  0 1 0
+            Camera "perspective" "float fov" [90]
+            Film "rgb" "integer xresolution" [200] "integer yresolution" [100]
+            Integrator "sppm" "integer maxdepth" 8
+            WorldBegin
+            Translate 2.5 0 0
+            Shape "trianglemesh" "point3 P" [-1 -1 0  1 -1 0  0 1 0] "integer indices" [0 1 2]
+        "#);
+        assert_eq!(importer.resolution, (200, 100));
+        assert_eq!(importer.integrator["type"].as_str(), Some("ppm"));
+        assert_eq!(importer.integrator["max-depth"].as_u32(), Some(8));
+
+        // the triangle is on the right half of the image
+        let (objects, camera) = importer.finish(2.0);
+        let mut sampler = RandomSampler::new();
+        let hit = objects[0].intersect(&camera.generate_ray(0.625, 0.5, &mut sampler), 1e-6, f64::MAX).unwrap();
+        assert!(hit.point.z.abs() < 1e-9);
+        assert!(objects[0].intersect(&camera.generate_ray(0.375, 0.5, &mut sampler), 1e-6, f64::MAX).is_none());
+
+        // integrators rayo does not have become path
+        assert_eq!(import(r#"Integrator "lightpath""#).integrator["type"].as_str(), Some("path"));
+    }
+
+    #[test]
+    fn test_lights() {
+        let importer = import(r#"
+            WorldBegin
+            LightSource "infinite" "rgb L" [0.1 0.2 0.3]
+            LightSource "infinite" "blackbody L" 5000 "float illuminance" 3.14159265358979
+            LightSource "spot"
+            AttributeBegin
+                AreaLightSource "diffuse" "blackbody L" [6500] "float scale" 3
+                Translate 0 10 0
+                Shape "sphere" "float radius" 2
+            AttributeEnd
+        "#);
+        assert!(importer.warnings.contains("spot lights are not supported"));
+
+        // infinite lights add up, the illuminance sets the luminance to a pi-th of it
+        let environment = importer.environment.unwrap();
+        assert!((environment.luminance() - 1.0 - Color::new(0.1, 0.2, 0.3).luminance()).abs() < 1e-6);
+
+        let (objects, _) = importer.finish(1.0);
+        assert_eq!(objects.len(), 2);
+        match &objects[0] {
+            Object::Sphere(sphere) => {
+                assert!(sphere.center.is_close(&Vec3::new(0.0, 10.0, 0.0)));
+                assert!(matches!(&sphere.material, Material::Emissive { emission, .. } if emission.texture.value(&sphere.sample_surface(&mut RandomSampler::new())).is_close(&(3.0 * Color::blackbody(6500.0)))));
+            },
+            other => panic!("Expected the area light sphere, got {:?}", other)
+        }
+
+        // the environment is a sphere around the scene that shines inwards
+        match &objects[1] {
+            Object::Environment(sphere) => assert!(sphere.radius > 100.0 && (sphere.center - Vec3::new(0.0, 10.0, 0.0)).norm() < 1e-9),
+            other => panic!("Expected the environment sphere, got {:?}", other)
+        }
+    }
+}
@@ -0,0 +1,246 @@
+use std::fs;
+
+use crate::color::Color;
+use crate::intersection::mesh::MeshData;
+use crate::vec::Vec3;
+
+// A property of an element, lists (like the vertex indices of faces) have a count type
+struct Property {
+    name: String,
+    kind: String,
+    count_kind: Option<String>,
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+// the values of the file after the header
+enum Body<'a> {
+    Ascii(std::str::SplitWhitespace<'a>),
+    Binary { bytes: &'a [u8], position: usize, big_endian: bool },
+}
+
+impl Body<'_> {
+
+    fn read(&mut self, kind: &str, path: &str) -> f64 {
+        match self {
+            Body::Ascii(tokens) => tokens
+                .next()
+                .and_then(|t| t.parse().ok())
+                .unwrap_or_else(|| panic!("{}: expected a number", path)),
+            Body::Binary { bytes, position, big_endian } => {
+                let size = match kind {
+                    "char" | "int8" | "uchar" | "uint8" => 1,
+                    "short" | "int16" | "ushort" | "uint16" => 2,
+                    "int" | "int32" | "uint" | "uint32" | "float" | "float32" => 4,
+                    "double" | "float64" => 8,
+                    _ => panic!("{}: unknown property type {}", path, kind)
+                };
+                let mut value = bytes
+                    .get(*position..*position + size)
+                    .unwrap_or_else(|| panic!("{}: file ends too early", path))
+                    .to_vec();
+                *position += size;
+                if *big_endian {
+                    value.reverse();
+                }
+
+                match kind {
+                    "char" | "int8" => value[0] as i8 as f64,
+                    "uchar" | "uint8" => value[0] as f64,
+                    "short" | "int16" => i16::from_le_bytes([value[0], value[1]]) as f64,
+                    "ushort" | "uint16" => u16::from_le_bytes([value[0], value[1]]) as f64,
+                    "int" | "int32" => i32::from_le_bytes([value[0], value[1], value[2], value[3]]) as f64,
+                    "uint" | "uint32" => u32::from_le_bytes([value[0], value[1], value[2], value[3]]) as f64,
+                    "float" | "float32" => f32::from_le_bytes([value[0], value[1], value[2], value[3]]) as f64,
+                    _ => f64::from_le_bytes([value[0], value[1], value[2], value[3], value[4], value[5], value[6], value[7]]),
+                }
+            }
+        }
+    }
+}
+
+// reads a PLY file (ASCII or binary) with its vertex positions, normals, colors and texture coordinates
+pub fn load(path: &str) -> MeshData {
+    let bytes = fs::read(path).unwrap_or_else(|e| panic!("Could not read PLY file {}: {}", path, e));
+    parse(&bytes, path)
+}
+
+fn parse(bytes: &[u8], path: &str) -> MeshData {
+    // the header is text and ends with a line holding end_header
+    let end = bytes
+        .windows(11)
+        .position(|w| w == b"end_header\n" || w == b"end_header\r")
+        .unwrap_or_else(|| panic!("{} is not a PLY file", path));
+    let header = String::from_utf8_lossy(&bytes[..end]);
+    let mut start = end + 11;
+    if bytes.get(start - 1) == Some(&b'\r') && bytes.get(start) == Some(&b'\n') {
+        start += 1;
+    }
+
+    let mut lines = header.lines();
+    if lines.next().map(|l| l.trim()) != Some("ply") {
+        panic!("{} is not a PLY file", path);
+    }
+
+    let mut format = "";
+    let mut elements: Vec<Element> = vec![];
+    for line in lines {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        match tokens[..] {
+            ["format", name, _] => format = name,
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count.parse().unwrap_or_else(|_| panic!("{}: invalid element count", path)),
+                properties: vec![],
+            }),
+            ["property", "list", count_kind, kind, name] if !elements.is_empty() => {
+                let count_kind = Some(count_kind.to_string());
+                elements.last_mut().unwrap().properties.push(Property { name: name.to_string(), kind: kind.to_string(), count_kind });
+            },
+            ["property", kind, name] if !elements.is_empty() => {
+                elements.last_mut().unwrap().properties.push(Property { name: name.to_string(), kind: kind.to_string(), count_kind: None });
+            },
+            _ => {}
+        }
+    }
+
+    let body = &bytes[start..];
+    let mut body = match format {
+        "ascii" => Body::Ascii(std::str::from_utf8(body).unwrap_or_else(|_| panic!("{}: invalid text", path)).split_whitespace()),
+        "binary_little_endian" => Body::Binary { bytes: body, position: 0, big_endian: false },
+        "binary_big_endian" => Body::Binary { bytes: body, position: 0, big_endian: true },
+        _ => panic!("{}: unknown PLY format {}", path, format)
+    };
+
+    let mut data = MeshData::default();
+    for element in &elements {
+        let index = |name: &str| element.properties.iter().position(|p| p.name == name);
+        let indices = |names: [&str; 3]| -> Option<[usize; 3]> { Some([index(names[0])?, index(names[1])?, index(names[2])?]) };
+        let position = indices(["x", "y", "z"]);
+        let normal = indices(["nx", "ny", "nz"]);
+        let color = indices(["red", "green", "blue"]);
+        let uv = ["u", "s", "texture_u", "texture_s"].iter().find_map(|name| index(name))
+            .zip(["v", "t", "texture_v", "texture_t"].iter().find_map(|name| index(name)));
+
+        // integer colors are scaled to [0, 1]
+        let color_scale = color.map_or(1.0, |[r, _, _]| match element.properties[r].kind.as_str() {
+            "uchar" | "uint8" => 1.0 / 255.0,
+            "ushort" | "uint16" => 1.0 / 65535.0,
+            _ => 1.0
+        });
+
+        for _ in 0..element.count {
+            let mut values = vec![];
+            let mut lists = vec![];
+            for property in &element.properties {
+                match &property.count_kind {
+                    Some(count_kind) => {
+                        let count = body.read(count_kind, path) as usize;
+                        let list: Vec<f64> = (0..count).map(|_| body.read(&property.kind, path)).collect();
+                        lists.push((property.name.as_str(), list));
+                        values.push(0.0);
+                    },
+                    None => values.push(body.read(&property.kind, path))
+                }
+            }
+
+            if element.name == "vertex" {
+                let vec = |[x, y, z]: [usize; 3]| Vec3::new(values[x], values[y], values[z]);
+                data.positions.push(vec(position.unwrap_or_else(|| panic!("{}: vertices need x, y and z", path))));
+                if let Some(normal) = normal {
+                    data.normals.push(vec(normal));
+                }
+                if let Some([r, g, b]) = color {
+                    data.colors.push(color_scale * Color::new(values[r], values[g], values[b]));
+                }
+                if let Some((u, v)) = uv {
+                    data.uvs.push((values[u], values[v]));
+                }
+            } else if element.name == "face" {
+                let corners = lists
+                    .iter()
+                    .find(|(name, _)| *name == "vertex_indices" || *name == "vertex_index")
+                    .map(|(_, list)| list)
+                    .unwrap_or_else(|| panic!("{}: faces need vertex indices", path));
+                for i in 1..corners.len().saturating_sub(1) {
+                    data.triangles.push([corners[0] as usize, corners[i] as usize, corners[i + 1] as usize]);
+                }
+            }
+        }
+    }
+
+    if data.triangles.iter().flatten().any(|&i| i >= data.positions.len()) {
+        panic!("{}: vertex index out of range", path);
+    }
+
+    data
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let ascii = b"ply
+format ascii 1.0
+comment a quad
+element vertex 4
+property float x
+property float y
+property float z
+property uchar red
+property uchar green
+property uchar blue
+element face 1
+property list uchar int vertex_indices
+end_header
+0 0 0 255 0 0
+1 0 0 0 255 0
+1 1 0 0 0 255
+0 1 0 255 255 255
+4 0 1 2 3
+";
+        let data = parse(ascii, "test.ply");
+        assert_eq!(data.triangles, vec![[0, 1, 2], [0, 2, 3]]);
+        assert!(data.positions[2].is_close(&Vec3::new(1.0, 1.0, 0.0)));
+        assert!(data.colors[1].is_close(&Color::new(0.0, 1.0, 0.0)));
+        assert!(data.normals.is_empty());
+
+        // the same triangle in big endian binary, with normals
+        let mut binary = b"ply
+format binary_big_endian 1.0
+element vertex 3
+property float x
+property float y
+property float z
+property double nx
+property double ny
+property double nz
+element face 1
+property list uchar uint vertex_index
+end_header
+".to_vec();
+        for (x, y) in [(0.0f32, 0.0f32), (1.0, 0.0), (1.0, 1.0)] {
+            for value in [x, y, 0.0] {
+                binary.extend(value.to_be_bytes());
+            }
+            for value in [0.0f64, 0.0, 1.0] {
+                binary.extend(value.to_be_bytes());
+            }
+        }
+        binary.push(3);
+        for index in [0u32, 1, 2] {
+            binary.extend(index.to_be_bytes());
+        }
+
+        let data = parse(&binary, "test.ply");
+        assert_eq!(data.triangles, vec![[0, 1, 2]]);
+        assert!(data.positions[1].is_close(&Vec3::new(1.0, 0.0, 0.0)));
+        assert!(data.normals[2].is_close(&Vec3::new(0.0, 0.0, 1.0)));
+    }
+}
@@ -1,16 +1,19 @@
+use crate::spectrum::Wavelengths;
 use crate::vec::Vec3;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub struct Ray {
     pub dir: Vec3,
     pub origin: Vec3,
     pub t: f64,
+    // set in spectral mode, the wavelengths the path carries
+    pub wavelengths: Option<Wavelengths>,
 }
 
 impl Ray {
 
     pub fn new(origin: Vec3, dir: Vec3) -> Ray {
-        Ray {origin: origin, dir: dir, t: 0.0}
+        Ray {origin, dir, t: 0.0, wavelengths: None}
     }
 
     pub fn at(&self, t: f64) -> Vec3 {
@@ -0,0 +1,25 @@
+use rand::{rngs::ThreadRng, Rng};
+
+// Source of the random numbers used while rendering. Routing every random decision through a
+// sampler allows integrators to control them, e.g. to mutate the numbers that produced a path.
+pub trait Sampler {
+    // uniformly distributed number in [0, 1)
+    fn next_f64(&mut self) -> f64;
+}
+
+// Independent uniform random numbers
+pub struct RandomSampler {
+    rng: ThreadRng,
+}
+
+impl RandomSampler {
+    pub fn new() -> RandomSampler {
+        RandomSampler { rng: rand::thread_rng() }
+    }
+}
+
+impl Sampler for RandomSampler {
+    fn next_f64(&mut self) -> f64 {
+        self.rng.gen()
+    }
+}
@@ -0,0 +1,227 @@
+use std::sync::OnceLock;
+
+use crate::color::Color;
+use crate::sampler::Sampler;
+
+// range of visible wavelengths in nanometers
+pub const LAMBDA_MIN: f64 = 360.0;
+pub const LAMBDA_MAX: f64 = 830.0;
+
+// CIE 1931 color matching functions, using the multi-lobe gaussian fit from
+// Wyman, Sloan and Shirley, "Simple Analytic Approximations to the CIE XYZ Color Matching Functions"
+pub fn cie_xyz(lambda: f64) -> (f64, f64, f64) {
+    fn g(x: f64, mu: f64, sigma_low: f64, sigma_high: f64) -> f64 {
+        let sigma = if x < mu { sigma_low } else { sigma_high };
+        (-0.5 * ((x - mu) / sigma).powi(2)).exp()
+    }
+
+    let x = 1.056 * g(lambda, 599.8, 37.9, 31.0) + 0.362 * g(lambda, 442.0, 16.0, 26.7)
+        - 0.065 * g(lambda, 501.1, 20.4, 26.2);
+    let y = 0.821 * g(lambda, 568.8, 46.9, 40.5) + 0.286 * g(lambda, 530.9, 16.3, 31.1);
+    let z = 1.217 * g(lambda, 437.0, 11.8, 36.0) + 0.681 * g(lambda, 459.0, 26.0, 13.8);
+
+    (x, y, z)
+}
+
+// linear sRGB (D65 white point) color of the given XYZ tristimulus values
+pub fn xyz_to_rgb(x: f64, y: f64, z: f64) -> Color {
+    Color {
+        r: 3.2404542 * x - 1.5371385 * y - 0.4985314 * z,
+        g: -0.9692660 * x + 1.8760108 * y + 0.0415560 * z,
+        b: 0.0556434 * x - 0.2040259 * y + 1.0572252 * z,
+    }
+}
+
+// spectral radiance of a blackbody at the given temperature (Kelvin) and wavelength (nanometers)
+pub fn planck(lambda: f64, temperature: f64) -> f64 {
+    let c = 299792458.0;
+    let h = 6.62606957e-34;
+    let kb = 1.3806488e-23;
+    let l = lambda * 1e-9;
+    (2.0 * h * c * c) / (l.powi(5) * (((h * c) / (l * kb * temperature)).exp() - 1.0))
+}
+
+// Smits' basis spectra for converting RGB colors to spectra, sampled in ten bins evenly
+// spaced between 380 and 720 nm ("An RGB-to-Spectrum Conversion for Reflectances", 1999)
+const SMITS_WHITE: [f64; 10] = [1.0000, 1.0000, 0.9999, 0.9993, 0.9992, 0.9998, 1.0000, 1.0000, 1.0000, 1.0000];
+const SMITS_CYAN: [f64; 10] = [0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0000, 0.0000, 0.0000];
+const SMITS_MAGENTA: [f64; 10] = [1.0000, 1.0000, 0.9685, 0.2229, 0.0000, 0.0458, 0.8369, 1.0000, 1.0000, 0.9959];
+const SMITS_YELLOW: [f64; 10] = [0.0001, 0.0000, 0.1088, 0.6651, 1.0000, 1.0000, 0.9996, 0.9586, 0.9685, 0.9840];
+const SMITS_RED: [f64; 10] = [0.1012, 0.0515, 0.0000, 0.0000, 0.0000, 0.0000, 0.8325, 1.0149, 1.0149, 1.0149];
+const SMITS_GREEN: [f64; 10] = [0.0000, 0.0000, 0.0273, 0.7937, 1.0000, 0.9418, 0.1719, 0.0000, 0.0000, 0.0025];
+const SMITS_BLUE: [f64; 10] = [1.0000, 1.0000, 0.8916, 0.3323, 0.0000, 0.0000, 0.0003, 0.0369, 0.0483, 0.0496];
+
+// value of a basis spectrum, interpolating linearly between the bin centers
+fn smits_basis(table: &[f64; 10], lambda: f64) -> f64 {
+    let bin_width = (720.0 - 380.0) / 10.0;
+    let position = ((lambda - 380.0) / bin_width - 0.5).clamp(0.0, 9.0);
+    let i = (position as usize).min(8);
+    let t = position - i as f64;
+    (1.0 - t) * table[i] + t * table[i + 1]
+}
+
+// value at lambda of a smooth spectrum with (approximately) the given linear sRGB color. The
+// smallest component is made of white, the next of the secondary and the rest of the primary
+pub fn rgb_to_spectrum(rgb: Color, lambda: f64) -> f64 {
+    let basis = |table: &[f64; 10]| smits_basis(table, lambda);
+    let (r, g, b) = (rgb.r, rgb.g, rgb.b);
+
+    if r <= g && r <= b {
+        let rest = if g <= b {
+            (g - r) * basis(&SMITS_CYAN) + (b - g) * basis(&SMITS_BLUE)
+        } else {
+            (b - r) * basis(&SMITS_CYAN) + (g - b) * basis(&SMITS_GREEN)
+        };
+        r * basis(&SMITS_WHITE) + rest
+    } else if g <= r && g <= b {
+        let rest = if r <= b {
+            (r - g) * basis(&SMITS_MAGENTA) + (b - r) * basis(&SMITS_BLUE)
+        } else {
+            (b - g) * basis(&SMITS_MAGENTA) + (r - b) * basis(&SMITS_RED)
+        };
+        g * basis(&SMITS_WHITE) + rest
+    } else {
+        let rest = if r <= g {
+            (r - b) * basis(&SMITS_YELLOW) + (g - r) * basis(&SMITS_GREEN)
+        } else {
+            (g - b) * basis(&SMITS_YELLOW) + (r - g) * basis(&SMITS_RED)
+        };
+        b * basis(&SMITS_WHITE) + rest
+    }
+}
+
+// Wavelengths carried by a path in spectral mode, using hero wavelength sampling: the first
+// one is uniformly distributed and the others are spread evenly from there. The channels of
+// a Color along the path hold the values at these wavelengths instead of red, green and blue.
+#[derive(Debug, Clone, Copy)]
+pub struct Wavelengths {
+    pub lambda: [f64; 3],
+}
+
+impl Wavelengths {
+
+    pub fn sample(sampler: &mut dyn Sampler) -> Wavelengths {
+        Wavelengths::new(sampler.next_f64())
+    }
+
+    // wavelengths with the hero one at the fraction u of the visible range
+    pub fn new(u: f64) -> Wavelengths {
+        let mut lambda = [0.0; 3];
+        for (i, l) in lambda.iter_mut().enumerate() {
+            *l = LAMBDA_MIN + (u + i as f64 / 3.0).fract() * (LAMBDA_MAX - LAMBDA_MIN);
+        }
+        Wavelengths { lambda }
+    }
+
+    // Keeps only the hero wavelength, for effects like dispersion that send every wavelength in
+    // a different direction. Returns the weight of the path, which is zero for the others and
+    // makes up for their loss in the hero wavelength. Doing it again changes nothing.
+    pub fn terminate_secondary(&mut self) -> Color {
+        if self.is_terminated() {
+            return Color::white();
+        }
+        self.lambda = [self.lambda[0]; 3];
+        Color::new(self.lambda.len() as f64, 0.0, 0.0)
+    }
+
+    pub fn is_terminated(&self) -> bool {
+        self.lambda[1..].iter().all(|&l| l == self.lambda[0])
+    }
+
+    // values of the spectrum upsampled from an RGB color at the wavelengths
+    pub fn upsample(&self, rgb: Color) -> Color {
+        Color::new(
+            rgb_to_spectrum(rgb, self.lambda[0]),
+            rgb_to_spectrum(rgb, self.lambda[1]),
+            rgb_to_spectrum(rgb, self.lambda[2]),
+        )
+    }
+
+    // Linear sRGB color of the radiance values at the wavelengths. The XYZ coordinates are
+    // scaled so a constant spectrum ends up white, which adapts the equal energy white point
+    // of the upsampled spectra to the D65 white point of sRGB.
+    pub fn to_rgb(self, values: Color) -> Color {
+        let (integral_x, integral_y, integral_z) = *CIE_INTEGRALS.get_or_init(|| {
+            let mut integrals = (0.0, 0.0, 0.0);
+            let mut lambda = LAMBDA_MIN;
+            while lambda <= LAMBDA_MAX {
+                let (x, y, z) = cie_xyz(lambda);
+                integrals = (integrals.0 + x, integrals.1 + y, integrals.2 + z);
+                lambda += 1.0;
+            }
+            integrals
+        });
+
+        let values = [values.r, values.g, values.b];
+        let (mut x, mut y, mut z) = (0.0, 0.0, 0.0);
+        for (value, &lambda) in values.iter().zip(&self.lambda) {
+            let (xbar, ybar, zbar) = cie_xyz(lambda);
+            x += value * xbar;
+            y += value * ybar;
+            z += value * zbar;
+        }
+
+        // the wavelengths are uniformly distributed, so the estimate of each integral is the mean times the range
+        let scale = (LAMBDA_MAX - LAMBDA_MIN) / values.len() as f64;
+        xyz_to_rgb(0.95047 * scale * x / integral_x, scale * y / integral_y, 1.08883 * scale * z / integral_z)
+    }
+}
+
+static CIE_INTEGRALS: OnceLock<(f64, f64, f64)> = OnceLock::new();
+
+// converts the channels of a color to RGB if they hold spectral values
+pub fn to_rgb(wavelengths: Option<Wavelengths>, values: Color) -> Color {
+    match wavelengths {
+        Some(wavelengths) => wavelengths.to_rgb(values),
+        None => values
+    }
+}
+
+// color of a spectrum given as (wavelength in nanometers, value) pairs, a constant spectrum is gray
+pub fn sampled_to_rgb(pairs: &[(f64, f64)]) -> Color {
+    let value = |lambda: f64| match pairs.iter().position(|&(l, _)| l >= lambda) {
+        Some(0) => pairs[0].1,
+        Some(i) => {
+            let ((l0, v0), (l1, v1)) = (pairs[i - 1], pairs[i]);
+            v0 + (v1 - v0) * (lambda - l0) / (l1 - l0)
+        },
+        None => pairs.last().map_or(0.0, |&(_, v)| v),
+    };
+
+    let (mut xyz, mut white) = ((0.0, 0.0, 0.0), (0.0, 0.0, 0.0));
+    let mut lambda = LAMBDA_MIN;
+    while lambda <= LAMBDA_MAX {
+        let (x, y, z) = cie_xyz(lambda);
+        let v = value(lambda);
+        xyz = (xyz.0 + v * x, xyz.1 + v * y, xyz.2 + v * z);
+        white = (white.0 + x, white.1 + y, white.2 + z);
+        lambda += 1.0;
+    }
+
+    let rgb = xyz_to_rgb(xyz.0, xyz.1, xyz.2);
+    let white = xyz_to_rgb(white.0, white.1, white.2);
+    Color::new((rgb.r / white.r).max(0.0), (rgb.g / white.g).max(0.0), (rgb.b / white.b).max(0.0))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_white_round_trip() {
+        // the sum over many wavelength samples approaches the integral
+        let n = 2000;
+        let mut rgb = Color::black();
+        for i in 0..n {
+            let u = (i as f64 + 0.5) / n as f64;
+            let lambda = LAMBDA_MIN + u * (LAMBDA_MAX - LAMBDA_MIN);
+            let wavelengths = Wavelengths { lambda: [lambda, lambda, lambda] };
+            rgb += wavelengths.to_rgb(wavelengths.upsample(Color::white()));
+        }
+        rgb /= n as f64;
+
+        assert!((rgb.r - 1.0).abs() < 0.01);
+        assert!((rgb.g - 1.0).abs() < 0.01);
+        assert!((rgb.b - 1.0).abs() < 0.01);
+    }
+}
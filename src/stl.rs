@@ -0,0 +1,80 @@
+use std::fs;
+
+use crate::intersection::mesh::MeshData;
+use crate::vec::Vec3;
+
+// reads an STL file, ASCII or binary. The triangles do not share vertices
+pub fn load(path: &str) -> MeshData {
+    let bytes = fs::read(path).unwrap_or_else(|e| panic!("Could not read STL file {}: {}", path, e));
+    parse(&bytes, path)
+}
+
+fn parse(bytes: &[u8], path: &str) -> MeshData {
+    // binary files can also start with "solid", their size tells them apart
+    let binary_count = bytes.get(80..84).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize);
+    let is_binary = binary_count.is_some_and(|count| bytes.len() == 84 + 50 * count) || !bytes.starts_with(b"solid");
+
+    let mut data = MeshData::default();
+    if is_binary {
+        let count = binary_count.unwrap_or_else(|| panic!("{} is too short for an STL file", path));
+        // each triangle has a normal, three vertices and two bytes of attributes
+        for triangle in bytes[84..].chunks_exact(50).take(count) {
+            let float = |i: usize| f32::from_le_bytes([triangle[i], triangle[i + 1], triangle[i + 2], triangle[i + 3]]) as f64;
+            for corner in 1..4 {
+                data.positions.push(Vec3::new(float(12 * corner), float(12 * corner + 4), float(12 * corner + 8)));
+            }
+        }
+    } else {
+        let text = String::from_utf8_lossy(bytes);
+        let mut tokens = text.split_whitespace();
+        while let Some(token) = tokens.next() {
+            if token == "vertex" {
+                let mut number = || -> f64 {
+                    tokens.next().and_then(|t| t.parse().ok()).unwrap_or_else(|| panic!("{}: expected a number", path))
+                };
+                data.positions.push(Vec3::new(number(), number(), number()));
+            }
+        }
+    }
+
+    if data.positions.len() % 3 != 0 {
+        panic!("{}: facets should have three vertices", path);
+    }
+    data.triangles = (0..data.positions.len() / 3).map(|i| [3 * i, 3 * i + 1, 3 * i + 2]).collect();
+    data
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let ascii = b"solid part
+  facet normal 0 0 1
+    outer loop
+      vertex 0 0 0
+      vertex 1 0 0
+      vertex 1 1 0
+    endloop
+  endfacet
+endsolid part
+";
+        let data = parse(ascii, "test.stl");
+        assert_eq!(data.triangles, vec![[0, 1, 2]]);
+        assert!(data.positions[2].is_close(&Vec3::new(1.0, 1.0, 0.0)));
+
+        // the same facet in binary, with a header that looks like text
+        let mut binary = b"solid binary".to_vec();
+        binary.resize(80, 0);
+        binary.extend(1u32.to_le_bytes());
+        for value in [0.0f32, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 1.0, 0.0] {
+            binary.extend(value.to_le_bytes());
+        }
+        binary.extend([0, 0]);
+
+        let binary_data = parse(&binary, "test.stl");
+        assert_eq!(binary_data.triangles, data.triangles);
+        assert!(binary_data.positions[1].is_close(&Vec3::new(1.0, 0.0, 0.0)));
+    }
+}
@@ -1,25 +1,148 @@
-use crate::{color::Color, vec::Vec3};
+use std::sync::Arc;
 
-#[derive(Debug, Clone, Copy)]
+use crate::{color::Color, intersection::hitinfo::HitInfo};
+
+#[derive(Debug, Clone)]
 pub enum Texture {
     Constant { color: Color },
     Checkered { even: Color, odd: Color, size: f64, round: f64 },
+    Image { image: Arc<ImageTexture> },
+    // alpha channel of an image times the factor, as a gray color
+    Alpha { image: Arc<ImageTexture>, factor: f64 },
+    // colors of the vertices of a mesh, white where there are none
+    VertexColor,
 }
 
 impl Texture {
 
-    pub fn new_constant(r: f64, g: f64, b: f64) -> Texture {
-        Texture::Constant { color: Color {r, g, b}} 
-    }
-
-    pub fn value(self, point: Vec3) -> Color {
+    pub fn value(&self, hit: &HitInfo) -> Color {
         match self {
-            Texture::Constant { color } => color,
+            Texture::Constant { color } => *color,
             Texture::Checkered { even, odd , size, round} => {
-                let sines = f64::sin(size * point.x) * f64::sin(size * point.z);
-                if sines < round {odd} else {even}
+                let sines = f64::sin(size * hit.point.x) * f64::sin(size * hit.point.z);
+                if sines < *round {*odd} else {*even}
             },
+            Texture::Image { image } => image.value(hit.u, hit.v),
+            Texture::Alpha { image, factor } => {
+                let alpha = factor * image.alpha(hit.u, hit.v);
+                Color::new(alpha, alpha, alpha)
+            },
+            Texture::VertexColor => hit.color.unwrap_or_else(Color::white),
         }
     }
 
 }
+
+// Image mapped onto the texture coordinates, repeating outside of [0, 1]. The colors are
+// stored linear, converted from sRGB when loading unless the image holds data like heights or
+// masks, which is already linear. Images without an alpha channel are opaque.
+#[derive(Debug)]
+pub struct ImageTexture {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+    alpha: Vec<f64>,
+    has_alpha: bool,
+}
+
+impl ImageTexture {
+
+    pub fn load(path: &str, srgb: bool) -> ImageTexture {
+        let image = image::open(path).unwrap_or_else(|e| panic!("Could not read texture {}: {}", path, e));
+        ImageTexture::from_dynamic(&image, srgb)
+    }
+
+    // an image file already in memory, None if it can not be decoded
+    pub fn decode(bytes: &[u8], srgb: bool) -> Option<ImageTexture> {
+        image::load_from_memory(bytes).ok().map(|image| ImageTexture::from_dynamic(&image, srgb))
+    }
+
+    fn from_dynamic(image: &image::DynamicImage, srgb: bool) -> ImageTexture {
+        let texture = ImageTexture::from_image(&image.to_rgba8(), srgb);
+        ImageTexture { has_alpha: image.color().has_alpha(), ..texture }
+    }
+
+    pub fn from_image(image: &image::RgbaImage, srgb: bool) -> ImageTexture {
+        let to_linear = |c: u8| {
+            let c = c as f64 / 255.0;
+            if !srgb { c } else if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+        };
+        let pixels = image
+            .pixels()
+            .map(|p| Color::new(to_linear(p[0]), to_linear(p[1]), to_linear(p[2])))
+            .collect();
+        // alpha is never stored as sRGB
+        let alpha = image.pixels().map(|p| p[3] as f64 / 255.0).collect();
+
+        ImageTexture { width: image.width() as usize, height: image.height() as usize, pixels, alpha, has_alpha: true }
+    }
+
+    // wether the image file has an alpha channel
+    pub fn has_alpha(&self) -> bool {
+        self.has_alpha
+    }
+
+    // bilinearly interpolated color, v goes from the bottom of the image to the top
+    pub fn value(&self, u: f64, v: f64) -> Color {
+        self.weights(u, v).iter().fold(Color::black(), |color, (i, w)| color + *w * self.pixels[*i])
+    }
+
+    // bilinearly interpolated alpha, one where the image has none
+    pub fn alpha(&self, u: f64, v: f64) -> f64 {
+        self.weights(u, v).iter().map(|(i, w)| w * self.alpha[*i]).sum()
+    }
+
+    // the four pixels around the texture coordinates and their weights
+    fn weights(&self, u: f64, v: f64) -> [(usize, f64); 4] {
+        let x = (u - u.floor()) * self.width as f64 - 0.5;
+        let y = (1.0 - (v - v.floor())) * self.height as f64 - 0.5;
+
+        let (x0, y0) = (x.floor(), y.floor());
+        let (tx, ty) = (x - x0, y - y0);
+        let pixel = |x: f64, y: f64| {
+            let x = (x as i64).rem_euclid(self.width as i64) as usize;
+            let y = (y as i64).rem_euclid(self.height as i64) as usize;
+            y * self.width + x
+        };
+
+        [
+            (pixel(x0, y0), (1.0 - ty) * (1.0 - tx)),
+            (pixel(x0 + 1.0, y0), (1.0 - ty) * tx),
+            (pixel(x0, y0 + 1.0), ty * (1.0 - tx)),
+            (pixel(x0 + 1.0, y0 + 1.0), ty * tx),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{material::Material, ray::Ray, vec::Vec3};
+
+    #[test]
+    fn test_image_texture() {
+        // a black and a white pixel next to each other
+        let image = image::RgbaImage::from_fn(2, 1, |x, _| image::Rgba([if x == 0 { 0 } else { 255 }; 4]));
+        let texture = ImageTexture::from_image(&image, true);
+
+        assert!(texture.value(0.25, 0.5).is_close(&Color::black()));
+        assert!(texture.value(0.75, 0.5).is_close(&Color::white()));
+        assert!(texture.value(0.5, 0.5).is_close(&Color::new(0.5, 0.5, 0.5)));
+
+        // the image repeats
+        assert!(texture.value(1.75, -0.5).is_close(&Color::white()));
+
+        // the alpha is interpolated linearly, and can be used as a texture
+        assert!((texture.alpha(0.5, 0.5) - 0.5).abs() < 1e-9);
+        let alpha = Texture::Alpha { image: Arc::new(texture), factor: 0.5 };
+        let material = Material::Lambertian { albedo: alpha.clone() };
+        let ray = Ray::new(Vec3::zero(), Vec3::new(0.0, 0.0, -1.0));
+        let hit = HitInfo::new(1.0, &ray, Vec3::new(0.0, 0.0, 1.0), &material, 0.75, 0.5);
+        assert!(alpha.value(&hit).is_close(&Color::new(0.5, 0.5, 0.5)));
+
+        // data is not converted from sRGB
+        let image = image::RgbaImage::from_pixel(1, 1, image::Rgba([51, 51, 51, 255]));
+        assert!(ImageTexture::from_image(&image, false).value(0.5, 0.5).is_close(&Color::new(0.2, 0.2, 0.2)));
+        assert!(ImageTexture::from_image(&image, true).value(0.5, 0.5).r < 0.1);
+    }
+}
@@ -0,0 +1,120 @@
+use std::f64::consts::PI;
+
+use crate::color::Color;
+use crate::intersection::hitinfo::HitInfo;
+use crate::spectrum::Wavelengths;
+use crate::texture::Texture;
+
+// number of wavelength triples the reflectance is averaged over when rendering in RGB
+const RGB_SAMPLES: usize = 8;
+
+// Thin transparent coating on a surface, like a soap bubble or oil on water. Light reflected at
+// the top and at the bottom of the film interferes, so the reflectance depends on the wavelength.
+#[derive(Debug, Clone)]
+pub struct ThinFilm {
+    // in nanometers, the average of the texture color is used
+    pub thickness: Texture,
+    pub ior: f64,
+}
+
+// what lies below the film, at one wavelength
+pub enum Substrate {
+    // index of refraction
+    Dielectric(f64),
+    // reflectance without the film
+    Metal(f64),
+}
+
+impl ThinFilm {
+
+    // Reflectance of the coated surface for light arriving at the given cosine from a medium
+    // with index n1. In spectral mode it is evaluated at the wavelengths of the hit, in RGB
+    // mode it is averaged over the visible spectrum. The substrate can depend on the wavelength.
+    pub fn reflectance(&self, hit: &HitInfo, cos_theta: f64, n1: f64, substrate: impl Fn(f64) -> Substrate) -> Color {
+        let thickness = self.thickness.value(hit).average();
+        let at = |wavelengths: &Wavelengths| {
+            let [l0, l1, l2] = wavelengths.lambda;
+            Color::new(
+                airy(l0, cos_theta, n1, self.ior, thickness, substrate(l0)),
+                airy(l1, cos_theta, n1, self.ior, thickness, substrate(l1)),
+                airy(l2, cos_theta, n1, self.ior, thickness, substrate(l2)),
+            )
+        };
+
+        match hit.wavelengths {
+            Some(wavelengths) => at(&wavelengths),
+            None => {
+                // normalized so a constant reflectance keeps its value
+                let mut rgb = Color::black();
+                let mut white = Color::black();
+                for i in 0..RGB_SAMPLES {
+                    let wavelengths = Wavelengths::new((i as f64 + 0.5) / RGB_SAMPLES as f64);
+                    rgb += wavelengths.to_rgb(at(&wavelengths));
+                    white += wavelengths.to_rgb(Color::white());
+                }
+                Color::new(rgb.r / white.r, rgb.g / white.g, rgb.b / white.b)
+            }
+        }
+    }
+}
+
+// Reflectance of a film with index n2 between a medium with index n1 and the substrate, summing
+// all the reflections inside the film (Airy). Both polarizations are averaged, and metals are
+// approximated by a real reflection coefficient with a phase flip.
+fn airy(lambda: f64, cos1: f64, n1: f64, n2: f64, thickness: f64, substrate: Substrate) -> f64 {
+    let sin1 = (1.0 - cos1 * cos1).max(0.0).sqrt();
+    let sin2 = n1 * sin1 / n2;
+    if sin2 >= 1.0 {
+        return 1.0;
+    }
+    let cos2 = (1.0 - sin2 * sin2).sqrt();
+
+    let (r23_s, r23_p) = match substrate {
+        Substrate::Dielectric(n3) => {
+            let sin3 = n2 * sin2 / n3;
+            if sin3 >= 1.0 {
+                return 1.0;
+            }
+            fresnel(n2, n3, cos2, (1.0 - sin3 * sin3).sqrt())
+        },
+        Substrate::Metal(reflectance) => {
+            let r = -reflectance.clamp(0.0, 1.0).sqrt();
+            (r, r)
+        }
+    };
+    let (r12_s, r12_p) = fresnel(n1, n2, cos1, cos2);
+
+    // phase difference between light reflected at the top and at the bottom
+    let phase = 4.0 * PI * n2 * thickness * cos2 / lambda;
+    let reflectance = |r12: f64, r23: f64| {
+        let interference = 2.0 * r12 * r23 * phase.cos();
+        (r12 * r12 + r23 * r23 + interference) / (1.0 + r12 * r12 * r23 * r23 + interference)
+    };
+
+    0.5 * (reflectance(r12_s, r23_s) + reflectance(r12_p, r23_p))
+}
+
+// amplitude reflection coefficients for s and p polarized light
+fn fresnel(n1: f64, n2: f64, cos1: f64, cos2: f64) -> (f64, f64) {
+    (
+        (n1 * cos1 - n2 * cos2) / (n1 * cos1 + n2 * cos2),
+        (n2 * cos1 - n1 * cos2) / (n2 * cos1 + n1 * cos2),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_airy() {
+        // without thickness the film disappears and the plain fresnel reflectance is left
+        let expected = ((1.5f64 - 1.0) / (1.5 + 1.0)).powi(2);
+        assert!((airy(550.0, 1.0, 1.0, 1.33, 0.0, Substrate::Dielectric(1.5)) - expected).abs() < 1e-9);
+
+        // a quarter wave film with the geometric mean index cancels the reflection
+        let n2 = 1.5f64.sqrt();
+        let thickness = 550.0 / (4.0 * n2);
+        assert!(airy(550.0, 1.0, 1.0, n2, thickness, Substrate::Dielectric(1.5)) < 1e-9);
+    }
+}
@@ -0,0 +1,160 @@
+use crate::vec::Vec3;
+
+// Affine transformation, a linear map (stored as rows of a 3x3 matrix) followed by a translation
+#[derive(Debug, Clone, Copy)]
+pub struct Transform {
+    rows: [Vec3; 3],
+    translation: Vec3,
+}
+
+impl Transform {
+
+    pub fn identity() -> Transform {
+        Transform::scale(Vec3::new(1.0, 1.0, 1.0))
+    }
+
+    pub fn translation(offset: Vec3) -> Transform {
+        Transform { translation: offset, ..Transform::linear(Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 0.0, 1.0)) }
+    }
+
+    pub fn scale(factors: Vec3) -> Transform {
+        Transform::linear(Vec3::new(factors.x, 0.0, 0.0), Vec3::new(0.0, factors.y, 0.0), Vec3::new(0.0, 0.0, factors.z))
+    }
+
+    // rotation by the given angles in degrees, first around the x axis, then y and then z
+    pub fn rotation(degrees: Vec3) -> Transform {
+        let (sx, cx) = degrees.x.to_radians().sin_cos();
+        let (sy, cy) = degrees.y.to_radians().sin_cos();
+        let (sz, cz) = degrees.z.to_radians().sin_cos();
+
+        let x = Transform::linear(Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, cx, -sx), Vec3::new(0.0, sx, cx));
+        let y = Transform::linear(Vec3::new(cy, 0.0, sy), Vec3::new(0.0, 1.0, 0.0), Vec3::new(-sy, 0.0, cy));
+        let z = Transform::linear(Vec3::new(cz, -sz, 0.0), Vec3::new(sz, cz, 0.0), Vec3::new(0.0, 0.0, 1.0));
+
+        x.then(&y).then(&z)
+    }
+
+    // rotation by an angle in degrees around an axis
+    pub fn axis_rotation(degrees: f64, axis: Vec3) -> Transform {
+        let a = axis.normalized();
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let row = |i: usize, j: usize| {
+            let cross = match (i, j) {
+                (0, 1) => -a.z, (0, 2) => a.y,
+                (1, 0) => a.z, (1, 2) => -a.x,
+                (2, 0) => -a.y, (2, 1) => a.x,
+                _ => 0.0
+            };
+            let diagonal = if i == j { cos } else { 0.0 };
+            diagonal + sin * cross + (1.0 - cos) * a[i] * a[j]
+        };
+        Transform::from_rows([0, 1, 2].map(|i| [row(i, 0), row(i, 1), row(i, 2), 0.0]))
+    }
+
+    // from a camera at eye looking along its z axis towards target to the world. Its y axis is
+    // as close to up as possible and its x axis is the cross product of up and the z axis
+    pub fn look_at(eye: Vec3, target: Vec3, up: Vec3) -> Transform {
+        let dir = (target - eye).normalized();
+        let x = Vec3::cross(&up.normalized(), &dir).normalized();
+        let y = Vec3::cross(&dir, &x);
+        Transform::from_rows([
+            [x.x, y.x, dir.x, eye.x],
+            [x.y, y.y, dir.y, eye.y],
+            [x.z, y.z, dir.z, eye.z],
+        ])
+    }
+
+    // from the first three rows of a 4x4 matrix, the last column holds the translation
+    pub fn from_rows(rows: [[f64; 4]; 3]) -> Transform {
+        let row = |r: [f64; 4]| Vec3::new(r[0], r[1], r[2]);
+        Transform {
+            rows: [row(rows[0]), row(rows[1]), row(rows[2])],
+            translation: Vec3::new(rows[0][3], rows[1][3], rows[2][3]),
+        }
+    }
+
+    fn linear(row0: Vec3, row1: Vec3, row2: Vec3) -> Transform {
+        Transform { rows: [row0, row1, row2], translation: Vec3::zero() }
+    }
+
+    // the transformation that first applies self and then other
+    pub fn then(&self, other: &Transform) -> Transform {
+        let columns = [
+            Vec3::new(self.rows[0].x, self.rows[1].x, self.rows[2].x),
+            Vec3::new(self.rows[0].y, self.rows[1].y, self.rows[2].y),
+            Vec3::new(self.rows[0].z, self.rows[1].z, self.rows[2].z),
+        ];
+        let row = |r: &Vec3| Vec3::new(Vec3::dot(r, &columns[0]), Vec3::dot(r, &columns[1]), Vec3::dot(r, &columns[2]));
+
+        Transform {
+            rows: [row(&other.rows[0]), row(&other.rows[1]), row(&other.rows[2])],
+            translation: other.point(self.translation),
+        }
+    }
+
+    // negative for transformations that mirror, which turns the winding of triangles around
+    pub fn determinant(&self) -> f64 {
+        let [a, b, c] = self.rows;
+        Vec3::dot(&a, &Vec3::cross(&b, &c))
+    }
+
+    pub fn inverse(&self) -> Transform {
+        let [a, b, c] = self.rows;
+
+        // the rows of the inverse are the cross products of the columns, divided by the determinant
+        let columns = [
+            Vec3::new(a.x, b.x, c.x),
+            Vec3::new(a.y, b.y, c.y),
+            Vec3::new(a.z, b.z, c.z),
+        ];
+        let determinant = Vec3::dot(&columns[0], &Vec3::cross(&columns[1], &columns[2]));
+        if determinant.abs() < 1e-12 {
+            panic!("Transform is not invertible");
+        }
+
+        let inverse = Transform::linear(
+            Vec3::cross(&columns[1], &columns[2]) / determinant,
+            Vec3::cross(&columns[2], &columns[0]) / determinant,
+            Vec3::cross(&columns[0], &columns[1]) / determinant,
+        );
+
+        Transform { translation: -inverse.vector(self.translation), ..inverse }
+    }
+
+    pub fn point(&self, p: Vec3) -> Vec3 {
+        self.vector(p) + self.translation
+    }
+
+    pub fn vector(&self, v: Vec3) -> Vec3 {
+        Vec3::new(Vec3::dot(&self.rows[0], &v), Vec3::dot(&self.rows[1], &v), Vec3::dot(&self.rows[2], &v))
+    }
+
+    // Normals stay perpendicular to the surface with the inverse transpose of the linear map. Its
+    // vector method transforms them, they have to be normalized again afterwards
+    pub fn normal_transform(&self) -> Transform {
+        let [a, b, c] = self.inverse().rows;
+        Transform::linear(Vec3::new(a.x, b.x, c.x), Vec3::new(a.y, b.y, c.y), Vec3::new(a.z, b.z, c.z))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_inverse() {
+        let transform = Transform::scale(Vec3::new(2.0, 1.0, 0.5))
+            .then(&Transform::rotation(Vec3::new(30.0, 45.0, -60.0)))
+            .then(&Transform::translation(Vec3::new(1.0, -2.0, 3.0)));
+
+        let p = Vec3::new(0.3, -1.2, 4.0);
+        assert!(transform.inverse().point(transform.point(p)).is_close(&p));
+
+        let rotated = Transform::rotation(Vec3::new(0.0, 0.0, 90.0)).point(Vec3::new(1.0, 0.0, 0.0));
+        assert!(rotated.is_close(&Vec3::new(0.0, 1.0, 0.0)));
+        // transformed normals stay perpendicular to transformed tangents
+        let (tangent, normal) = (Vec3::new(1.0, 1.0, 0.0), Vec3::new(1.0, -1.0, 0.0));
+        let normal = transform.normal_transform().vector(normal);
+        assert!(Vec3::dot(&transform.vector(tangent), &normal).abs() < 1e-9);
+    }
+}
@@ -1,6 +1,6 @@
 use std::{f64::consts::PI, ops};
 
-use rand::Rng;
+use crate::sampler::Sampler;
 
 #[derive(Debug, Clone, Copy)]
 pub struct Vec3 {
@@ -68,6 +68,20 @@ impl ops::Neg for Vec3 {
     }
 }
 
+// Component access by axis, 0 is x, 1 is y and 2 is z
+impl ops::Index<usize> for Vec3 {
+    type Output = f64;
+
+    fn index(&self, axis: usize) -> &f64 {
+        match axis {
+            0 => &self.x,
+            1 => &self.y,
+            2 => &self.z,
+            _ => panic!("Vec3 has no axis {}", axis)
+        }
+    }
+}
+
 impl ops::AddAssign<Vec3> for Vec3 {
     fn add_assign(&mut self, rhs: Vec3) {
         self.x += rhs.x;
@@ -98,26 +112,32 @@ impl Vec3 {
         Vec3 {x, y, z}
     }
 
-    pub fn random(min: f64, max: f64) -> Vec3 {
-        let mut rng = rand::thread_rng();
-        let rx: f64 = rng.gen_range(min..max);
-        let ry: f64 = rng.gen_range(min..max);
-        let rz: f64 = rng.gen_range(min..max);
+    pub fn random(min: f64, max: f64, sampler: &mut dyn Sampler) -> Vec3 {
+        let rx: f64 = min + (max - min) * sampler.next_f64();
+        let ry: f64 = min + (max - min) * sampler.next_f64();
+        let rz: f64 = min + (max - min) * sampler.next_f64();
         Vec3 {x: rx, y: ry, z: rz}
     }
     
-    pub fn random_unit() -> Vec3 {
-        let rx: f64 = rand::random();
-        let ry: f64 = rand::random();
-        let rz: f64 = rand::random();
+    pub fn random_unit(sampler: &mut dyn Sampler) -> Vec3 {
+        let rx: f64 = sampler.next_f64();
+        let ry: f64 = sampler.next_f64();
+        let rz: f64 = sampler.next_f64();
         Vec3 {x: rx, y: ry, z: rz}
     }
 
-    pub fn random_in_unit_disk() -> Vec3 {
-        let mut rng = rand::thread_rng();
-        
-        let a = rng.gen::<f64>() * 2.0 * PI;
-        let r = rng.gen::<f64>().sqrt();
+    // uniformly distributed direction
+    pub fn random_unit_vector(sampler: &mut dyn Sampler) -> Vec3 {
+        let z = 1.0 - 2.0 * sampler.next_f64();
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let a = sampler.next_f64() * 2.0 * PI;
+
+        Vec3 {x: r * a.cos(), y: r * a.sin(), z}
+    }
+
+    pub fn random_in_unit_disk(sampler: &mut dyn Sampler) -> Vec3 {
+        let a = sampler.next_f64() * 2.0 * PI;
+        let r = sampler.next_f64().sqrt();
 
         let x = r * a.cos();
         let y = r * a.sin();
@@ -125,6 +145,26 @@ impl Vec3 {
         Vec3 {x, y, z: 0.0}
     }
 
+    // random direction in the hemisphere around normal, distributed proportional to the cosine
+    pub fn random_cosine_direction(normal: &Vec3, sampler: &mut dyn Sampler) -> Vec3 {
+        let a = sampler.next_f64() * 2.0 * PI;
+        let r2 = sampler.next_f64();
+        let r = r2.sqrt();
+
+        let (tangent, bitangent) = normal.orthonormal_basis();
+        r * a.cos() * tangent + r * a.sin() * bitangent + (1.0 - r2).sqrt() * *normal
+    }
+
+    // random direction distributed uniformly in the cone around axis with the given cosine of its half angle
+    pub fn random_cone_direction(axis: &Vec3, cos_max: f64, sampler: &mut dyn Sampler) -> Vec3 {
+        let a = sampler.next_f64() * 2.0 * PI;
+        let cos_theta = 1.0 - sampler.next_f64() * (1.0 - cos_max);
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+
+        let (tangent, bitangent) = axis.orthonormal_basis();
+        sin_theta * a.cos() * tangent + sin_theta * a.sin() * bitangent + cos_theta * *axis
+    }
+
     pub fn dot(v1: &Vec3, v2: &Vec3) -> f64 {
         v1.x * v2.x + v1.y * v2.y + v1.z * v2.z
     }
@@ -147,7 +187,7 @@ impl Vec3 {
 
     pub fn near_zero(&self) -> bool {
         let s = 1e-8;
-        return (self.x.abs() < s) && (self.y.abs() < s) && (self.z.abs() < s);
+        (self.x.abs() < s) && (self.y.abs() < s) && (self.z.abs() < s)
     }
 
     pub fn is_close(&self, vec: &Vec3) -> bool {
@@ -166,4 +206,12 @@ impl Vec3 {
             z: u.x * v.y - u.y * v.x
         }
     }
-}
\ No newline at end of file
+
+    // two unit vectors that together with self (assumed normalized) form an orthonormal basis
+    pub fn orthonormal_basis(&self) -> (Vec3, Vec3) {
+        let helper = if self.x.abs() > 0.9 { Vec3::new(0.0, 1.0, 0.0) } else { Vec3::new(1.0, 0.0, 0.0) };
+        let tangent = Vec3::cross(&helper, self).normalized();
+        let bitangent = Vec3::cross(self, &tangent);
+        (tangent, bitangent)
+    }
+}